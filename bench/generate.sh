@@ -0,0 +1,56 @@
+#!/bin/sh
+#
+# Writes the benchmark programs into this directory. The language has no
+# loops or functions yet, so each program repeats its body instead:
+#
+#   bench/generate.sh
+#   ./your_program.sh bench bench/fib.lox
+
+set -e
+
+cd "$(dirname "$0")"
+
+# repeat <count> <text>: prints <text> <count> times.
+repeat() {
+  i=0
+  while [ "$i" -lt "$1" ]; do
+    printf '%s' "$2"
+    i=$((i + 1))
+  done
+}
+
+{
+  echo '// Iterative Fibonacci, unrolled: the language has no loops or functions yet.'
+  printf 'var a = 0;\nvar b = 1;\nvar t = nil;\n'
+  round="a = 0;
+b = 1;
+$(repeat 70 't = a + b;
+a = b;
+b = t;
+')"
+  repeat 50 "$round
+"
+  echo 'print b;'
+} > fib.lox
+
+{
+  echo '// Repeated string concatenation into a global.'
+  echo 'var s = "";'
+  round="s = \"\";
+$(repeat 200 's = s + "abc";
+')"
+  repeat 20 "$round
+"
+  echo 'print s == "";'
+} > concat.lox
+
+{
+  echo '// Block-scoped locals and arithmetic, standing in for a loop body.'
+  echo 'var total = 0;'
+  x=0
+  while [ "$x" -lt 1000 ]; do
+    printf '{\n    var x = %d;\n    var y = x * 2;\n    total = total + x + y - 1;\n}\n' "$x"
+    x=$((x + 1))
+  done
+  echo 'print total;'
+} > scopes.lox
@@ -0,0 +1,130 @@
+//! The C representation of values and native functions, shared by the
+//! embedding interface in `ffi` and by plugins.
+
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::ptr;
+
+use crate::grammar::Literal;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoxType {
+    Nil,
+    Boolean,
+    Number,
+    String,
+    /// Lists, maps and functions, which C reads as their printed form.
+    Other,
+}
+
+/// A value crossing the boundary. Only the field matching `kind` is set.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LoxValue {
+    pub kind: LoxType,
+    pub boolean: bool,
+    pub number: f64,
+    pub string: *const c_char,
+}
+
+impl LoxValue {
+    const NIL: LoxValue = LoxValue {
+        kind: LoxType::Nil,
+        boolean: false,
+        number: 0.0,
+        string: ptr::null(),
+    };
+}
+
+/// Called with the arguments and a value to fill in. Returns 0 on success;
+/// otherwise `result` may hold a string with the error message.
+pub type LoxCallback = extern "C" fn(
+    user_data: *mut c_void,
+    argc: usize,
+    argv: *const LoxValue,
+    result: *mut LoxValue,
+) -> c_int;
+
+/// The embedder's pointer, passed back to its callbacks untouched.
+struct UserData(*mut c_void);
+
+// The library never dereferences it; making it safe to move along with the
+// interpreter is the embedder's side of the contract.
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl UserData {
+    /// A method rather than the field, so that closures capture the whole
+    /// `UserData` along with its `Send` and `Sync`.
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// A native that calls `callback`, to be registered under `name`.
+pub fn native(name: &str, arity: usize, callback: LoxCallback, user_data: *mut c_void) -> Literal {
+    let user_data = UserData(user_data);
+    let native = name.to_string();
+    Literal::native(name, arity, move |args| {
+        let (argv, _strings): (Vec<_>, Vec<_>) = args.iter().map(to_c).unzip();
+        let mut result = LoxValue::NIL;
+        let status = callback(user_data.get(), argv.len(), argv.as_ptr(), &mut result);
+        // The callback keeps ownership of any string it returns, so it
+        // is copied before anything else happens.
+        let value = from_c(&result);
+        match (status, value) {
+            (0, value) => Ok(value),
+            (_, Literal::String(message)) => Err(message),
+            _ => Err(format!("Native function '{native}' failed.")),
+        }
+    })
+}
+
+/// `s` as a `&str`, if it is a non-null pointer to valid UTF-8.
+///
+/// # Safety
+///
+/// `s` must be null or NUL-terminated, and outlive `'a`.
+pub unsafe fn string<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// The C form of `value`, and the string its `string` points into.
+pub fn to_c(value: &Literal) -> (LoxValue, Option<CString>) {
+    let text = |s: String| CString::new(s.replace('\0', "")).unwrap_or_default();
+    let (kind, string) = match value {
+        Literal::Nil => (LoxType::Nil, None),
+        Literal::Boolean(_) => (LoxType::Boolean, None),
+        Literal::Number(_) => (LoxType::Number, None),
+        Literal::String(s) => (LoxType::String, Some(text(s.clone()))),
+        other => (LoxType::Other, Some(text(other.to_string()))),
+    };
+    let result = LoxValue {
+        kind,
+        boolean: matches!(value, Literal::Boolean(true)),
+        number: match value {
+            Literal::Number(n) => *n,
+            _ => 0.0,
+        },
+        string: string.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+    };
+    (result, string)
+}
+
+fn from_c(value: &LoxValue) -> Literal {
+    match value.kind {
+        LoxType::Boolean => Literal::Boolean(value.boolean),
+        LoxType::Number => Literal::Number(value.number),
+        LoxType::String | LoxType::Other => {
+            // SAFETY: the callback contract requires a NUL-terminated string.
+            match unsafe { string(value.string) } {
+                Some(s) => Literal::String(s.to_string()),
+                None => Literal::Nil,
+            }
+        }
+        LoxType::Nil => Literal::Nil,
+    }
+}
@@ -0,0 +1,314 @@
+use bytes::{Buf, BufMut};
+use thiserror::Error;
+
+use crate::chunk::{Chunk, Op};
+use crate::grammar::{Literal, Span};
+
+/// Files start with this magic, the format version, a hash of the source
+/// they were compiled from and the [`Options`] it was compiled with. Bump
+/// the version whenever `Op` or the layout changes.
+const MAGIC: &[u8; 4] = b"RLXC";
+const FORMAT_VERSION: u16 = 7;
+
+pub const EXTENSION: &str = "rlxc";
+
+#[derive(Debug, Error)]
+pub enum ArtifactError {
+    #[error("not a compiled bytecode file")]
+    BadMagic,
+    #[error("bytecode format version {0} is not supported (expected {FORMAT_VERSION})")]
+    Version(u16),
+    #[error("bytecode file is truncated or corrupt")]
+    Corrupt,
+}
+
+pub struct Artifact {
+    pub source_hash: u64,
+    pub options: Options,
+    pub chunk: Chunk,
+}
+
+/// Everything besides the source that the compiled code depends on, so that
+/// a cached file is only reused under the same ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    pub optimized: bool,
+    pub optional_semicolons: bool,
+    pub print_keyword: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            optimized: false,
+            optional_semicolons: false,
+            print_keyword: true,
+        }
+    }
+}
+
+impl Options {
+    fn to_flags(self) -> u8 {
+        self.optimized as u8
+            | (self.optional_semicolons as u8) << 1
+            | (self.print_keyword as u8) << 2
+    }
+
+    fn from_flags(flags: u8) -> Option<Self> {
+        (flags < 8).then_some(Options {
+            optimized: flags & 1 != 0,
+            optional_semicolons: flags & 2 != 0,
+            print_keyword: flags & 4 != 0,
+        })
+    }
+}
+
+/// FNV-1a, chosen because it is stable across Rust releases unlike `DefaultHasher`.
+pub fn hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn encode(chunk: &Chunk, source_hash: u64, options: Options) -> Vec<u8> {
+    let mut buf = vec![];
+    buf.put_slice(MAGIC);
+    buf.put_u16(FORMAT_VERSION);
+    buf.put_u64(source_hash);
+    buf.put_u8(options.to_flags());
+
+    buf.put_u32(chunk.constants.len() as u32);
+    for constant in &chunk.constants {
+        match constant {
+            Literal::Nil => buf.put_u8(0),
+            Literal::Boolean(b) => {
+                buf.put_u8(1);
+                buf.put_u8(*b as u8);
+            }
+            Literal::Number(n) => {
+                buf.put_u8(2);
+                buf.put_f64(*n);
+            }
+            Literal::String(s) => {
+                buf.put_u8(3);
+                buf.put_u32(s.len() as u32);
+                buf.put_slice(s.as_bytes());
+            }
+            Literal::List(_) | Literal::Map(_) | Literal::Native(_) => {
+                unreachable!("only scalars are constants")
+            }
+        }
+    }
+
+    buf.put_u32(chunk.code.len() as u32);
+    for ((op, line), span) in chunk.code.iter().zip(&chunk.lines).zip(&chunk.spans) {
+        let (tag, operand) = encode_op(*op);
+        buf.put_u8(tag);
+        if let Some(operand) = operand {
+            buf.put_u16(operand);
+        }
+        buf.put_u32(*line as u32);
+        buf.put_u32(span.start as u32);
+        buf.put_u32(span.end as u32);
+    }
+    buf
+}
+
+pub fn decode(mut bytes: &[u8]) -> Result<Artifact, ArtifactError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(ArtifactError::BadMagic);
+    }
+    bytes.advance(MAGIC.len());
+    let version = read(&mut bytes, Buf::get_u16)?;
+    if version != FORMAT_VERSION {
+        return Err(ArtifactError::Version(version));
+    }
+    let source_hash = read(&mut bytes, Buf::get_u64)?;
+    let options =
+        Options::from_flags(read(&mut bytes, Buf::get_u8)?).ok_or(ArtifactError::Corrupt)?;
+
+    let mut chunk = Chunk::default();
+    for _ in 0..read(&mut bytes, Buf::get_u32)? {
+        let constant = match read(&mut bytes, Buf::get_u8)? {
+            0 => Literal::Nil,
+            1 => Literal::Boolean(read(&mut bytes, Buf::get_u8)? != 0),
+            2 => Literal::Number(read(&mut bytes, Buf::get_f64)?),
+            3 => {
+                let len = read(&mut bytes, Buf::get_u32)? as usize;
+                if bytes.remaining() < len {
+                    return Err(ArtifactError::Corrupt);
+                }
+                let s =
+                    String::from_utf8(bytes[..len].to_vec()).map_err(|_| ArtifactError::Corrupt)?;
+                bytes.advance(len);
+                Literal::String(s)
+            }
+            _ => return Err(ArtifactError::Corrupt),
+        };
+        chunk.constants.push(constant);
+    }
+
+    // Values on the stack before each instruction, which the VM trusts.
+    let mut height = 0;
+    for _ in 0..read(&mut bytes, Buf::get_u32)? {
+        let tag = read(&mut bytes, Buf::get_u8)?;
+        let operand = if has_operand(tag) {
+            read(&mut bytes, Buf::get_u16)?
+        } else {
+            0
+        };
+        let op = decode_op(tag, operand).ok_or(ArtifactError::Corrupt)?;
+        height = check(op, &chunk.constants, height).ok_or(ArtifactError::Corrupt)?;
+        let line = read(&mut bytes, Buf::get_u32)?;
+        let span = Span {
+            start: read(&mut bytes, Buf::get_u32)? as usize,
+            end: read(&mut bytes, Buf::get_u32)? as usize,
+        };
+        chunk.write(op, line as usize, span);
+    }
+    if chunk.code.last() != Some(&Op::Return) {
+        return Err(ArtifactError::Corrupt);
+    }
+    Ok(Artifact {
+        source_hash,
+        options,
+        chunk,
+    })
+}
+
+/// The stack height after `op`, or `None` if it refers to a constant or
+/// local that is not there, takes more values than the stack holds, or
+/// names a global with something other than a string. Code runs straight
+/// through, so following it in order is enough to know every height.
+fn check(op: Op, constants: &[Literal], height: usize) -> Option<usize> {
+    let constant = |index: u16| constants.get(index as usize);
+    let local = |slot: u16| (height > slot as usize).then_some(());
+    let (pops, pushes) = match op {
+        Op::Constant(index) => {
+            constant(index)?;
+            (0, 1)
+        }
+        Op::AddConstant(index) => {
+            constant(index)?;
+            (1, 1)
+        }
+        Op::GetGlobal(index)
+        | Op::DefineGlobal(index)
+        | Op::DefineConstant(index)
+        | Op::SetGlobal(index) => {
+            let Literal::String(_) = constant(index)? else {
+                return None;
+            };
+            match op {
+                Op::GetGlobal(_) => (0, 1),
+                Op::SetGlobal(_) => (1, 1),
+                _ => (1, 0),
+            }
+        }
+        Op::GetLocal(slot) => {
+            local(slot)?;
+            (0, 1)
+        }
+        Op::SetLocal(slot) | Op::AddLocal(slot) => {
+            local(slot)?;
+            (1, 1)
+        }
+        Op::Nil | Op::True | Op::False => (0, 1),
+        Op::Pop | Op::Print => (1, 0),
+        Op::Not | Op::Negate => (1, 1),
+        Op::Equal
+        | Op::NotEqual
+        | Op::Greater
+        | Op::GreaterEqual
+        | Op::Less
+        | Op::LessEqual
+        | Op::Add
+        | Op::Subtract
+        | Op::Multiply
+        | Op::Divide
+        | Op::Index => (2, 1),
+        Op::Call(argc) => (argc as usize + 1, 1),
+        Op::Return => (0, 0),
+    };
+    height.checked_sub(pops).map(|height| height + pushes)
+}
+
+fn read<'a, T>(bytes: &mut &'a [u8], get: fn(&mut &'a [u8]) -> T) -> Result<T, ArtifactError> {
+    if bytes.remaining() < std::mem::size_of::<T>() {
+        return Err(ArtifactError::Corrupt);
+    }
+    Ok(get(bytes))
+}
+
+fn encode_op(op: Op) -> (u8, Option<u16>) {
+    match op {
+        Op::Constant(index) => (0, Some(index)),
+        Op::Nil => (1, None),
+        Op::True => (2, None),
+        Op::False => (3, None),
+        Op::Pop => (4, None),
+        Op::GetLocal(slot) => (5, Some(slot)),
+        Op::SetLocal(slot) => (6, Some(slot)),
+        Op::GetGlobal(index) => (7, Some(index)),
+        Op::DefineGlobal(index) => (8, Some(index)),
+        Op::SetGlobal(index) => (9, Some(index)),
+        Op::Equal => (10, None),
+        Op::Greater => (11, None),
+        Op::GreaterEqual => (12, None),
+        Op::Less => (13, None),
+        Op::LessEqual => (14, None),
+        Op::Add => (15, None),
+        Op::Subtract => (16, None),
+        Op::Multiply => (17, None),
+        Op::Divide => (18, None),
+        Op::Not => (19, None),
+        Op::Negate => (20, None),
+        Op::Print => (21, None),
+        Op::Return => (22, None),
+        Op::AddLocal(slot) => (23, Some(slot)),
+        Op::AddConstant(index) => (24, Some(index)),
+        Op::NotEqual => (25, None),
+        Op::Index => (26, None),
+        Op::Call(argc) => (27, Some(argc as u16)),
+        Op::DefineConstant(index) => (28, Some(index)),
+    }
+}
+
+fn has_operand(tag: u8) -> bool {
+    matches!(tag, 0 | 5..=9 | 23 | 24 | 27 | 28)
+}
+
+fn decode_op(tag: u8, operand: u16) -> Option<Op> {
+    Some(match tag {
+        0 => Op::Constant(operand),
+        1 => Op::Nil,
+        2 => Op::True,
+        3 => Op::False,
+        4 => Op::Pop,
+        5 => Op::GetLocal(operand),
+        6 => Op::SetLocal(operand),
+        7 => Op::GetGlobal(operand),
+        8 => Op::DefineGlobal(operand),
+        9 => Op::SetGlobal(operand),
+        10 => Op::Equal,
+        11 => Op::Greater,
+        12 => Op::GreaterEqual,
+        13 => Op::Less,
+        14 => Op::LessEqual,
+        15 => Op::Add,
+        16 => Op::Subtract,
+        17 => Op::Multiply,
+        18 => Op::Divide,
+        19 => Op::Not,
+        20 => Op::Negate,
+        21 => Op::Print,
+        22 => Op::Return,
+        23 => Op::AddLocal(operand),
+        24 => Op::AddConstant(operand),
+        25 => Op::NotEqual,
+        26 => Op::Index,
+        27 => Op::Call(u8::try_from(operand).ok()?),
+        28 => Op::DefineConstant(operand),
+        _ => return None,
+    })
+}
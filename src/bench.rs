@@ -0,0 +1,54 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The system allocator, counting allocations so `bench` can report them.
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+pub fn allocations() -> u64 {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// Measurements of one timed run.
+pub struct Sample {
+    pub time: Duration,
+    pub steps: u64,
+    pub allocations: u64,
+}
+
+/// Summarises the timed runs of a benchmark, one line per metric.
+pub fn report(name: &str, unit: &str, samples: &[Sample]) -> String {
+    let times: Vec<Duration> = samples.iter().map(|sample| sample.time).collect();
+    let total: Duration = times.iter().sum();
+    let mean = total / samples.len().max(1) as u32;
+    let min = times.iter().min().copied().unwrap_or_default();
+    let max = times.iter().max().copied().unwrap_or_default();
+    let last = samples.last();
+    format!(
+        "{name}: {} runs\n  time: mean {mean:.2?}, min {min:.2?}, max {max:.2?}\n  {unit}: {}\n  allocations: {}",
+        samples.len(),
+        last.map_or(0, |sample| sample.steps),
+        last.map_or(0, |sample| sample.allocations),
+    )
+}
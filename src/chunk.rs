@@ -0,0 +1,171 @@
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use crate::diagnostics::Location;
+use crate::grammar::{Literal, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum Op {
+    Constant(u16),
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal(u16),
+    SetLocal(u16),
+    GetGlobal(u16),
+    DefineGlobal(u16),
+    DefineConstant(u16),
+    SetGlobal(u16),
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Index,
+    /// Calls the value below this many arguments on the stack.
+    Call(u8),
+    Print,
+    Return,
+    // Superinstructions produced by the optimizer.
+    AddLocal(u16),
+    AddConstant(u16),
+    NotEqual,
+}
+
+impl Op {
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Constant(_) => "CONSTANT",
+            Op::Nil => "NIL",
+            Op::True => "TRUE",
+            Op::False => "FALSE",
+            Op::Pop => "POP",
+            Op::GetLocal(_) => "GET_LOCAL",
+            Op::SetLocal(_) => "SET_LOCAL",
+            Op::GetGlobal(_) => "GET_GLOBAL",
+            Op::DefineGlobal(_) => "DEFINE_GLOBAL",
+            Op::DefineConstant(_) => "DEFINE_CONSTANT",
+            Op::SetGlobal(_) => "SET_GLOBAL",
+            Op::Equal => "EQUAL",
+            Op::Greater => "GREATER",
+            Op::GreaterEqual => "GREATER_EQUAL",
+            Op::Less => "LESS",
+            Op::LessEqual => "LESS_EQUAL",
+            Op::Add => "ADD",
+            Op::Subtract => "SUBTRACT",
+            Op::Multiply => "MULTIPLY",
+            Op::Divide => "DIVIDE",
+            Op::Not => "NOT",
+            Op::Negate => "NEGATE",
+            Op::Index => "INDEX",
+            Op::Call(_) => "CALL",
+            Op::Print => "PRINT",
+            Op::Return => "RETURN",
+            Op::AddLocal(_) => "ADD_LOCAL",
+            Op::AddConstant(_) => "ADD_CONSTANT",
+            Op::NotEqual => "NOT_EQUAL",
+        }
+    }
+}
+
+/// A compiled program: a flat list of instructions, the line and source
+/// span each one came from and the constants they refer to by index.
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub lines: Vec<usize>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Literal>,
+    fingerprint: OnceLock<u64>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, op: Op, line: usize, span: Span) {
+        self.fingerprint = OnceLock::new();
+        self.code.push(op);
+        self.lines.push(line);
+        self.spans.push(span);
+    }
+
+    pub fn add_constant(&mut self, value: Literal) -> usize {
+        self.fingerprint = OnceLock::new();
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// Tells chunks apart by everything running them depends on. It is
+    /// worked out the first time it is asked for and kept, so a chunk is not
+    /// to be changed through its fields once it has run; [`Chunk::write`]
+    /// and [`Chunk::add_constant`] start it over.
+    #[cfg(feature = "jit")]
+    pub(crate) fn fingerprint(&self) -> u64 {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+
+        *self.fingerprint.get_or_init(|| {
+            let mut hasher = DefaultHasher::new();
+            self.code.hash(&mut hasher);
+            self.lines.hash(&mut hasher);
+            self.spans.hash(&mut hasher);
+            format!("{:?}", self.constants).hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    /// Human-readable listing: one instruction per line with its offset,
+    /// source line (`|` when unchanged) and column and decoded operand,
+    /// followed by the constant table.
+    pub fn disassemble(&self, name: &str, source: &str) -> String {
+        let mut out = format!("== {name} ==\n");
+        for offset in 0..self.code.len() {
+            out.push_str(&self.disassemble_instruction(offset, source));
+            out.push('\n');
+        }
+        out.push_str("== constants ==\n");
+        for (index, value) in self.constants.iter().enumerate() {
+            writeln!(out, "{index:4} {}", describe(value)).unwrap();
+        }
+        out
+    }
+
+    pub fn disassemble_instruction(&self, offset: usize, source: &str) -> String {
+        let op = self.code[offset];
+        let line = if offset > 0 && self.lines[offset] == self.lines[offset - 1] {
+            "   |".to_string()
+        } else {
+            format!("{:4}", self.lines[offset])
+        };
+        let column = Location::of(source, self.spans[offset].start).column;
+        let operand = match op {
+            Op::Constant(index)
+            | Op::AddConstant(index)
+            | Op::GetGlobal(index)
+            | Op::DefineGlobal(index)
+            | Op::DefineConstant(index)
+            | Op::SetGlobal(index) => {
+                format!("{index:4} {}", describe(&self.constants[index as usize]))
+            }
+            Op::GetLocal(slot) | Op::SetLocal(slot) | Op::AddLocal(slot) => {
+                format!("{slot:4} (slot)")
+            }
+            Op::Call(argc) => format!("{argc:4} (args)"),
+            _ => String::new(),
+        };
+        format!("{offset:04} {line}:{column:<3} {:<16} {operand}", op.name())
+            .trim_end()
+            .to_string()
+    }
+}
+
+fn describe(value: &Literal) -> String {
+    match value {
+        Literal::String(s) => format!("{s:?}"),
+        value => value.to_string(),
+    }
+}
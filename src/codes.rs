@@ -0,0 +1,404 @@
+use std::fmt::Display;
+
+use crate::diagnostics::Phase;
+
+/// Stable identifiers for every diagnostic the interpreter can produce. The
+/// leading letter names the phase: `L` lexical, `P` parse, `C` bytecode
+/// compilation, `R` runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Code {
+    UnexpectedCharacter,
+    UnterminatedString,
+    ExpectExpression,
+    ExpectToken,
+    InvalidAssignmentTarget,
+    TooManyArguments,
+    AssignToConstant,
+    TooManyConstants,
+    TooManyLocals,
+    UnsupportedStatement,
+    OperandType,
+    UndefinedVariable,
+    Interrupted,
+    IndexOutOfRange,
+    LimitExceeded,
+    NotCallable,
+    ArityMismatch,
+    NativeError,
+    OutputFailed,
+    Exit,
+    ImportFailed,
+    Uncaught,
+    Panic,
+    DivisionByZero,
+    ReassignedConstant,
+}
+
+impl Code {
+    pub const ALL: &'static [Code] = &[
+        Code::UnexpectedCharacter,
+        Code::UnterminatedString,
+        Code::ExpectExpression,
+        Code::ExpectToken,
+        Code::InvalidAssignmentTarget,
+        Code::TooManyArguments,
+        Code::AssignToConstant,
+        Code::TooManyConstants,
+        Code::TooManyLocals,
+        Code::UnsupportedStatement,
+        Code::OperandType,
+        Code::UndefinedVariable,
+        Code::Interrupted,
+        Code::IndexOutOfRange,
+        Code::LimitExceeded,
+        Code::NotCallable,
+        Code::ArityMismatch,
+        Code::NativeError,
+        Code::OutputFailed,
+        Code::Exit,
+        Code::ImportFailed,
+        Code::Uncaught,
+        Code::Panic,
+        Code::DivisionByZero,
+        Code::ReassignedConstant,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => "L0001",
+            Code::UnterminatedString => "L0002",
+            Code::ExpectExpression => "P1001",
+            Code::ExpectToken => "P1002",
+            Code::InvalidAssignmentTarget => "P1003",
+            Code::TooManyArguments => "P1004",
+            Code::AssignToConstant => "P1005",
+            Code::TooManyConstants => "C3001",
+            Code::TooManyLocals => "C3002",
+            Code::UnsupportedStatement => "C3003",
+            Code::OperandType => "R2001",
+            Code::UndefinedVariable => "R2002",
+            Code::Interrupted => "R2003",
+            Code::IndexOutOfRange => "R2004",
+            Code::LimitExceeded => "R2005",
+            Code::NotCallable => "R2006",
+            Code::ArityMismatch => "R2007",
+            Code::NativeError => "R2008",
+            Code::OutputFailed => "R2009",
+            Code::Exit => "R2010",
+            Code::ImportFailed => "R2011",
+            Code::Uncaught => "R2012",
+            Code::Panic => "R2013",
+            Code::DivisionByZero => "R2014",
+            Code::ReassignedConstant => "R2015",
+        }
+    }
+
+    pub fn lookup(code: &str) -> Option<Code> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|c| c.as_str().eq_ignore_ascii_case(code))
+    }
+
+    pub fn phase(self) -> Phase {
+        match self.as_str().as_bytes()[0] {
+            b'L' => Phase::Scan,
+            b'P' => Phase::Parse,
+            b'C' => Phase::Compile,
+            _ => Phase::Runtime,
+        }
+    }
+
+    pub fn explanation(self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => {
+                "The scanner found a character that does not start any token.
+
+Lox source may only contain the punctuation ( ) { } [ ] , . - + ; : * / = ! < > \",
+digits, letters, underscores and whitespace. Anything else is reported and
+skipped, and the script is not run.
+
+Erroneous example:
+
+    var price = $5;
+
+Remove the character or move it inside a string literal:
+
+    var price = \"$5\";"
+            }
+            Code::UnterminatedString => {
+                "A string literal was opened with `\"` but the end of the file was
+reached before the closing quote.
+
+Erroneous example:
+
+    print \"hello;
+
+Close the string:
+
+    print \"hello\";"
+            }
+            Code::ExpectExpression => {
+                "The parser needed an expression (a literal, a variable, a unary or
+binary operation, or a parenthesised group) but found something else.
+
+Erroneous example:
+
+    print 1 + ;
+
+Complete the expression:
+
+    print 1 + 2;"
+            }
+            Code::ExpectToken => {
+                "The parser expected a specific token, such as the `;` that ends a
+statement, the `)` closing a group, the `}` closing a block or the name in a
+`var` declaration, and found a different one.
+
+Erroneous example:
+
+    var x = 1
+    print x;
+
+Add the missing token:
+
+    var x = 1;
+    print x;
+
+With `--optional-semicolons`, or `// lox: optional-semicolons` as the first
+line of the file, a line that ends in a name, a literal, `)` or `]` ends its
+statement, so most `;` can be left out."
+            }
+            Code::InvalidAssignmentTarget => {
+                "The left-hand side of `=` is not something that can be assigned to.
+Only variables are valid assignment targets.
+
+Erroneous example:
+
+    1 + 2 = 3;
+
+Assign to a variable instead:
+
+    var sum = 1 + 2;"
+            }
+            Code::TooManyArguments => {
+                "A call passes more than 255 arguments, the most a function can take.
+
+Pass a list instead, or split the work across several calls."
+            }
+            Code::AssignToConstant => {
+                "A variable declared with `const` is assigned to later in the same
+source. Constants keep the value they were declared with.
+
+Erroneous example:
+
+    const limit = 10;
+    limit = 20;
+
+Declare it with `var` if it needs to change, or use a new name:
+
+    const limit = 10;
+    var current = limit;
+    current = 20;"
+            }
+            Code::TooManyConstants => {
+                "The bytecode compiler (`--backend=vm`) stores literals and variable
+names in a per-program constant table addressed by a 16-bit index, so one
+program can refer to at most 65536 of them.
+
+Split very large generated scripts into smaller ones, or run them with the
+default tree-walking backend, which has no such limit."
+            }
+            Code::TooManyLocals => {
+                "The bytecode compiler (`--backend=vm`) keeps variables declared inside
+blocks in numbered stack slots, and at most 65536 can be live at once.
+
+Move some declarations to the top level, where they become globals, or
+close blocks sooner so their variables go out of scope."
+            }
+            Code::UnsupportedStatement => {
+                "The bytecode compiler (`--backend=vm`) does not support this statement.
+`import`, `defer`, `throw` and `try` need the tree-walking interpreter,
+which is the default backend.
+
+Run the script without `--backend=vm`."
+            }
+            Code::OperandType => {
+                "An operator was applied to values of the wrong type at runtime.
+Arithmetic and comparison operators require numbers, `+` accepts either two
+numbers or two strings, unary `-` requires a number, and only lists and maps
+can be indexed, lists by a number and maps by a string.
+
+Erroneous example:
+
+    print \"total: \" + 3;
+
+Make both operands the same type:
+
+    print \"total: \" + \"3\";"
+            }
+            Code::UndefinedVariable => {
+                "A variable was read or assigned before it was declared with `var`.
+Variables declared inside a block are not visible once the block ends.
+
+Erroneous example:
+
+    {
+      var x = 1;
+    }
+    print x;
+
+Declare the variable in a scope that encloses its use:
+
+    var x;
+    {
+      x = 1;
+    }
+    print x;
+
+With `--implicit-globals`, assigning to an undefined variable defines it as
+a global instead; reading one is still an error."
+            }
+            Code::IndexOutOfRange => {
+                "A list was indexed with a number that is negative, not a whole number,
+or not less than the list's length, or a map was indexed with a key it does
+not contain. Lists are indexed from zero.
+
+Erroneous example, when the script is run without arguments:
+
+    print ARGS[0];
+
+Pass the arguments the script expects:
+
+    rustyinterpreter run script.lox first"
+            }
+            Code::LimitExceeded => {
+                "The script ran past a resource limit set for this run: it took longer
+than the timeout, or nested blocks deeper than the maximum depth.
+
+Limits are set with `limits.timeout_ms` and `limits.max_depth` in
+rustyinterpreter.toml, or with the RUSTYINT_TIMEOUT_MS and RUSTYINT_MAX_DEPTH
+environment variables. Raise or unset them if the script is expected to need
+more, for example:
+
+    RUSTYINT_TIMEOUT_MS=10000 rustyinterpreter run script.lox"
+            }
+            Code::NotCallable => {
+                "A value that is not a function was called. Only functions can be
+followed by an argument list in parentheses.
+
+Erroneous example:
+
+    var name = \"lox\";
+    name();
+
+Call a function, or drop the parentheses to use the value itself:
+
+    print name;"
+            }
+            Code::ArityMismatch => {
+                "A function was called with a different number of arguments than it
+takes. Every call must pass exactly as many arguments as the function has
+parameters.
+
+Erroneous example:
+
+    print clock(1);
+
+Pass the expected number of arguments:
+
+    print clock();"
+            }
+            Code::NativeError => {
+                "A function provided by the host application, rather than written in
+Lox, reported an error. The message comes from that function and says what
+went wrong, typically an argument of the wrong type or out of range.
+
+Check the arguments against the function's documentation."
+            }
+            Code::OutputFailed => {
+                "`print` could not write its output, for example because standard
+output was closed by the program reading it, as `head` does once it has enough
+lines, or because the disk is full.
+
+Output that was written before the failure is kept; the script stops at the
+`print` that failed."
+            }
+            Code::Interrupted => {
+                "The script was stopped by an interrupt (Ctrl-C) before it finished.
+
+The interpreter checks for interrupts between statements, so output that was
+already printed is complete and the process exits with status 130. Nothing in
+the script caused this error; run it again to let it finish."
+            }
+            Code::Exit => {
+                "The script called `exit` to stop early. This is not a mistake: the
+command line exits with the status the script passed, without printing
+anything, and the REPL ends the session.
+
+Hosts embedding the interpreter see it as an error so that the run stops
+where `exit` was called; the message carries the status."
+            }
+            Code::ImportFailed => {
+                "An `import` statement could not load its module: the file does not
+exist or cannot be read, it has a syntax error, or it is already being
+imported further up the chain, which would never finish. For cycles, the
+message lists the files from the first import of the repeated one.
+
+Paths starting with `./` or `../` are relative to the importing file.
+Other relative paths are looked for in the working directory, then in the
+directories given by `--lox-path=`, by `lox_path` under `[project]` in
+lox.toml, and by the LOX_PATH environment variable."
+            }
+            Code::Uncaught => {
+                "The script threw a value with `throw` and no `try` around it caught it.
+
+Wrap the code that can throw in `try { ... } catch (e) { ... }` to handle
+the error, where `e` is the thrown value. A `catch` also receives errors
+raised by the interpreter and natives, as a map of their `type`, `message`,
+`line` and error `code`, so `e.message` is the text; it cannot catch
+`exit`, `panic`, interrupts or exceeded limits.
+
+`catch (e: Type)` only catches errors of that type, so that others go on
+to the next `catch` or out of the `try`. Scripts make their own with
+`throw error(\"MyError\", \"message\");`. The interpreter's are TypeError,
+NameError, IndexError, CallError, NativeError, OutputError, ImportError
+and ZeroDivisionError, and `Error` matches any error."
+            }
+            Code::Panic => {
+                "The script called `panic`, which ends the run with this error and a
+stack trace. Unlike errors raised with `throw`, no `catch` can stop it,
+so it marks bugs rather than failures the script expects to handle.
+
+Fix the condition the panic reports, or use `throw` if callers should be
+able to recover."
+            }
+            Code::DivisionByZero => {
+                "A number was divided by zero while `--checked-division` was on.
+
+Without the flag, dividing by zero gives `Infinity`, `-Infinity` or `NaN`
+as IEEE 754 says, which can go unnoticed until much later. Check the
+divisor first, or catch the error as a ZeroDivisionError.
+
+Erroneous example:
+
+    var count = 0;
+    print 10 / count;"
+            }
+            Code::ReassignedConstant => {
+                "A global declared with `const` was assigned to by a later source: a
+further file given to `run`, or a later line at the REPL. Assignments in the
+same source are reported before it runs, as P1005.
+
+Declaring the name again with `var` or `const` replaces the constant, and
+the error can be caught as a TypeError."
+            }
+        }
+    }
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use crate::chunk::{Chunk, Op};
+use crate::codes::Code;
+use crate::diagnostics::Diagnostic;
+use crate::grammar::*;
+
+/// Identity of a pooled constant. Numbers are keyed by their bits so that
+/// `0` and `-0`, which compare equal, keep separate slots.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Number(u64),
+    String(String),
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+}
+
+/// Lowers a parsed program to a [`Chunk`] for the [`Vm`](crate::vm::Vm).
+/// Variables declared at the top level become globals looked up by name;
+/// variables declared inside blocks live in stack slots.
+pub struct Compiler {
+    chunk: Chunk,
+    constants: HashMap<ConstantKey, u16>,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    line: usize,
+    span: Span,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            chunk: Chunk::default(),
+            constants: HashMap::new(),
+            locals: vec![],
+            scope_depth: 0,
+            line: 1,
+            span: Span::default(),
+        }
+    }
+
+    pub fn compile(mut self, statements: &[Statement]) -> Result<Chunk, Diagnostic> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        self.emit(Op::Return);
+        Ok(self.chunk)
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), Diagnostic> {
+        match statement {
+            Statement::Print { expr, .. } => {
+                self.expression(expr)?;
+                self.emit(Op::Print);
+            }
+            Statement::Expression { expr, .. } => {
+                self.expression(expr)?;
+                self.emit(Op::Pop);
+            }
+            Statement::Variable {
+                name,
+                init,
+                constant,
+                ..
+            } => {
+                self.at(name);
+                match init {
+                    Some(expr) => self.expression(expr)?,
+                    None => self.emit(Op::Nil),
+                }
+                if self.scope_depth > 0 {
+                    self.add_local(name)?;
+                } else {
+                    let index = self.identifier(name)?;
+                    if *constant {
+                        self.emit(Op::DefineConstant(index));
+                    } else {
+                        self.emit(Op::DefineGlobal(index));
+                    }
+                }
+            }
+            Statement::Block { statements, line } => {
+                self.line = *line;
+                self.span = Span::default();
+                self.scope_depth += 1;
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                self.scope_depth -= 1;
+                while self
+                    .locals
+                    .last()
+                    .is_some_and(|local| local.depth > self.scope_depth)
+                {
+                    self.locals.pop();
+                    self.emit(Op::Pop);
+                }
+            }
+            Statement::Import { line, .. } => {
+                self.line = *line;
+                return Err(self.error(
+                    None,
+                    Code::UnsupportedStatement,
+                    "import is only supported by the tree-walking interpreter.",
+                ));
+            }
+            Statement::Throw { keyword, .. } | Statement::Defer { keyword, .. } => {
+                return Err(self.error(
+                    Some(keyword),
+                    Code::UnsupportedStatement,
+                    &format!(
+                        "{} is only supported by the tree-walking interpreter.",
+                        keyword.lexeme
+                    ),
+                ));
+            }
+            Statement::Try { line, .. } => {
+                self.line = *line;
+                return Err(self.error(
+                    None,
+                    Code::UnsupportedStatement,
+                    "try is only supported by the tree-walking interpreter.",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expression) -> Result<(), Diagnostic> {
+        match expr {
+            Expression::Literal(literal) => match literal {
+                Literal::Nil => self.emit(Op::Nil),
+                Literal::Boolean(true) => self.emit(Op::True),
+                Literal::Boolean(false) => self.emit(Op::False),
+                _ => {
+                    let index = self.constant(literal.clone(), None)?;
+                    self.emit(Op::Constant(index));
+                }
+            },
+            Expression::Group(expr) => self.expression(expr)?,
+            Expression::Unary { op, expr } => {
+                self.expression(expr)?;
+                self.at(op);
+                match op.token_type {
+                    TokenType::BANG => self.emit(Op::Not),
+                    TokenType::MINUS => self.emit(Op::Negate),
+                    _ => unreachable!(),
+                }
+            }
+            Expression::Binary { op, left, right } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.at(op);
+                match op.token_type {
+                    TokenType::PLUS => self.emit(Op::Add),
+                    TokenType::MINUS => self.emit(Op::Subtract),
+                    TokenType::STAR => self.emit(Op::Multiply),
+                    TokenType::SLASH => self.emit(Op::Divide),
+                    TokenType::EQUAL_EQUAL => self.emit(Op::Equal),
+                    TokenType::BANG_EQUAL => {
+                        self.emit(Op::Equal);
+                        self.emit(Op::Not);
+                    }
+                    TokenType::GREATER => self.emit(Op::Greater),
+                    TokenType::GREATER_EQUAL => self.emit(Op::GreaterEqual),
+                    TokenType::LESS => self.emit(Op::Less),
+                    TokenType::LESS_EQUAL => self.emit(Op::LessEqual),
+                    _ => unreachable!(),
+                }
+            }
+            Expression::Variable(name) => {
+                self.at(name);
+                match self.resolve_local(name) {
+                    Some(slot) => self.emit(Op::GetLocal(slot)),
+                    None => {
+                        let index = self.identifier(name)?;
+                        self.emit(Op::GetGlobal(index));
+                    }
+                }
+            }
+            Expression::Assign { name, right } => {
+                self.expression(right)?;
+                self.at(name);
+                match self.resolve_local(name) {
+                    Some(slot) => self.emit(Op::SetLocal(slot)),
+                    None => {
+                        let index = self.identifier(name)?;
+                        self.emit(Op::SetGlobal(index));
+                    }
+                }
+            }
+            Expression::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.at(bracket);
+                self.emit(Op::Index);
+            }
+            Expression::Call {
+                callee,
+                paren,
+                args,
+            } => {
+                self.expression(callee)?;
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                self.at(paren);
+                // The parser allows at most 255 arguments.
+                self.emit(Op::Call(args.len() as u8));
+            }
+        }
+        Ok(())
+    }
+
+    /// Attributes the instructions emitted next to `token`.
+    fn at(&mut self, token: &Token) {
+        self.line = token.line_num;
+        self.span = token.span;
+    }
+
+    fn emit(&mut self, op: Op) {
+        self.chunk.write(op, self.line, self.span);
+    }
+
+    fn constant(&mut self, value: Literal, token: Option<&Token>) -> Result<u16, Diagnostic> {
+        let key = match &value {
+            Literal::Number(n) => Some(ConstantKey::Number(n.to_bits())),
+            Literal::String(s) => Some(ConstantKey::String(s.clone())),
+            _ => None,
+        };
+        if let Some(index) = key.as_ref().and_then(|key| self.constants.get(key)) {
+            return Ok(*index);
+        }
+        let index = u16::try_from(self.chunk.add_constant(value)).map_err(|_| {
+            self.error(
+                token,
+                Code::TooManyConstants,
+                "Too many constants in one chunk.",
+            )
+        })?;
+        if let Some(key) = key {
+            self.constants.insert(key, index);
+        }
+        Ok(index)
+    }
+
+    fn identifier(&mut self, name: &Token) -> Result<u16, Diagnostic> {
+        self.constant(Literal::String(name.lexeme.clone()), Some(name))
+    }
+
+    fn add_local(&mut self, name: &Token) -> Result<(), Diagnostic> {
+        if self.locals.len() > u16::MAX as usize {
+            return Err(self.error(
+                Some(name),
+                Code::TooManyLocals,
+                "Too many local variables in scope.",
+            ));
+        }
+        self.locals.push(Local {
+            name: name.lexeme.clone(),
+            depth: self.scope_depth,
+        });
+        Ok(())
+    }
+
+    fn resolve_local(&self, name: &Token) -> Option<u16> {
+        self.locals
+            .iter()
+            .rposition(|local| local.name == name.lexeme)
+            .map(|slot| slot as u16)
+    }
+
+    fn error(&self, token: Option<&Token>, code: Code, message: &str) -> Diagnostic {
+        match token {
+            Some(token) => Diagnostic::at_token(code, token, message),
+            None => Diagnostic::error(code, self.line, Span::default(), message),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+pub const FILE_NAME: &str = "rustyinterpreter.toml";
+/// The project manifest, which takes the same settings plus a `[project]`
+/// table naming the entry point and where modules and plugins are.
+pub const MANIFEST: &str = "lox.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+/// One `key = value` line. `key` includes its table, as in `repl.prompt`.
+#[derive(Debug, Clone)]
+pub struct Setting {
+    pub key: String,
+    pub value: Value,
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+#[derive(Debug, Error)]
+#[error("{}:{line}: {message}", file.display())]
+pub struct ConfigError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn at(setting: &Setting, message: impl Into<String>) -> Self {
+        ConfigError {
+            file: setting.file.clone(),
+            line: setting.line,
+            message: message.into(),
+        }
+    }
+}
+
+/// Settings from the user-level file followed by the project file and the
+/// manifest in the current directory, so that applying them in order lets
+/// the project win.
+pub fn load() -> Result<Vec<Setting>, ConfigError> {
+    let mut settings = vec![];
+    for path in [
+        user_file(),
+        Some(PathBuf::from(FILE_NAME)),
+        Some(PathBuf::from(MANIFEST)),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if let Ok(text) = fs::read_to_string(&path) {
+            settings.extend(parse(&path, &text)?);
+        }
+    }
+    Ok(settings)
+}
+
+fn user_file() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("rustyinterpreter").join(FILE_NAME))
+}
+
+/// The subset of TOML the options need: `[table]` headers and `key = value`
+/// lines whose values are basic strings, integers, booleans or one-line
+/// arrays of those.
+pub fn parse(file: &Path, text: &str) -> Result<Vec<Setting>, ConfigError> {
+    let mut settings = vec![];
+    let mut table = String::new();
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: &str| ConfigError {
+            file: file.to_path_buf(),
+            line: index + 1,
+            message: message.to_string(),
+        };
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            table = name
+                .strip_suffix(']')
+                .ok_or_else(|| error("expected `]` after table name"))?
+                .trim()
+                .to_string();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected `key = value`"))?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(error("invalid key"));
+        }
+        let value = parse_value(value.trim()).ok_or_else(|| error("invalid value"))?;
+        settings.push(Setting {
+            key: if table.is_empty() {
+                key.to_string()
+            } else {
+                format!("{table}.{key}")
+            },
+            value,
+            file: file.to_path_buf(),
+            line: index + 1,
+        });
+    }
+    Ok(settings)
+}
+
+fn strip_comment(line: &str) -> &str {
+    find_outside_strings(line, '#').map_or(line, |i| &line[..i])
+}
+
+/// The first `target` in `text` that is not inside a basic string.
+fn find_outside_strings(text: &str, target: char) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            _ if c == target && !in_string => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    match text {
+        "true" => return Some(Value::Boolean(true)),
+        "false" => return Some(Value::Boolean(false)),
+        _ => {}
+    }
+    if let Some(mut inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let mut values = vec![];
+        while !inner.trim().is_empty() {
+            let end = find_outside_strings(inner, ',').unwrap_or(inner.len());
+            let item = inner[..end].trim();
+            // Only flat arrays: commas in nested ones would split them.
+            if item.starts_with('[') {
+                return None;
+            }
+            values.push(parse_value(item)?);
+            inner = inner.get(end + 1..).unwrap_or("");
+        }
+        return Some(Value::Array(values));
+    }
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        let mut out = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => out.push(match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    '"' => '"',
+                    '\\' => '\\',
+                    _ => return None,
+                }),
+                '"' => return None,
+                c => out.push(c),
+            }
+        }
+        return Some(Value::String(out));
+    }
+    text.replace('_', "").parse().ok().map(Value::Integer)
+}
@@ -0,0 +1,165 @@
+//! Conversions between Rust values and [`Literal`], for hosts defining
+//! globals and natives:
+//!
+//! ```
+//! use rustyinterpreter::grammar::Literal;
+//!
+//! let scores = Literal::from(vec![1.5, 2.0]);
+//! let back: Vec<f64> = scores.try_into().unwrap();
+//! assert_eq!(back, [1.5, 2.0]);
+//! ```
+//!
+//! Natives can use `?` on a failed conversion, which becomes the native's
+//! runtime error.
+
+use std::collections::{BTreeMap, HashMap};
+
+use thiserror::Error;
+
+use crate::grammar::Literal;
+use crate::shared::Shared;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("Expected {expected} but got {found}.")]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl ConversionError {
+    fn new(expected: &'static str, value: &Literal) -> Self {
+        ConversionError {
+            expected,
+            found: value.type_name(),
+        }
+    }
+}
+
+impl From<ConversionError> for String {
+    fn from(err: ConversionError) -> Self {
+        err.to_string()
+    }
+}
+
+impl From<bool> for Literal {
+    fn from(value: bool) -> Self {
+        Literal::Boolean(value)
+    }
+}
+
+impl From<f64> for Literal {
+    fn from(value: f64) -> Self {
+        Literal::Number(value)
+    }
+}
+
+impl From<i32> for Literal {
+    fn from(value: i32) -> Self {
+        Literal::Number(value.into())
+    }
+}
+
+impl From<String> for Literal {
+    fn from(value: String) -> Self {
+        Literal::String(value)
+    }
+}
+
+impl From<&str> for Literal {
+    fn from(value: &str) -> Self {
+        Literal::String(value.to_string())
+    }
+}
+
+/// `None` becomes `nil`.
+impl<T: Into<Literal>> From<Option<T>> for Literal {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Literal::Nil, Into::into)
+    }
+}
+
+impl<T: Into<Literal>> From<Vec<T>> for Literal {
+    fn from(items: Vec<T>) -> Self {
+        Literal::List(Shared::new(items.into_iter().map(Into::into).collect()))
+    }
+}
+
+impl<T: Into<Literal>> From<HashMap<String, T>> for Literal {
+    fn from(entries: HashMap<String, T>) -> Self {
+        let entries: BTreeMap<_, _> = entries
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+        Literal::Map(Shared::new(entries))
+    }
+}
+
+impl TryFrom<Literal> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Literal::Boolean(b) => Ok(b),
+            value => Err(ConversionError::new("boolean", &value)),
+        }
+    }
+}
+
+impl TryFrom<Literal> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Literal::Number(n) => Ok(n),
+            value => Err(ConversionError::new("number", &value)),
+        }
+    }
+}
+
+impl TryFrom<Literal> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Literal::String(s) => Ok(s),
+            value => Err(ConversionError::new("string", &value)),
+        }
+    }
+}
+
+/// `nil` becomes `None`; anything else must convert to `T`.
+impl<T: TryFrom<Literal, Error = ConversionError>> TryFrom<Literal> for Option<T> {
+    type Error = ConversionError;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Literal::Nil => Ok(None),
+            value => T::try_from(value).map(Some),
+        }
+    }
+}
+
+impl<T: TryFrom<Literal, Error = ConversionError>> TryFrom<Literal> for Vec<T> {
+    type Error = ConversionError;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Literal::List(items) => items.iter().cloned().map(T::try_from).collect(),
+            value => Err(ConversionError::new("list", &value)),
+        }
+    }
+}
+
+impl<T: TryFrom<Literal, Error = ConversionError>> TryFrom<Literal> for HashMap<String, T> {
+    type Error = ConversionError;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Literal::Map(entries) => entries
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), T::try_from(value.clone())?)))
+                .collect(),
+            value => Err(ConversionError::new("map", &value)),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::grammar::*;
+
+/// Where `--coverage` writes its reports.
+pub const DIR: &str = "coverage";
+
+/// Line coverage of one file. Every line a statement starts on is
+/// executable; each maps to the number of times one of its statements ran.
+pub struct FileCoverage {
+    pub file: String,
+    pub source: String,
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl FileCoverage {
+    /// Every executable line of `statements`, none of them run yet.
+    pub fn new(file: &str, source: &str, statements: &[Statement]) -> Self {
+        let mut lines = BTreeMap::new();
+        executable_lines(statements, &mut lines);
+        FileCoverage {
+            file: file.to_string(),
+            source: source.to_string(),
+            lines,
+        }
+    }
+
+    /// Takes the counts from [`Interpreter::take_hits`](crate::interpreter::Interpreter::take_hits).
+    pub fn record(&mut self, hits: &HashMap<usize, u64>) {
+        for (line, count) in self.lines.iter_mut() {
+            *count = hits.get(line).copied().unwrap_or(0);
+        }
+    }
+
+    pub fn hit(&self) -> usize {
+        self.lines.values().filter(|count| **count > 0).count()
+    }
+
+    pub fn percent(&self) -> f64 {
+        match self.lines.len() {
+            0 => 100.0,
+            found => self.hit() as f64 * 100.0 / found as f64,
+        }
+    }
+}
+
+fn executable_lines(statements: &[Statement], lines: &mut BTreeMap<usize, u64>) {
+    for statement in statements {
+        lines.insert(statement.line(), 0);
+        match statement {
+            Statement::Block { statements, .. } => executable_lines(statements, lines),
+            Statement::Try {
+                body,
+                catches,
+                finally,
+                ..
+            } => {
+                executable_lines(body, lines);
+                for catch in catches {
+                    executable_lines(&catch.body, lines);
+                }
+                if let Some(finally) = finally {
+                    executable_lines(finally, lines);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// One line per file plus a total, as printed after the run.
+pub fn summary(files: &[FileCoverage]) -> String {
+    let mut out = String::new();
+    let (mut hit, mut found) = (0, 0);
+    for file in files {
+        out.push_str(&format!(
+            "{}: {}/{} lines ({:.1}%)\n",
+            file.file,
+            file.hit(),
+            file.lines.len(),
+            file.percent()
+        ));
+        hit += file.hit();
+        found += file.lines.len();
+    }
+    let percent = if found == 0 {
+        100.0
+    } else {
+        hit as f64 * 100.0 / found as f64
+    };
+    out.push_str(&format!("total: {hit}/{found} lines ({percent:.1}%)"));
+    out
+}
+
+/// The lcov tracefile format read by `genhtml` and most CI coverage tools.
+pub fn lcov(files: &[FileCoverage]) -> String {
+    let mut out = String::new();
+    for file in files {
+        out.push_str(&format!("TN:\nSF:{}\n", file.file));
+        for (line, count) in &file.lines {
+            out.push_str(&format!("DA:{line},{count}\n"));
+        }
+        out.push_str(&format!(
+            "LF:{}\nLH:{}\nend_of_record\n",
+            file.lines.len(),
+            file.hit()
+        ));
+    }
+    out
+}
+
+/// A standalone page listing each file's source with executed lines in green
+/// and missed ones in red.
+pub fn html(files: &[FileCoverage]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Coverage</title>\n\
+         <style>\n\
+         body { font-family: sans-serif; }\n\
+         td { padding: 0 0.5em; font-family: monospace; white-space: pre; }\n\
+         .hit { background: #dfd; }\n\
+         .miss { background: #fdd; }\n\
+         .count { text-align: right; color: #666; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    for file in files {
+        out.push_str(&format!(
+            "<h2>{} &mdash; {}/{} lines ({:.1}%)</h2>\n<table>\n",
+            escape(&file.file),
+            file.hit(),
+            file.lines.len(),
+            file.percent()
+        ));
+        for (index, text) in file.source.lines().enumerate() {
+            let line = index + 1;
+            let (class, count) = match file.lines.get(&line) {
+                Some(0) => ("miss", "0".to_string()),
+                Some(count) => ("hit", count.to_string()),
+                None => ("", String::new()),
+            };
+            out.push_str(&format!(
+                "<tr class=\"{class}\"><td class=\"count\">{line}</td>\
+                 <td class=\"count\">{count}</td><td>{}</td></tr>\n",
+                escape(text)
+            ));
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes `lcov.info` and `index.html` into [`DIR`].
+pub fn write(files: &[FileCoverage]) -> io::Result<()> {
+    let dir = Path::new(DIR);
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("lcov.info"), lcov(files))?;
+    fs::write(dir.join("index.html"), html(files))
+}
@@ -0,0 +1,632 @@
+//! A debug adapter speaking the Debug Adapter Protocol over stdio, so that
+//! editors can run a script under the tree-walking interpreter with
+//! breakpoints, stepping and variable inspection.
+//!
+//! Requests are read on their own thread and handed over through a channel.
+//! A [hook](crate::hook) runs before each statement: it picks up requests
+//! that arrived meanwhile, and when the script should stop it blocks,
+//! answering requests about the paused script until one resumes it. What
+//! the script prints is sent to the editor as `output` events.
+//!
+//! Breakpoints are by line in any file the script runs, imported modules
+//! included.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::diagnostics::{Diagnostic, Diagnostics, Format};
+use crate::grammar::*;
+use crate::hook::{Node, Step};
+use crate::interpreter::{FrameKind, Interpreter, RuntimeErrorKind};
+use crate::interrupt;
+use crate::json::{self, Value};
+use crate::lsp::{read_message, send};
+use crate::output::{Flush, Writer};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::shared::{Lock, MaybeSend, Shared};
+
+const THREAD: usize = 1;
+/// `variablesReference`s of the two scopes shown.
+const LOCALS: usize = 1;
+const GLOBALS: usize = 2;
+
+/// Runs the program named by the client's `launch` request on an
+/// interpreter the host has set up, until the client disconnects or closes
+/// the input.
+pub struct Adapter {
+    interpreter: Interpreter,
+    optional_semicolons: bool,
+    print_keyword: bool,
+}
+
+impl Adapter {
+    pub fn new(interpreter: Interpreter) -> Self {
+        Adapter {
+            interpreter,
+            optional_semicolons: false,
+            print_keyword: true,
+        }
+    }
+
+    /// Scans the program as if it had the optional-semicolons pragma.
+    pub fn with_optional_semicolons(mut self, optional: bool) -> Self {
+        self.optional_semicolons = optional;
+        self
+    }
+
+    pub fn with_print_keyword(mut self, keyword: bool) -> Self {
+        self.print_keyword = keyword;
+        self
+    }
+
+    pub fn serve(
+        mut self,
+        input: impl BufRead + Send + 'static,
+        output: impl Write + MaybeSend + 'static,
+    ) -> io::Result<()> {
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let mut input = input;
+            while let Ok(Some(body)) = read_message(&mut input) {
+                if let Some(request) = json::parse(&body) {
+                    if sender.send(request).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        let channel = Shared::new(Lock::new(Channel {
+            output: Box::new(output),
+            seq: 0,
+        }));
+        let session = Shared::new(Lock::new(Session {
+            channel: channel.clone(),
+            requests,
+            breakpoints: HashMap::new(),
+            program: PathBuf::new(),
+            mode: Mode::Run,
+            last_line: 0,
+            stopped_line: 0,
+            running: false,
+            disconnected: false,
+        }));
+
+        let mut launch = None;
+        let mut configured = false;
+        while launch.is_none() || !configured {
+            let Ok(request) = session.lock().requests.recv() else {
+                return Ok(());
+            };
+            let result = match command(&request) {
+                "initialize" => {
+                    let capabilities = Value::object([
+                        ("supportsConfigurationDoneRequest", true.into()),
+                        ("supportsEvaluateForHovers", true.into()),
+                        ("supportsTerminateRequest", true.into()),
+                    ]);
+                    channel.lock().respond(&request, Ok(capabilities))?;
+                    channel.lock().event("initialized", Value::object([]))?;
+                    continue;
+                }
+                "launch" => {
+                    let arguments = request.get("arguments");
+                    let program = arguments
+                        .and_then(|arguments| arguments.get("program"))
+                        .and_then(Value::as_str);
+                    match program {
+                        Some(program) => {
+                            let stop_on_entry = arguments
+                                .and_then(|arguments| arguments.get("stopOnEntry"))
+                                == Some(&Value::Bool(true));
+                            launch = Some((program.to_string(), stop_on_entry));
+                            Ok(Value::Null)
+                        }
+                        None => Err("The launch configuration needs a program.".to_string()),
+                    }
+                }
+                "configurationDone" => {
+                    configured = true;
+                    Ok(Value::Null)
+                }
+                "disconnect" | "terminate" => {
+                    channel.lock().respond(&request, Ok(Value::Null))?;
+                    return Ok(());
+                }
+                _ => {
+                    session.lock().handle(&request, None)?;
+                    continue;
+                }
+            };
+            channel.lock().respond(&request, result)?;
+        }
+
+        let (program, stop_on_entry) = launch.unwrap_or_default();
+        {
+            let mut session = session.lock();
+            session.program = canonical(&program);
+            if stop_on_entry {
+                session.mode = Mode::Pause("entry");
+            }
+            session.running = true;
+        }
+        let status = self.run(&program, &channel, &session)?;
+        session.lock().running = false;
+        // A disconnect as the script ended may have left the flag set.
+        interrupt::take();
+        if !session.lock().disconnected {
+            let mut channel = channel.lock();
+            channel.event(
+                "exited",
+                Value::object([("exitCode", Value::Number(status.into()))]),
+            )?;
+            channel.event("terminated", Value::object([]))?;
+        }
+
+        // Answer what the client still asks until it lets go.
+        let mut session = session.lock();
+        while !session.disconnected {
+            let Ok(request) = session.requests.recv() else {
+                break;
+            };
+            session.handle(&request, None)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the program to the end, giving its exit status.
+    fn run(
+        &mut self,
+        program: &str,
+        channel: &Shared<Lock<Channel>>,
+        session: &Shared<Lock<Session>>,
+    ) -> io::Result<i32> {
+        let source = match fs::read_to_string(program) {
+            Ok(source) => source,
+            Err(err) => {
+                channel
+                    .lock()
+                    .output("stderr", &format!("Failed to read {program}: {err}\n"))?;
+                return Ok(66);
+            }
+        };
+        let mut diagnostics = Diagnostics::new(Format::Plain);
+        let tokens = Scanner::new(&source)
+            .with_optional_semicolons(self.optional_semicolons)
+            .with_print_keyword(self.print_keyword)
+            .scan_tokens(&mut diagnostics);
+        let mut errors: Vec<Diagnostic> = diagnostics.iter().cloned().collect();
+        let statements = match Parser::new(&tokens).parse_all() {
+            Ok(statements) if errors.is_empty() => statements,
+            Ok(_) => vec![],
+            Err(parse_errors) => {
+                errors.extend(parse_errors);
+                vec![]
+            }
+        };
+        if !errors.is_empty() {
+            let mut channel = channel.lock();
+            for diagnostic in errors {
+                let text = diagnostic.render(Format::Plain, program, &source);
+                channel.output("stderr", &format!("{text}\n"))?;
+            }
+            return Ok(65);
+        }
+
+        let interpreter = &mut self.interpreter;
+        interpreter.set_output(
+            Console {
+                channel: channel.clone(),
+                line: vec![],
+            },
+            Flush::Line,
+        );
+        interpreter.set_script(program);
+        let stepping = session.clone();
+        interpreter.add_hook(move |interpreter, step| {
+            if let Node::Statement(_) = step.node {
+                // A failed write means the client is gone, which the reader
+                // thread notices too.
+                let _ = stepping.lock().step(interpreter, step);
+            }
+        });
+        match interpreter.interpret(statements) {
+            Ok(()) => Ok(0),
+            Err(err) => {
+                let status = match err.kind {
+                    // Stopped by the client, which is not listening anymore.
+                    RuntimeErrorKind::Interrupted if session.lock().disconnected => return Ok(130),
+                    RuntimeErrorKind::Interrupted => 130,
+                    RuntimeErrorKind::Exit(status) => return Ok(status),
+                    _ => 70,
+                };
+                let text = Diagnostic::from(err).render(Format::Plain, program, &source);
+                channel.lock().output("stderr", &format!("{text}\n"))?;
+                Ok(status)
+            }
+        }
+    }
+}
+
+/// What the script does until it next stops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Run,
+    /// Stop at the next statement, for the given reason.
+    Pause(&'static str),
+    StepIn,
+    /// Stop at the next line no deeper than the given depth.
+    Next(usize),
+    /// Stop once out of the block at the given depth.
+    StepOut(usize),
+}
+
+struct Session {
+    channel: Shared<Lock<Channel>>,
+    requests: Receiver<Value>,
+    /// Lines with breakpoints, by canonical path.
+    breakpoints: HashMap<PathBuf, HashSet<usize>>,
+    program: PathBuf,
+    mode: Mode,
+    /// Line of the statement before the current one.
+    last_line: usize,
+    /// Line of the statement last stopped at.
+    stopped_line: usize,
+    running: bool,
+    disconnected: bool,
+}
+
+impl Session {
+    /// Called before each statement: stops there if the mode or a breakpoint
+    /// says so.
+    fn step(&mut self, interpreter: &Interpreter, step: &Step) -> io::Result<()> {
+        while let Ok(request) = self.requests.try_recv() {
+            self.handle(&request, None)?;
+        }
+        let line = step.line;
+        let file = interpreter.current_file().unwrap_or(&self.program);
+        let breakpoint = line != self.last_line
+            && self
+                .breakpoints
+                .get(file)
+                .is_some_and(|lines| lines.contains(&line));
+        self.last_line = line;
+        let reason = match self.mode {
+            _ if self.disconnected => return Ok(()),
+            Mode::Pause(reason) => reason,
+            _ if breakpoint => "breakpoint",
+            Mode::StepIn if line != self.stopped_line => "step",
+            Mode::Next(depth) if step.depth <= depth && line != self.stopped_line => "step",
+            Mode::StepOut(depth) if step.depth < depth => "step",
+            _ => return Ok(()),
+        };
+        self.stopped_line = line;
+        self.channel.lock().event(
+            "stopped",
+            Value::object([
+                ("reason", reason.into()),
+                ("threadId", THREAD.into()),
+                ("allThreadsStopped", true.into()),
+            ]),
+        )?;
+        loop {
+            let Ok(request) = self.requests.recv() else {
+                // The client is gone: stop the script rather than run it
+                // unattended.
+                self.disconnected = true;
+                interrupt::raise();
+                return Ok(());
+            };
+            if let Some(mode) = self.handle(&request, Some((interpreter, step)))? {
+                self.mode = mode;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Answers `request`, with the interpreter and step when the script is
+    /// paused. Gives the mode to resume in for the requests that resume it.
+    fn handle(
+        &mut self,
+        request: &Value,
+        paused: Option<(&Interpreter, &Step)>,
+    ) -> io::Result<Option<Mode>> {
+        let arguments = request.get("arguments").cloned().unwrap_or(Value::Null);
+        let depth = paused.map_or(0, |(_, step)| step.depth);
+        let mut resume = None;
+        let result = match command(request) {
+            "threads" => Ok(Value::object([(
+                "threads",
+                Value::Array(vec![Value::object([
+                    ("id", THREAD.into()),
+                    ("name", "main".into()),
+                ])]),
+            )])),
+            "setBreakpoints" => {
+                let path = arguments
+                    .at(&["source", "path"])
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                let lines: Vec<usize> = match arguments.get("breakpoints") {
+                    Some(Value::Array(breakpoints)) => breakpoints
+                        .iter()
+                        .filter_map(|breakpoint| breakpoint.get("line")?.as_usize())
+                        .collect(),
+                    _ => vec![],
+                };
+                let breakpoints = lines
+                    .iter()
+                    .map(|&line| Value::object([("verified", true.into()), ("line", line.into())]))
+                    .collect();
+                self.breakpoints
+                    .insert(canonical(path), lines.into_iter().collect());
+                Ok(Value::object([("breakpoints", Value::Array(breakpoints))]))
+            }
+            "setExceptionBreakpoints" => Ok(Value::object([])),
+            "stackTrace" => Ok(self.stack_trace(paused)),
+            "scopes" => Ok(Value::object([(
+                "scopes",
+                Value::Array(vec![scope("Locals", LOCALS), scope("Globals", GLOBALS)]),
+            )])),
+            "variables" => {
+                let reference = arguments
+                    .get("variablesReference")
+                    .and_then(Value::as_usize);
+                let variables = match (paused, reference) {
+                    (Some((interpreter, _)), Some(reference)) => variables(interpreter, reference),
+                    _ => vec![],
+                };
+                Ok(Value::object([("variables", Value::Array(variables))]))
+            }
+            "evaluate" => {
+                let name = arguments
+                    .get("expression")
+                    .and_then(Value::as_str)
+                    .unwrap_or("")
+                    .trim();
+                match paused.and_then(|(interpreter, _)| interpreter.lookup(name)) {
+                    Some(value) => Ok(Value::object([
+                        ("result", value.to_string().into()),
+                        ("type", value.type_name().into()),
+                        ("variablesReference", 0.into()),
+                    ])),
+                    None => Err(format!("No variable named '{name}' in scope.")),
+                }
+            }
+            "continue" => {
+                resume = Some(Mode::Run);
+                Ok(Value::object([("allThreadsContinued", true.into())]))
+            }
+            "next" => {
+                resume = Some(Mode::Next(depth));
+                Ok(Value::Null)
+            }
+            "stepIn" => {
+                resume = Some(Mode::StepIn);
+                Ok(Value::Null)
+            }
+            "stepOut" => {
+                resume = Some(Mode::StepOut(depth));
+                Ok(Value::Null)
+            }
+            "pause" => {
+                self.mode = Mode::Pause("pause");
+                Ok(Value::Null)
+            }
+            "disconnect" | "terminate" => {
+                self.disconnected = true;
+                if self.running {
+                    interrupt::raise();
+                }
+                resume = Some(Mode::Run);
+                Ok(Value::Null)
+            }
+            other => Err(format!("Unsupported request '{other}'.")),
+        };
+        self.channel.lock().respond(request, result)?;
+        Ok(resume)
+    }
+
+    /// The block frames of the paused script, innermost first.
+    fn stack_trace(&self, paused: Option<(&Interpreter, &Step)>) -> Value {
+        let Some((interpreter, step)) = paused else {
+            return Value::object([
+                ("stackFrames", Value::Array(vec![])),
+                ("totalFrames", 0.into()),
+            ]);
+        };
+        // Frames outside the innermost script frame belong to the file
+        // that imported it; the interpreter only knows the launched one.
+        let file = interpreter.current_file().unwrap_or(&self.program);
+        let mut lines = vec![("script", step.line, file)];
+        let frames = interpreter.stack_trace();
+        if !frames.is_empty() {
+            let mut outer = false;
+            lines = frames
+                .iter()
+                .enumerate()
+                .map(|(i, frame)| {
+                    let file = if outer { self.program.as_path() } else { file };
+                    let name = match frame.kind {
+                        FrameKind::Script => {
+                            outer = true;
+                            "script"
+                        }
+                        FrameKind::Block => "block",
+                    };
+                    (name, if i == 0 { step.line } else { frame.line }, file)
+                })
+                .collect();
+        }
+        let total = lines.len();
+        let frames = lines
+            .into_iter()
+            .enumerate()
+            .map(|(id, (name, line, file))| {
+                Value::object([
+                    ("id", id.into()),
+                    ("name", name.into()),
+                    ("source", source(file)),
+                    ("line", line.into()),
+                    ("column", 1.into()),
+                ])
+            })
+            .collect();
+        Value::object([
+            ("stackFrames", Value::Array(frames)),
+            ("totalFrames", total.into()),
+        ])
+    }
+}
+
+fn source(file: &Path) -> Value {
+    Value::object([
+        (
+            "name",
+            file.file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().into_owned())
+                .into(),
+        ),
+        ("path", file.to_string_lossy().into_owned().into()),
+    ])
+}
+
+fn scope(name: &str, reference: usize) -> Value {
+    Value::object([
+        ("name", name.into()),
+        ("variablesReference", reference.into()),
+        ("expensive", false.into()),
+    ])
+}
+
+/// The variables of a scope: those of enclosing blocks, or the globals
+/// other than natives.
+fn variables(interpreter: &Interpreter, reference: usize) -> Vec<Value> {
+    let globals = interpreter.globals();
+    let shown: Vec<(&String, &Literal)> = if reference == LOCALS {
+        interpreter
+            .visible()
+            .into_iter()
+            .filter(|(_, value)| {
+                !globals
+                    .iter()
+                    .any(|(_, global)| std::ptr::eq(*global, *value))
+            })
+            .collect()
+    } else {
+        globals
+            .iter()
+            .copied()
+            .filter(|(_, value)| !matches!(value, Literal::Native(_)))
+            .collect()
+    };
+    shown
+        .into_iter()
+        .map(|(name, value)| {
+            Value::object([
+                ("name", name.as_str().into()),
+                ("value", value.to_string().into()),
+                ("type", value.type_name().into()),
+                ("variablesReference", 0.into()),
+            ])
+        })
+        .collect()
+}
+
+fn command(request: &Value) -> &str {
+    request.get("command").and_then(Value::as_str).unwrap_or("")
+}
+
+/// Paths are compared once canonical, as the client may send them absolute.
+fn canonical(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The output half of the connection, numbering what it sends.
+struct Channel {
+    output: Box<Writer>,
+    seq: usize,
+}
+
+impl Channel {
+    fn send(&mut self, kind: &str, mut fields: Vec<(String, Value)>) -> io::Result<()> {
+        self.seq += 1;
+        fields.splice(
+            0..0,
+            [
+                ("seq".to_string(), self.seq.into()),
+                ("type".to_string(), kind.into()),
+            ],
+        );
+        send(&mut self.output, &Value::Object(fields))
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut fields = vec![
+            (
+                "request_seq".to_string(),
+                request.get("seq").cloned().unwrap_or(Value::Null),
+            ),
+            ("success".to_string(), result.is_ok().into()),
+            ("command".to_string(), command(request).into()),
+        ];
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => fields.push(("body".to_string(), body)),
+            Err(message) => fields.push(("message".to_string(), message.into())),
+        }
+        self.send("response", fields)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(
+            "event",
+            vec![
+                ("event".to_string(), event.into()),
+                ("body".to_string(), body),
+            ],
+        )
+    }
+
+    fn output(&mut self, category: &str, text: &str) -> io::Result<()> {
+        self.event(
+            "output",
+            Value::object([("category", category.into()), ("output", text.into())]),
+        )
+    }
+}
+
+/// Where the script's `print` goes: `output` events to the client, a line
+/// at a time.
+struct Console {
+    channel: Shared<Lock<Channel>>,
+    line: Vec<u8>,
+}
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if let Some(end) = self.line.iter().rposition(|&b| b == b'\n') {
+            let text: Vec<u8> = self.line.drain(..=end).collect();
+            self.channel
+                .lock()
+                .output("stdout", &String::from_utf8_lossy(&text))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            let text = std::mem::take(&mut self.line);
+            self.channel
+                .lock()
+                .output("stdout", &String::from_utf8_lossy(&text))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+//! The debugger behind `run --debug`: a command prompt on stderr that stops
+//! the tree-walker before statements, for when there is no editor speaking
+//! the Debug Adapter Protocol to use [`crate::dap`] with.
+//!
+//! It stops before the first statement. Expressions given to `print` are
+//! evaluated on a copy of the variables in scope, so assigning to them does
+//! not change the script.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::diagnostics::{Diagnostics, Format};
+use crate::grammar::*;
+use crate::hook::{Node, Step};
+use crate::input::Input;
+use crate::interpreter::{FrameKind, Interpreter};
+use crate::interrupt;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+
+const PROMPT: &str = "(debug) ";
+const HELP: &str = "\
+break <line>   stop before running a line; without a line, list breakpoints
+delete <line>  remove the breakpoint on a line
+step           run to the next line, entering blocks
+next           run to the next line, stepping over blocks
+continue       run to the next breakpoint
+locals         print the variables of the enclosing blocks
+globals        print the global variables other than natives
+print <expr>   evaluate an expression with the variables in scope
+where          show the blocks the paused line is in
+quit           stop the script
+An empty line repeats the last step, next or continue.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Run,
+    Step,
+    /// Stop at the next line no deeper than the given depth.
+    Next(usize),
+}
+
+pub struct Debugger {
+    input: Input,
+    output: OutputFormat,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    /// Line of the statement before the current one.
+    last_line: usize,
+    /// Line of the statement last stopped at.
+    stopped_line: usize,
+    /// The command that last resumed the script.
+    last_command: String,
+    /// Lines of the files shown so far.
+    sources: HashMap<PathBuf, Vec<String>>,
+}
+
+impl Debugger {
+    /// Reads commands from `input`; pass [`Interpreter::input`] to share it
+    /// with the script.
+    pub fn new(input: Input) -> Self {
+        Debugger {
+            input,
+            output: OutputFormat::default(),
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            last_line: 0,
+            stopped_line: 0,
+            last_command: "step".to_string(),
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Shows values the way `print` does under `output`.
+    pub fn with_output_format(mut self, output: OutputFormat) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Stops `interpreter` as the debugger says from now on.
+    pub fn attach(mut self, interpreter: &mut Interpreter) {
+        interpreter.add_hook(move |interpreter, step| {
+            if let Node::Statement(_) = step.node {
+                self.step(interpreter, step);
+            }
+        });
+    }
+
+    fn step(&mut self, interpreter: &Interpreter, step: &Step) {
+        let line = step.line;
+        let breakpoint = line != self.last_line && self.breakpoints.contains(&line);
+        self.last_line = line;
+        let stop = breakpoint
+            || match self.mode {
+                Mode::Run => false,
+                Mode::Step => line != self.stopped_line,
+                Mode::Next(depth) => step.depth <= depth && line != self.stopped_line,
+            };
+        if !stop {
+            return;
+        }
+        self.stopped_line = line;
+        if breakpoint {
+            eprintln!("Breakpoint at line {line}.");
+        }
+        self.show_line(interpreter.current_file(), line);
+        self.prompt(interpreter, step);
+    }
+
+    fn show_line(&mut self, file: Option<&Path>, line: usize) {
+        let text = file.and_then(|file| {
+            let lines = self.sources.entry(file.to_path_buf()).or_insert_with(|| {
+                fs::read_to_string(file)
+                    .map(|source| source.lines().map(str::to_string).collect())
+                    .unwrap_or_default()
+            });
+            lines.get(line.wrapping_sub(1))
+        });
+        match text {
+            Some(text) => eprintln!("{line:>4} | {text}"),
+            None => eprintln!("line {line}"),
+        }
+    }
+
+    /// Reads commands until one resumes the script.
+    fn prompt(&mut self, interpreter: &Interpreter, step: &Step) {
+        loop {
+            eprint!("{PROMPT}");
+            let _ = io::stderr().flush();
+            let line = match self.input.read_line() {
+                Ok(Some(line)) => line,
+                // Nobody is left to answer: run to the end.
+                Ok(None) | Err(_) => {
+                    eprintln!();
+                    self.mode = Mode::Run;
+                    self.breakpoints.clear();
+                    return;
+                }
+            };
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+            let (command, argument) = line.split_once(' ').unwrap_or((&line, ""));
+            let argument = argument.trim();
+            match command {
+                "s" | "step" => self.mode = Mode::Step,
+                "n" | "next" => self.mode = Mode::Next(step.depth),
+                "c" | "continue" => self.mode = Mode::Run,
+                "q" | "quit" => {
+                    interrupt::raise();
+                    self.mode = Mode::Run;
+                    self.breakpoints.clear();
+                    return;
+                }
+                "b" | "break" if argument.is_empty() => {
+                    if self.breakpoints.is_empty() {
+                        eprintln!("No breakpoints.");
+                    }
+                    for line in &self.breakpoints {
+                        eprintln!("Breakpoint at line {line}.");
+                    }
+                    continue;
+                }
+                "b" | "break" | "d" | "delete" => {
+                    match argument.parse::<usize>() {
+                        Ok(line) if command.starts_with('b') => {
+                            self.breakpoints.insert(line);
+                            eprintln!("Breakpoint at line {line}.");
+                        }
+                        Ok(line) if self.breakpoints.remove(&line) => {
+                            eprintln!("Deleted the breakpoint at line {line}.");
+                        }
+                        Ok(line) => eprintln!("No breakpoint at line {line}."),
+                        Err(_) => eprintln!("Expected a line number, got '{argument}'."),
+                    }
+                    continue;
+                }
+                "l" | "locals" => {
+                    let globals = interpreter.globals();
+                    let locals = interpreter.visible().into_iter().filter(|(_, value)| {
+                        !globals
+                            .iter()
+                            .any(|(_, global)| std::ptr::eq(*global, *value))
+                    });
+                    self.list(locals, "No local variables.");
+                    continue;
+                }
+                "g" | "globals" => {
+                    let globals = interpreter
+                        .globals()
+                        .into_iter()
+                        .filter(|(_, value)| !matches!(value, Literal::Native(_)));
+                    self.list(globals, "No global variables.");
+                    continue;
+                }
+                "p" | "print" => {
+                    match self.evaluate(interpreter, argument) {
+                        Ok(value) => eprintln!("{value}"),
+                        Err(message) => eprintln!("{message}"),
+                    }
+                    continue;
+                }
+                "w" | "where" => {
+                    let frames = interpreter.stack_trace();
+                    if frames.is_empty() {
+                        eprintln!("in script at line {}", step.line);
+                    }
+                    for (i, frame) in frames.iter().enumerate() {
+                        let line = if i == 0 { step.line } else { frame.line };
+                        match frame.kind {
+                            FrameKind::Script => eprintln!("in script at line {line}"),
+                            FrameKind::Block => eprintln!(
+                                "in block at line {line}, entered from line {}",
+                                frame.entry_line
+                            ),
+                        }
+                    }
+                    continue;
+                }
+                "h" | "help" => {
+                    eprintln!("{HELP}");
+                    continue;
+                }
+                _ => {
+                    eprintln!("Unknown command '{command}'; try 'help'.");
+                    continue;
+                }
+            }
+            self.last_command = command.to_string();
+            return;
+        }
+    }
+
+    fn list<'a>(&self, variables: impl Iterator<Item = (&'a String, &'a Literal)>, empty: &str) {
+        let mut any = false;
+        for (name, value) in variables {
+            eprintln!("{name} = {}", self.output.show(value));
+            any = true;
+        }
+        if !any {
+            eprintln!("{empty}");
+        }
+    }
+
+    /// Evaluates `source` on a scratch interpreter holding the variables in
+    /// scope.
+    fn evaluate(&self, interpreter: &Interpreter, source: &str) -> Result<String, String> {
+        let mut diagnostics = Diagnostics::new(Format::Plain);
+        let tokens = Scanner::new(source).scan_tokens(&mut diagnostics);
+        if let Some(diagnostic) = diagnostics.iter().next() {
+            return Err(diagnostic.render(Format::Plain, "<debug>", source));
+        }
+        let expression = Parser::new(&tokens)
+            .complete_expression()
+            .map_err(|diagnostic| diagnostic.render(Format::Plain, "<debug>", source))?;
+        let mut scratch = Interpreter::new();
+        for (name, value) in interpreter.visible() {
+            scratch.define_global(name, value.clone());
+        }
+        scratch
+            .evaluate(&expression)
+            .map(|value| self.output.show(&value))
+            .map_err(|err| err.message)
+    }
+}
@@ -0,0 +1,219 @@
+//! The import graph for the `deps` command: which modules a script pulls
+//! in, directly or through other modules, as an indented tree or as a
+//! Graphviz digraph. Imports that loop back to a file already being
+//! imported are marked, since running them fails with an import cycle.
+
+use crate::grammar::Statement;
+
+/// How `deps` prints the graph.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Style {
+    /// Files indented under the files importing them.
+    #[default]
+    Tree,
+    /// Graphviz `dot` input, with the edges on a cycle in red.
+    Dot,
+}
+
+impl Style {
+    /// Parses the value of `--format=` for the `deps` command.
+    pub fn from_flag(value: &str) -> Option<Self> {
+        match value {
+            "tree" => Some(Style::Tree),
+            "dot" => Some(Style::Dot),
+            _ => None,
+        }
+    }
+}
+
+struct File {
+    name: String,
+    /// Whether the file could be read.
+    found: bool,
+    imports: Vec<usize>,
+}
+
+/// Files and the imports between them; the first file added is the entry
+/// point.
+#[derive(Default)]
+pub struct Graph {
+    files: Vec<File>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file and gives its index.
+    pub fn add_file(&mut self, name: impl Into<String>, found: bool) -> usize {
+        self.files.push(File {
+            name: name.into(),
+            found,
+            imports: vec![],
+        });
+        self.files.len() - 1
+    }
+
+    /// Records that `from` imports `to`, once however often it does.
+    pub fn add_import(&mut self, from: usize, to: usize) {
+        if !self.files[from].imports.contains(&to) {
+            self.files[from].imports.push(to);
+        }
+    }
+
+    /// Each cycle reached from the entry point, as the files from the first
+    /// import of the repeated one, with that file again at the end.
+    ///
+    /// ```
+    /// # use rustyinterpreter::deps::Graph;
+    /// let mut graph = Graph::new();
+    /// let main = graph.add_file("main.lox", true);
+    /// let a = graph.add_file("a.lox", true);
+    /// let b = graph.add_file("b.lox", true);
+    /// graph.add_import(main, a);
+    /// graph.add_import(a, b);
+    /// graph.add_import(b, a);
+    /// assert_eq!(graph.cycles(), vec![vec!["a.lox", "b.lox", "a.lox"]]);
+    /// ```
+    pub fn cycles(&self) -> Vec<Vec<&str>> {
+        let mut cycles = vec![];
+        self.walk(|chain, file, _| {
+            if let Some(start) = chain.iter().position(|&ancestor| ancestor == file) {
+                cycles.push(
+                    chain[start..]
+                        .iter()
+                        .chain([&file])
+                        .map(|&n| self.files[n].name.as_str())
+                        .collect(),
+                );
+            }
+        });
+        cycles
+    }
+
+    /// Visits the imports depth first from the entry point, calling `visit`
+    /// with the files importing down to the current one, the imported file
+    /// and whether it was visited before. Files already visited are not
+    /// followed again.
+    fn walk(&self, mut visit: impl FnMut(&[usize], usize, bool)) {
+        if self.files.is_empty() {
+            return;
+        }
+        let mut visited = vec![false; self.files.len()];
+        let mut chain = vec![];
+        self.visit(0, &mut chain, &mut visited, &mut visit);
+    }
+
+    fn visit(
+        &self,
+        file: usize,
+        chain: &mut Vec<usize>,
+        visited: &mut [bool],
+        visit: &mut impl FnMut(&[usize], usize, bool),
+    ) {
+        visit(chain, file, visited[file]);
+        if visited[file] {
+            return;
+        }
+        visited[file] = true;
+        chain.push(file);
+        for &import in &self.files[file].imports {
+            self.visit(import, chain, visited, visit);
+        }
+        chain.pop();
+    }
+
+    /// An edge is on a cycle when its importer can be reached from the file
+    /// it imports.
+    fn on_cycle(&self, from: usize, to: usize) -> bool {
+        let mut seen = vec![false; self.files.len()];
+        let mut pending = vec![to];
+        while let Some(file) = pending.pop() {
+            if file == from {
+                return true;
+            }
+            if !std::mem::replace(&mut seen[file], true) {
+                pending.extend(&self.files[file].imports);
+            }
+        }
+        false
+    }
+
+    /// One line per import, indented under its importer. A file's imports
+    /// are listed the first time it appears only.
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.walk(|chain, file, visited| {
+            let note = if chain.contains(&file) {
+                " (cycle)"
+            } else if !self.files[file].found {
+                " (not found)"
+            } else if visited {
+                " (see above)"
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "{}{}{note}\n",
+                "  ".repeat(chain.len()),
+                self.files[file].name
+            ));
+        });
+        out
+    }
+
+    pub fn dot(&self) -> String {
+        let mut out = "digraph imports {\n".to_string();
+        for (n, file) in self.files.iter().enumerate() {
+            let style = if file.found { "" } else { ", style=dashed" };
+            out.push_str(&format!(
+                "  n{n} [label=\"{}\"{style}];\n",
+                file.name.replace('\\', "\\\\").replace('"', "\\\"")
+            ));
+        }
+        for (from, file) in self.files.iter().enumerate() {
+            for &to in &file.imports {
+                let color = if self.on_cycle(from, to) {
+                    " [color=red]"
+                } else {
+                    ""
+                };
+                out.push_str(&format!("  n{from} -> n{to}{color};\n"));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// The paths of the `import` statements in `statements`, at any depth.
+pub fn imports(statements: &[Statement]) -> Vec<&str> {
+    let mut paths = vec![];
+    for statement in statements {
+        match statement {
+            Statement::Import { path, .. } => paths.push(path.as_str()),
+            Statement::Block { statements, .. } => paths.extend(imports(statements)),
+            Statement::Try {
+                body,
+                catches,
+                finally,
+                ..
+            } => {
+                paths.extend(imports(body));
+                for catch in catches {
+                    paths.extend(imports(&catch.body));
+                }
+                if let Some(finally) = finally {
+                    paths.extend(imports(finally));
+                }
+            }
+            Statement::Expression { .. }
+            | Statement::Print { .. }
+            | Statement::Variable { .. }
+            | Statement::Defer { .. }
+            | Statement::Throw { .. } => {}
+        }
+    }
+    paths
+}
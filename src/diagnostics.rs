@@ -0,0 +1,332 @@
+use std::env;
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+
+use crate::codes::Code;
+use crate::grammar::{Span, Token};
+use crate::interpreter::{RuntimeError, RuntimeErrorKind};
+use crate::json;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "Error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The bare `[line N] Error: ...` lines of the reference implementation.
+    Plain,
+    /// Source snippet with the offending span underlined.
+    Rich { color: bool },
+    /// One JSON object per line, for editors and CI.
+    Json,
+}
+
+impl Format {
+    /// Rich output when stderr is a terminal, plain otherwise so that tools
+    /// matching on stderr keep working. Colors honour `NO_COLOR`.
+    pub fn detect() -> Self {
+        if io::stderr().is_terminal() {
+            Self::rich()
+        } else {
+            Format::Plain
+        }
+    }
+
+    fn rich() -> Self {
+        let color = env::var_os("NO_COLOR").map_or(true, |value| value.is_empty());
+        Format::Rich { color }
+    }
+
+    /// Parses the value of `--diagnostics=`.
+    pub fn from_flag(value: &str) -> Option<Self> {
+        match value {
+            "plain" => Some(Format::Plain),
+            "rich" => Some(Self::rich()),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The phase a diagnostic was raised in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Scan,
+    Parse,
+    Compile,
+    Runtime,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    pub line: usize,
+    pub span: Span,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, line: usize, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            line,
+            span,
+            notes: vec![],
+        }
+    }
+
+    pub fn at_token(code: Code, token: &Token, message: impl Into<String>) -> Self {
+        Self::error(code, token.line_num, token.span, message)
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.code.phase()
+    }
+
+    pub fn with_notes(mut self, notes: Vec<String>) -> Self {
+        self.notes = notes;
+        self
+    }
+
+    pub fn render(&self, format: Format, file: &str, source: &str) -> String {
+        match format {
+            Format::Plain => self.render_plain(source),
+            Format::Rich { color } => self.render_rich(file, source, color),
+            Format::Json => self.render_json(file, source),
+        }
+    }
+
+    /// The span to point at. Runtime errors that only know their line, or
+    /// whose span does not fit the source (a compiled artifact run without
+    /// it), cover the whole line instead.
+    fn range(&self, source: &str) -> Span {
+        let known = self.span != Span::default() && self.span.end <= source.len();
+        if known || self.phase() != Phase::Runtime {
+            return self.span;
+        }
+        let start = source
+            .split_inclusive('\n')
+            .take(self.line.saturating_sub(1))
+            .map(str::len)
+            .sum::<usize>();
+        let end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let text = &source[start..end];
+        Span {
+            start: start + text.len() - text.trim_start().len(),
+            end: start + text.trim_end().len(),
+        }
+    }
+
+    fn render_json(&self, file: &str, source: &str) -> String {
+        let span = self.range(source);
+        let position = |offset| {
+            let location = Location::of(source, offset);
+            format!(
+                "{{\"line\":{},\"column\":{}}}",
+                location.line, location.column
+            )
+        };
+        let notes: Vec<String> = self.notes.iter().map(|note| json::string(note)).collect();
+        format!(
+            "{{\"file\":{},\"range\":{{\"start\":{},\"end\":{}}},\"severity\":{},\"code\":{},\"message\":{},\"notes\":[{}]}}",
+            json::string(file),
+            position(span.start),
+            position(span.end),
+            json::string(&self.severity.to_string().to_lowercase()),
+            json::string(self.code.as_str()),
+            json::string(&self.message),
+            notes.join(",")
+        )
+    }
+
+    fn render_plain(&self, source: &str) -> String {
+        let mut out = match self.phase() {
+            Phase::Scan => format!("[line {}] {}: {}", self.line, self.severity, self.message),
+            Phase::Parse | Phase::Compile => format!(
+                "[line {}] {} at '{}': {}",
+                self.line,
+                self.severity,
+                source.get(self.span.start..self.span.end).unwrap_or(""),
+                self.message
+            ),
+            Phase::Runtime => format!("{}\n[line {}]", self.message, self.line),
+        };
+        for note in &self.notes {
+            out.push_str(&format!("\n  {note}"));
+        }
+        out
+    }
+
+    fn render_rich(&self, file: &str, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("\x1b[{style}m{text}\x1b[0m")
+            } else {
+                text.to_string()
+            }
+        };
+        let span = self.range(source);
+        let location = Location::of(source, span.start);
+        let text = &source[location.line_start..location.line_end];
+        let end = span.end.clamp(span.start, location.line_end);
+        let width = source[span.start.min(end)..end].chars().count().max(1);
+        let indent: String = source[location.line_start..span.start.min(end)]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let line_num = location.line.to_string();
+        let gutter = " ".repeat(line_num.len());
+        let bar = paint("1;34", "|");
+
+        let severity = self.severity.to_string().to_lowercase();
+        let mut out = format!(
+            "{}{}\n",
+            paint("1;31", &format!("{severity}[{}]", self.code)),
+            paint("1", &format!(": {}", self.message)),
+        );
+        out.push_str(&format!(
+            "{gutter}{} {file}:{}:{}\n",
+            paint("1;34", "-->"),
+            location.line,
+            location.column
+        ));
+        out.push_str(&format!("{gutter} {bar}\n"));
+        out.push_str(&format!("{} {bar} {text}\n", paint("1;34", &line_num)));
+        out.push_str(&format!(
+            "{gutter} {bar} {indent}{}",
+            paint("1;31", &"^".repeat(width))
+        ));
+        for note in &self.notes {
+            out.push_str(&format!("\n{gutter} {} note: {note}", paint("1;34", "=")));
+        }
+        out
+    }
+}
+
+/// Line and column of a byte offset, along with the bounds of its line.
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    line_start: usize,
+    line_end: usize,
+}
+
+impl Location {
+    pub fn of(source: &str, offset: usize) -> Self {
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |i| offset + i);
+        Location {
+            line: source[..offset].matches('\n').count() + 1,
+            column: source[line_start..offset].chars().count() + 1,
+            line_start,
+            line_end,
+        }
+    }
+}
+
+impl From<RuntimeErrorKind> for Code {
+    fn from(kind: RuntimeErrorKind) -> Self {
+        match kind {
+            RuntimeErrorKind::Type => Code::OperandType,
+            RuntimeErrorKind::UndefinedVariable => Code::UndefinedVariable,
+            RuntimeErrorKind::Interrupted => Code::Interrupted,
+            RuntimeErrorKind::Index => Code::IndexOutOfRange,
+            RuntimeErrorKind::Limit => Code::LimitExceeded,
+            RuntimeErrorKind::NotCallable => Code::NotCallable,
+            RuntimeErrorKind::Arity => Code::ArityMismatch,
+            RuntimeErrorKind::Native => Code::NativeError,
+            RuntimeErrorKind::Output => Code::OutputFailed,
+            RuntimeErrorKind::Exit(_) => Code::Exit,
+            RuntimeErrorKind::Import => Code::ImportFailed,
+            RuntimeErrorKind::Thrown => Code::Uncaught,
+            RuntimeErrorKind::Panic => Code::Panic,
+            RuntimeErrorKind::DivisionByZero => Code::DivisionByZero,
+            RuntimeErrorKind::Constant => Code::ReassignedConstant,
+        }
+    }
+}
+
+impl From<RuntimeError> for Diagnostic {
+    fn from(err: RuntimeError) -> Self {
+        Diagnostic::error(Code::from(err.kind), err.line, err.span, err.message)
+    }
+}
+
+/// Collects diagnostics from every phase so they can be rendered together.
+#[derive(Debug)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+    format: Format,
+    /// Set when reporting every error rather than stopping after the first
+    /// failing phase; at most this many are shown.
+    max_errors: Option<usize>,
+}
+
+impl Diagnostics {
+    pub fn new(format: Format) -> Self {
+        Diagnostics {
+            items: vec![],
+            format,
+            max_errors: None,
+        }
+    }
+
+    pub fn collect_all(mut self, max_errors: usize) -> Self {
+        self.max_errors = Some(max_errors);
+        self
+    }
+
+    /// Whether the front end should stop after the first phase with errors.
+    pub fn fail_fast(&self) -> bool {
+        self.max_errors.is_none()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.items.push(diagnostic);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.items
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.items.iter()
+    }
+
+    pub fn emit(&self, file: &str, source: &str) {
+        let shown = self.max_errors.unwrap_or(usize::MAX);
+        for diagnostic in self.iter().take(shown) {
+            eprintln!("{}", diagnostic.render(self.format, file, source));
+        }
+        let hidden = self.items.len().saturating_sub(shown);
+        if hidden > 0 && self.format != Format::Json {
+            eprintln!(
+                "{} more errors not shown (raise --max-errors to see them)",
+                hidden
+            );
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! API documentation for the `doc` command: the top-level declarations of a
+//! script and of the modules it imports, with their `///` comments, as
+//! Markdown or as an HTML page.
+
+use crate::grammar::*;
+use crate::highlight::escape;
+
+/// A top-level `var` or `const`.
+pub struct Item {
+    pub name: String,
+    pub constant: bool,
+    /// The initialiser, when it is a literal.
+    pub value: Option<String>,
+    pub doc: Option<String>,
+}
+
+/// A top-level `import`.
+pub struct Import {
+    pub name: String,
+    pub path: String,
+    /// Where the imported module is among those documented, if it is.
+    pub module: Option<usize>,
+}
+
+pub struct Module {
+    /// The file, as the documentation shows it.
+    pub file: String,
+    pub items: Vec<Item>,
+    pub imports: Vec<Import>,
+}
+
+impl Module {
+    pub fn new(file: impl Into<String>, statements: &[Statement]) -> Self {
+        let mut module = Module {
+            file: file.into(),
+            items: vec![],
+            imports: vec![],
+        };
+        for statement in statements {
+            match statement {
+                Statement::Variable {
+                    name,
+                    init,
+                    constant,
+                    doc,
+                } => module.items.push(Item {
+                    name: name.lexeme.clone(),
+                    constant: *constant,
+                    value: match init {
+                        Some(Expression::Literal(Literal::String(s))) => Some(format!("{s:?}")),
+                        Some(Expression::Literal(value)) => Some(OutputFormat::Lox.show(value)),
+                        _ => None,
+                    },
+                    doc: doc.clone(),
+                }),
+                Statement::Import { path, name, .. } => module.imports.push(Import {
+                    name: name.clone(),
+                    path: path.clone(),
+                    module: None,
+                }),
+                _ => {}
+            }
+        }
+        module
+    }
+}
+
+impl Item {
+    /// `var name` or `const name = value`.
+    fn signature(&self) -> String {
+        let keyword = if self.constant { "const" } else { "var" };
+        match &self.value {
+            Some(value) => format!("{keyword} {} = {value}", self.name),
+            None => format!("{keyword} {}", self.name),
+        }
+    }
+}
+
+pub fn markdown(modules: &[Module]) -> String {
+    let mut out = String::new();
+    for module in modules {
+        out.push_str(&format!("# {}\n\n", module.file));
+        for import in &module.imports {
+            out.push_str(&format!(
+                "Imports `{}` as `{}`.\n\n",
+                import.path, import.name
+            ));
+        }
+        for item in &module.items {
+            out.push_str(&format!("## `{}`\n\n", item.signature()));
+            if let Some(doc) = &item.doc {
+                out.push_str(&format!("{}\n\n", doc.trim()));
+            }
+        }
+    }
+    let len = out.trim_end().len();
+    out.truncate(len);
+    out.push('\n');
+    out
+}
+
+/// A standalone page, with each module under an anchor named after its
+/// position, so that imports can link to it. Doc comments are shown as
+/// paragraphs of plain text.
+pub fn html(modules: &[Module]) -> String {
+    let title = modules.first().map_or("", |module| module.file.as_str());
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n",
+        escape(title)
+    );
+    for (i, module) in modules.iter().enumerate() {
+        out.push_str(&format!(
+            "<section id=\"module-{i}\">\n<h1>{}</h1>\n",
+            escape(&module.file)
+        ));
+        for import in &module.imports {
+            let path = format!("<code>{}</code>", escape(&import.path));
+            let path = match import.module {
+                Some(n) => format!("<a href=\"#module-{n}\">{path}</a>"),
+                None => path,
+            };
+            out.push_str(&format!(
+                "<p>Imports {path} as <code>{}</code>.</p>\n",
+                escape(&import.name)
+            ));
+        }
+        for item in &module.items {
+            out.push_str(&format!(
+                "<h2 id=\"module-{i}-{}\"><code>{}</code></h2>\n",
+                escape(&item.name),
+                escape(&item.signature())
+            ));
+            for paragraph in item.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+                if !paragraph.trim().is_empty() {
+                    out.push_str(&format!("<p>{}</p>\n", escape(paragraph.trim())));
+                }
+            }
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
@@ -0,0 +1,154 @@
+//! C interface for embedding, enabled by the `cdylib` feature. Build the
+//! shared library with
+//!
+//! ```text
+//! cargo rustc --lib --release --features cdylib --crate-type cdylib
+//! ```
+//!
+//! and declare what is used from C:
+//!
+//! ```c
+//! typedef struct LoxHandle LoxHandle;
+//! typedef enum { LOX_NIL, LOX_BOOLEAN, LOX_NUMBER, LOX_STRING, LOX_OTHER } LoxType;
+//! typedef struct { LoxType kind; bool boolean; double number; const char *string; } LoxValue;
+//! typedef int (*LoxCallback)(void *user_data, size_t argc, const LoxValue *argv, LoxValue *result);
+//!
+//! LoxHandle *lox_new(void);
+//! void lox_free(LoxHandle *lox);
+//! int lox_run(LoxHandle *lox, const char *source);
+//! const char *lox_error(const LoxHandle *lox);
+//! int lox_get(LoxHandle *lox, const char *name, LoxValue *value);
+//! int lox_register(LoxHandle *lox, const char *name, size_t arity, LoxCallback callback, void *user_data);
+//! ```
+//!
+//! Strings handed out by the library stay valid until the next call on the
+//! same handle.
+
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::ptr;
+
+use crate::abi::{self, string, to_c, LoxCallback, LoxValue};
+use crate::diagnostics::{Diagnostic, Format};
+use crate::Lox;
+
+pub struct LoxHandle {
+    lox: Lox,
+    error: Option<CString>,
+    /// Backs the `string` of the last value returned by [`lox_get`].
+    value: Option<CString>,
+}
+
+const OK: c_int = 0;
+const INVALID: c_int = -1;
+const SYNTAX_ERROR: c_int = 65;
+const RUNTIME_ERROR: c_int = 70;
+
+#[no_mangle]
+pub extern "C" fn lox_new() -> *mut LoxHandle {
+    Box::into_raw(Box::new(LoxHandle {
+        lox: Lox::new(),
+        error: None,
+        value: None,
+    }))
+}
+
+/// # Safety
+///
+/// `lox` must come from [`lox_new`] and not be used afterwards. Null is
+/// ignored.
+#[no_mangle]
+pub unsafe extern "C" fn lox_free(lox: *mut LoxHandle) {
+    if !lox.is_null() {
+        drop(Box::from_raw(lox));
+    }
+}
+
+/// Runs `source` in the handle's session. Returns 0, or the exit code the
+/// command line would use: 65 for syntax errors and 70 for runtime errors,
+/// with the message available from [`lox_error`]; -1 for invalid arguments.
+///
+/// # Safety
+///
+/// `lox` must be a live handle and `source` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lox_run(lox: *mut LoxHandle, source: *const c_char) -> c_int {
+    let (Some(handle), Some(source)) = (lox.as_mut(), string(source)) else {
+        return INVALID;
+    };
+    handle.error = None;
+    match handle.lox.run(source) {
+        Ok(()) => OK,
+        Err(diagnostics) => {
+            let code = match diagnostics.first().map(Diagnostic::phase) {
+                Some(crate::diagnostics::Phase::Runtime) => RUNTIME_ERROR,
+                _ => SYNTAX_ERROR,
+            };
+            let message: Vec<String> = diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.render(Format::Plain, "", source))
+                .collect();
+            handle.error = CString::new(message.join("\n")).ok();
+            code
+        }
+    }
+}
+
+/// The errors of the last failed [`lox_run`], or null.
+///
+/// # Safety
+///
+/// `lox` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn lox_error(lox: *const LoxHandle) -> *const c_char {
+    lox.as_ref()
+        .and_then(|handle| handle.error.as_ref())
+        .map_or(ptr::null(), |error| error.as_ptr())
+}
+
+/// Reads the global `name` into `value`. Returns 0, or -1 if it is not
+/// defined.
+///
+/// # Safety
+///
+/// `lox` must be a live handle, `name` a NUL-terminated string and `value`
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn lox_get(
+    lox: *mut LoxHandle,
+    name: *const c_char,
+    value: *mut LoxValue,
+) -> c_int {
+    let (Some(handle), Some(name), false) = (lox.as_mut(), string(name), value.is_null()) else {
+        return INVALID;
+    };
+    let Some(global) = handle.lox.interpreter().global(name).cloned() else {
+        return INVALID;
+    };
+    let (result, string) = to_c(&global);
+    handle.value = string;
+    *value = result;
+    OK
+}
+
+/// Makes `callback` callable from scripts as the global `name`, taking
+/// exactly `arity` arguments.
+///
+/// # Safety
+///
+/// `lox` must be a live handle and `name` a NUL-terminated string.
+/// `callback` is called with `user_data` for as long as the handle lives.
+#[no_mangle]
+pub unsafe extern "C" fn lox_register(
+    lox: *mut LoxHandle,
+    name: *const c_char,
+    arity: usize,
+    callback: LoxCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let (Some(handle), Some(name)) = (lox.as_mut(), string(name)) else {
+        return INVALID;
+    };
+    let native = abi::native(name, arity, callback, user_data);
+    handle.lox.interpreter().define_global(name, native);
+    OK
+}
@@ -1,18 +1,24 @@
+use std::collections::BTreeMap;
 use std::fmt::Display;
 
+use crate::shared::{MaybeSync, Shared};
+
 #[derive(Debug, PartialEq, Clone)]
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 pub enum TokenType {
     LEFT_PAREN,
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
 
     COMMA,
     DOT,
     MINUS,
     PLUS,
     SEMICOLON,
+    COLON,
     SLASH,
     STAR,
 
@@ -30,9 +36,13 @@ pub enum TokenType {
     NUMBER,
 
     AND,
+    CATCH,
     CLASS,
+    CONST,
+    DEFER,
     ELSE,
     FALSE,
+    FINALLY,
     FOR,
     FUN,
     IF,
@@ -42,7 +52,9 @@ pub enum TokenType {
     RETURN,
     SUPER,
     THIS,
+    THROW,
     TRUE,
+    TRY,
     VAR,
     WHILE,
 
@@ -53,9 +65,13 @@ impl TokenType {
     pub fn get_token_type(identifier: &str) -> Self {
         match identifier {
             "and" => Self::AND,
+            "catch" => Self::CATCH,
             "class" => Self::CLASS,
+            "const" => Self::CONST,
+            "defer" => Self::DEFER,
             "else" => Self::ELSE,
             "false" => Self::FALSE,
+            "finally" => Self::FINALLY,
             "for" => Self::FOR,
             "fun" => Self::FUN,
             "if" => Self::IF,
@@ -65,7 +81,9 @@ impl TokenType {
             "return" => Self::RETURN,
             "super" => Self::SUPER,
             "this" => Self::THIS,
+            "throw" => Self::THROW,
             "true" => Self::TRUE,
+            "try" => Self::TRY,
             "var" => Self::VAR,
             "while" => Self::WHILE,
             _ => Self::IDENTIFIER,
@@ -73,29 +91,156 @@ impl TokenType {
     }
 }
 
+/// Byte range of a token in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<Literal>,
     pub line_num: usize,
+    pub span: Span,
+    /// The `///` comment lines right before the token, without the slashes.
+    pub doc: Option<String>,
 }
 
 impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.literal {
+            Some(Literal::Number(n)) => {
+                write!(
+                    f,
+                    "{:?} {} {}",
+                    self.token_type,
+                    self.lexeme,
+                    literal_number(*n)
+                )
+            }
             Some(value) => write!(f, "{:?} {} {value}", self.token_type, self.lexeme),
             None => write!(f, "{:?} {} null", self.token_type, self.lexeme),
         }
     }
 }
 
+/// A runtime value. `==` compares by value: numbers as IEEE 754 does, so
+/// `NaN` is unequal to itself, and lists and maps element by element, all
+/// the way down, so that two lists built apart are equal when their items
+/// are. Only natives compare by identity. [`Literal::identical`] tells
+/// whether two lists or maps are the same one.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Boolean(bool),
     String(String),
     Number(f64),
     Nil,
+    List(Shared<Vec<Literal>>),
+    /// String keys to values. There is no syntax for maps; they come from the
+    /// host, and are read by indexing with a key.
+    Map(Shared<BTreeMap<String, Literal>>),
+    Native(Shared<Native>),
+}
+
+/// What a native function returns: its result, or the message of the
+/// runtime error it raises.
+pub type NativeResult = Result<Literal, String>;
+
+#[cfg(not(feature = "sync"))]
+pub type NativeFn = dyn Fn(&[Literal]) -> NativeResult;
+#[cfg(feature = "sync")]
+pub type NativeFn = dyn Fn(&[Literal]) -> NativeResult + Send + Sync;
+
+/// A function implemented by the host and called from scripts. Two natives
+/// are equal only if they are the same registration.
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    /// Whether it also takes more than `arity` arguments.
+    pub variadic: bool,
+    pub function: Box<NativeFn>,
+}
+
+impl std::fmt::Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let more = if self.variadic { "+" } else { "" };
+        write!(f, "Native({}/{}{more})", self.name, self.arity)
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Literal {
+    pub fn native(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Literal]) -> NativeResult + MaybeSync + 'static,
+    ) -> Self {
+        Literal::Native(Shared::new(Native {
+            name: name.to_string(),
+            arity,
+            variadic: false,
+            function: Box::new(function),
+        }))
+    }
+
+    /// A native taking `arity` arguments or more.
+    pub fn variadic(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Literal]) -> NativeResult + MaybeSync + 'static,
+    ) -> Self {
+        Literal::Native(Shared::new(Native {
+            name: name.to_string(),
+            arity,
+            variadic: true,
+            function: Box::new(function),
+        }))
+    }
+
+    /// Whether both are the same list, map or native, rather than equal ones.
+    /// Other values have no identity apart from their value, so they are
+    /// identical when they are equal.
+    pub fn identical(&self, other: &Literal) -> bool {
+        match (self, other) {
+            (Literal::List(a), Literal::List(b)) => Shared::ptr_eq(a, b),
+            (Literal::Map(a), Literal::Map(b)) => Shared::ptr_eq(a, b),
+            (Literal::Native(a), Literal::Native(b)) => Shared::ptr_eq(a, b),
+            (a, b) => a == b,
+        }
+    }
+
+    /// Truthiness under [`Compat::Extended`]; see [`Compat::truthy`].
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Literal::Boolean(b) => *b,
+            Literal::Number(n) => *n != 0.0,
+            Literal::String(s) => !s.is_empty(),
+            Literal::Nil => false,
+            Literal::List(items) => !items.is_empty(),
+            Literal::Map(entries) => !entries.is_empty(),
+            Literal::Native(_) => true,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Literal::Boolean(_) => "boolean",
+            Literal::String(_) => "string",
+            Literal::Number(_) => "number",
+            Literal::Nil => "nil",
+            Literal::List(_) => "list",
+            Literal::Map(_) => "map",
+            Literal::Native(_) => "function",
+        }
+    }
 }
 
 impl Display for Literal {
@@ -103,15 +248,35 @@ impl Display for Literal {
         match self {
             Literal::Boolean(b) => write!(f, "{b}"),
             Literal::String(s) => write!(f, "{s}"),
-            Literal::Number(n) => {
-                let int = n.trunc();
-                if int == *n {
-                    write!(f, "{int}.0")
-                } else {
-                    write!(f, "{n}")
+            Literal::Number(n) => write!(f, "{}", format_number(*n, false)),
+            Literal::Nil => write!(f, "nil"),
+            Literal::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match item {
+                        Literal::String(s) => write!(f, "{s:?}")?,
+                        item => write!(f, "{item}")?,
+                    }
                 }
+                write!(f, "]")
             }
-            Literal::Nil => write!(f, "nil"),
+            Literal::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match value {
+                        Literal::String(s) => write!(f, "{key:?}: {s:?}")?,
+                        value => write!(f, "{key:?}: {value}")?,
+                    }
+                }
+                write!(f, "}}")
+            }
+            Literal::Native(_) => write!(f, "<native fn>"),
         }
     }
 }
@@ -134,11 +299,22 @@ pub enum Expression {
         name: Token,
         right: Box<Expression>,
     },
+    Index {
+        object: Box<Expression>,
+        bracket: Token,
+        index: Box<Expression>,
+    },
+    Call {
+        callee: Box<Expression>,
+        paren: Token,
+        args: Vec<Expression>,
+    },
 }
 
 impl Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Expression::Literal(Literal::Number(n)) => write!(f, "{}", literal_number(*n)),
             Expression::Literal(l) => write!(f, "{l}"),
             Expression::Group(g) => {
                 write!(f, "(group {g})")
@@ -153,17 +329,236 @@ impl Display for Expression {
             Expression::Assign { name, right } => {
                 write!(f, "(assign {} {})", name.lexeme, right)
             }
+            Expression::Index { object, index, .. } => write!(f, "(index {object} {index})"),
+            Expression::Call { callee, args, .. } => {
+                write!(f, "(call {callee}")?;
+                for arg in args {
+                    write!(f, " {arg}")?;
+                }
+                write!(f, ")")
+            }
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Statement {
-    Expression(Expression),
-    Print(Expression),
+    Expression {
+        expr: Expression,
+        line: usize,
+    },
+    Print {
+        expr: Expression,
+        line: usize,
+    },
+    /// `var name = init;`, or `const name = init;` when `constant`, which
+    /// cannot be assigned to afterwards. `doc` is the `///` comment above it.
     Variable {
         name: Token,
         init: Option<Expression>,
+        constant: bool,
+        doc: Option<String>,
     },
-    Block(Vec<Statement>),
+    Block {
+        statements: Vec<Statement>,
+        line: usize,
+    },
+    /// `import "path";` or `import "path" as name;`, binding the module's
+    /// top-level variables as a map. Without `as`, the name is the file's.
+    Import {
+        path: String,
+        name: String,
+        line: usize,
+    },
+    /// `defer expr;`, evaluating `expr` when the enclosing block or script
+    /// finishes, whether or not it fails. Later ones run first.
+    Defer {
+        keyword: Token,
+        expr: Expression,
+    },
+    /// `throw value;`, raising `value` as an error that `try` can catch.
+    Throw {
+        keyword: Token,
+        value: Expression,
+    },
+    /// `try { } catch (name) { } finally { }`, with at least one of the
+    /// clauses. Of several `catch` clauses, the first that matches runs.
+    Try {
+        body: Vec<Statement>,
+        catches: Vec<Catch>,
+        finally: Option<Vec<Statement>>,
+        line: usize,
+    },
+}
+
+/// The `catch (name) { }` clause of a `try`, which runs with the error bound
+/// to `name`. With `catch (name: Type)`, only errors of that type match.
+#[derive(Debug, Clone)]
+pub struct Catch {
+    pub name: Token,
+    pub error_type: Option<Token>,
+    pub body: Vec<Statement>,
+}
+
+impl Statement {
+    /// The line the statement starts on.
+    pub fn line(&self) -> usize {
+        match self {
+            Statement::Expression { line, .. }
+            | Statement::Print { line, .. }
+            | Statement::Block { line, .. }
+            | Statement::Import { line, .. }
+            | Statement::Try { line, .. } => *line,
+            Statement::Variable { name, .. } => name.line_num,
+            Statement::Throw { keyword, .. } | Statement::Defer { keyword, .. } => keyword.line_num,
+        }
+    }
+}
+
+/// Which values count as false, for `!` and natives such as `bool` and
+/// `assert`, chosen with `--compat=`. Scripts ask with `truthy(value)`.
+///
+/// - `extended` (the default) also takes `0`, `""` and empty lists and maps
+///   as false. Every other number, string, list and map is true, however
+///   its contents would count, so `[false]` and `[[]]` are true.
+/// - `lox` takes only `nil` and `false` as false, as the reference Lox
+///   implementation does, so empty strings, lists and maps are true.
+///
+/// Functions are always true.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Compat {
+    #[default]
+    Extended,
+    Lox,
+}
+
+impl Compat {
+    /// Parses the value of `--compat=`.
+    pub fn from_flag(value: &str) -> Option<Self> {
+        match value {
+            "extended" => Some(Compat::Extended),
+            "lox" => Some(Compat::Lox),
+            _ => None,
+        }
+    }
+
+    pub fn truthy(self, value: &Literal) -> bool {
+        match (self, value) {
+            (Compat::Extended, value) => value.is_truthy(),
+            (Compat::Lox, Literal::Nil | Literal::Boolean(false)) => false,
+            (Compat::Lox, _) => true,
+        }
+    }
+}
+
+/// Number literals as `tokenize` and `parse` write them: whole numbers with
+/// a trailing `.0`, others with Rust's shortest digits, never in scientific
+/// notation.
+fn literal_number(n: f64) -> String {
+    if n.trunc() == n {
+        format!("{n}.0")
+    } else {
+        n.to_string()
+    }
+}
+
+/// Numbers as text, the way the running program sees them: `print`, `str`,
+/// the REPL and error messages. It is what the reference Lox implementation prints,
+/// Java's `Double.toString`: the fewest digits that read back as the same
+/// number, at least one after the point, and scientific notation below
+/// 10^-3 and from 10^7 up. Without `whole`, a trailing `.0` is dropped, as
+/// the reference `print` does.
+///
+/// ```
+/// use rustyinterpreter::grammar::format_number;
+///
+/// assert_eq!(format_number(3.0, true), "3.0");
+/// assert_eq!(format_number(3.0, false), "3");
+/// assert_eq!(format_number(0.5, false), "0.5");
+/// assert_eq!(format_number(12345678.0, false), "1.2345678E7");
+/// assert_eq!(format_number(1e21, false), "1.0E21");
+/// assert_eq!(format_number(0.0001, true), "1.0E-4");
+/// assert_eq!(format_number(f64::NEG_INFINITY, true), "-Infinity");
+/// ```
+pub fn format_number(n: f64, whole: bool) -> String {
+    let text = if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 || (1e-3..1e7).contains(&n.abs()) {
+        let text = n.to_string();
+        if text.contains('.') {
+            text
+        } else {
+            format!("{text}.0")
+        }
+    } else {
+        // `{:e}` has the same shortest digits, as `1.2345678e7` or `1e-4`.
+        let text = format!("{n:e}");
+        let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+        if mantissa.contains('.') {
+            format!("{mantissa}E{exponent}")
+        } else {
+            format!("{mantissa}.0E{exponent}")
+        }
+    };
+    match text.strip_suffix(".0") {
+        Some(short) if !whole => short.to_string(),
+        _ => text,
+    }
+}
+
+/// How values are shown by `print`, the `evaluate` command and the REPL,
+/// chosen with `--format=`. Numbers go through [`format_number`].
+///
+/// - `lox` (the default) writes numbers as the reference Lox implementation
+///   does: whole numbers without a fractional part, `3` and `0.5`.
+/// - `plain` always writes a fractional part, `3.0` and `0.5`.
+///
+/// Both apply inside lists too, and both write large and small numbers in
+/// scientific notation, `1.0E21`, and `NaN`, `Infinity` and `-Infinity` for
+/// the numbers that have no digits.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Lox,
+    Plain,
+}
+
+impl OutputFormat {
+    /// Parses the value of `--format=`.
+    pub fn from_flag(value: &str) -> Option<Self> {
+        match value {
+            "lox" => Some(OutputFormat::Lox),
+            "plain" => Some(OutputFormat::Plain),
+            _ => None,
+        }
+    }
+
+    pub fn show(self, value: &Literal) -> String {
+        match value {
+            Literal::Number(n) => format_number(*n, self == OutputFormat::Plain),
+            Literal::List(items) => {
+                let items: Vec<_> = items
+                    .iter()
+                    .map(|item| match item {
+                        Literal::String(s) => format!("{s:?}"),
+                        item => self.show(item),
+                    })
+                    .collect();
+                format!("[{}]", items.join(", "))
+            }
+            Literal::Map(entries) => {
+                let entries: Vec<_> = entries
+                    .iter()
+                    .map(|(key, value)| match value {
+                        Literal::String(s) => format!("{key:?}: {s:?}"),
+                        value => format!("{key:?}: {}", self.show(value)),
+                    })
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            value => value.to_string(),
+        }
+    }
 }
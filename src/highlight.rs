@@ -0,0 +1,146 @@
+//! Syntax highlighting from the scanner's tokens, so that what is colored
+//! as a keyword or a string is exactly what the scanner takes for one. The
+//! text between tokens is kept as it is, with `//` comments marked.
+
+use crate::grammar::{Token, TokenType};
+
+/// How `highlight` marks up the source.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Style {
+    /// Terminal colors.
+    #[default]
+    Ansi,
+    /// A `<pre>` block with a `lox-*` class on each highlighted span, to be
+    /// styled by the page.
+    Html,
+}
+
+impl Style {
+    /// Parses the value of `--format=` for the `highlight` command.
+    pub fn from_flag(value: &str) -> Option<Self> {
+        match value {
+            "ansi" => Some(Style::Ansi),
+            "html" => Some(Style::Html),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Keyword,
+    Literal,
+    Number,
+    String,
+    Comment,
+}
+
+impl Kind {
+    fn of(token_type: &TokenType) -> Option<Self> {
+        use TokenType::*;
+        match token_type {
+            TRUE | FALSE | NIL => Some(Kind::Literal),
+            NUMBER => Some(Kind::Number),
+            STRING => Some(Kind::String),
+            AND | CATCH | CLASS | CONST | DEFER | ELSE | FINALLY | FOR | FUN | IF | OR | PRINT
+            | RETURN | SUPER | THIS | THROW | TRY | VAR | WHILE => Some(Kind::Keyword),
+            _ => None,
+        }
+    }
+
+    fn ansi(self) -> &'static str {
+        match self {
+            Kind::Keyword => "1;35",
+            Kind::Literal => "36",
+            Kind::Number => "33",
+            Kind::String => "32",
+            Kind::Comment => "90",
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Kind::Keyword => "lox-keyword",
+            Kind::Literal => "lox-literal",
+            Kind::Number => "lox-number",
+            Kind::String => "lox-string",
+            Kind::Comment => "lox-comment",
+        }
+    }
+}
+
+/// Marks up `source` given the tokens the scanner found in it.
+///
+/// ```
+/// # use rustyinterpreter::{diagnostics::{Diagnostics, Format}, highlight::{self, Style}, scanner::Scanner};
+/// let source = "var x = 1; // one";
+/// let tokens = Scanner::new(source).scan_tokens(&mut Diagnostics::new(Format::Plain));
+/// assert_eq!(
+///     highlight::highlight(source, &tokens, Style::Html),
+///     "<pre class=\"lox\"><code><span class=\"lox-keyword\">var</span> x = \
+///      <span class=\"lox-number\">1</span>; <span class=\"lox-comment\">// one</span></code></pre>\n"
+/// );
+/// ```
+pub fn highlight(source: &str, tokens: &[Token], style: Style) -> String {
+    let mut out = String::new();
+    if style == Style::Html {
+        out.push_str("<pre class=\"lox\"><code>");
+    }
+    let mut end = 0;
+    // Tokens the scanner inserts, such as optional semicolons, cover no text.
+    for token in tokens
+        .iter()
+        .filter(|token| token.span.end > token.span.start)
+    {
+        if token.span.start < end {
+            continue;
+        }
+        gap(&mut out, &source[end..token.span.start], style);
+        span(
+            &mut out,
+            &source[token.span.start..token.span.end],
+            Kind::of(&token.token_type),
+            style,
+        );
+        end = token.span.end;
+    }
+    gap(&mut out, &source[end..], style);
+    if style == Style::Html {
+        out.push_str("</code></pre>\n");
+    }
+    out
+}
+
+/// Text between tokens: whitespace, comments, and whatever the scanner
+/// rejected.
+fn gap(out: &mut String, mut text: &str, style: Style) {
+    while let Some(start) = text.find("//") {
+        span(out, &text[..start], None, style);
+        let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+        span(out, &text[start..end], Some(Kind::Comment), style);
+        text = &text[end..];
+    }
+    span(out, text, None, style);
+}
+
+fn span(out: &mut String, text: &str, kind: Option<Kind>, style: Style) {
+    match (style, kind) {
+        (_, _) if text.is_empty() => {}
+        (Style::Ansi, Some(kind)) => out.push_str(&format!("\x1b[{}m{text}\x1b[0m", kind.ansi())),
+        (Style::Ansi, None) => out.push_str(text),
+        (Style::Html, Some(kind)) => out.push_str(&format!(
+            "<span class=\"{}\">{}</span>",
+            kind.class(),
+            escape(text)
+        )),
+        (Style::Html, None) => out.push_str(&escape(text)),
+    }
+}
+
+/// Escapes `text` for HTML.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
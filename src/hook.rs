@@ -0,0 +1,23 @@
+use crate::grammar::*;
+use crate::interpreter::Interpreter;
+
+/// What the tree-walker is about to run when it calls the hooks.
+pub enum Node<'a> {
+    Statement(&'a Statement),
+    Expression(&'a Expression),
+}
+
+pub struct Step<'a> {
+    pub node: Node<'a>,
+    pub line: usize,
+    /// Blocks entered, 0 at the top level.
+    pub depth: usize,
+}
+
+/// Called before each statement and expression, with the interpreter to
+/// read variables from through [`Interpreter::lookup`] and
+/// [`Interpreter::visible`].
+#[cfg(not(feature = "sync"))]
+pub type Hook = dyn FnMut(&Interpreter, &Step);
+#[cfg(feature = "sync")]
+pub type Hook = dyn FnMut(&Interpreter, &Step) + Send;
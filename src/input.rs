@@ -0,0 +1,44 @@
+use std::io::{self, BufRead, BufReader};
+
+use crate::shared::{Lock, MaybeSend, Shared};
+
+#[cfg(not(feature = "sync"))]
+type Reader = dyn BufRead;
+#[cfg(feature = "sync")]
+type Reader = dyn BufRead + Send;
+
+/// Where lines of input come from: the REPL's, and the script's own. Clones
+/// share the source, and [`Input::replace`] swaps it for all of them, so
+/// natives holding a clone follow the interpreter's input.
+#[derive(Clone)]
+pub struct Input(Shared<Lock<Box<Reader>>>);
+
+impl Input {
+    pub fn new(reader: impl BufRead + MaybeSend + 'static) -> Self {
+        Input(Shared::new(Lock::new(Box::new(reader))))
+    }
+
+    /// Standard input, locked only while a line is being read.
+    pub fn stdin() -> Self {
+        Self::new(BufReader::new(io::stdin()))
+    }
+
+    pub fn replace(&self, reader: impl BufRead + MaybeSend + 'static) {
+        *self.0.lock() = Box::new(reader);
+    }
+
+    /// The next line without its line ending, or `None` at the end of input.
+    pub fn read_line(&self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.0.lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+}
@@ -1,64 +1,926 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+use thiserror::Error;
+
+use crate::codes::Code;
+use crate::diagnostics::{Diagnostic, Diagnostics, Format};
 use crate::grammar::*;
+use crate::hook::{Hook, Node, Step};
+use crate::input::Input;
+use crate::interrupt;
+use crate::metrics::{self, Memory, Metrics};
+use crate::natives::{self, Context, Request, Sandbox};
+use crate::output::{Flush, Output};
+use crate::parser::Parser;
+use crate::replay::Log;
+use crate::scanner::Scanner;
+use crate::shared::{MaybeSend, MaybeSync, Shared};
+use crate::snapshot::{self, SnapshotError};
+use crate::trace::{Event, Tracer};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuntimeErrorKind {
+    Type,
+    UndefinedVariable,
+    Interrupted,
+    Index,
+    Limit,
+    NotCallable,
+    Arity,
+    Native,
+    Output,
+    /// The script called `exit` with this status.
+    Exit(i32),
+    /// A module could not be read or parsed.
+    Import,
+    /// The script threw a value that no `catch` caught.
+    Thrown,
+    /// The script called `panic`.
+    Panic,
+    /// The script divided by zero, with checked division on.
+    DivisionByZero,
+    /// The script assigned to a constant declared by an earlier source.
+    Constant,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("{message}\n[line {line}]")]
+pub struct RuntimeError {
+    pub message: String,
+    pub line: usize,
+    pub span: Span,
+    pub kind: RuntimeErrorKind,
+    /// The value given to `throw`, for errors of that kind.
+    pub thrown: Option<Literal>,
+}
+
+impl RuntimeError {
+    pub fn new(token: &Token, kind: RuntimeErrorKind, message: impl Into<String>) -> Self {
+        RuntimeError {
+            message: message.into(),
+            line: token.line_num,
+            span: token.span,
+            kind,
+            thrown: None,
+        }
+    }
+
+    /// For errors raised away from the syntax tree, such as in the VM.
+    pub fn at(line: usize, span: Span, kind: RuntimeErrorKind, message: impl Into<String>) -> Self {
+        RuntimeError {
+            message: message.into(),
+            line,
+            span,
+            kind,
+            thrown: None,
+        }
+    }
+
+    /// For errors raised where only the line is known.
+    pub fn at_line(line: usize, kind: RuntimeErrorKind, message: impl Into<String>) -> Self {
+        Self::at(line, Span::default(), kind, message)
+    }
+
+    fn type_error(token: &Token, message: &str) -> Self {
+        Self::new(token, RuntimeErrorKind::Type, message)
+    }
+
+    /// Whether `try` can catch it. Exiting, panics, interrupts and exceeded
+    /// limits always end the run.
+    pub fn catchable(&self) -> bool {
+        !matches!(
+            self.kind,
+            RuntimeErrorKind::Exit(_)
+                | RuntimeErrorKind::Panic
+                | RuntimeErrorKind::Interrupted
+                | RuntimeErrorKind::Limit
+        )
+    }
+
+    /// The value a `catch` binds: what was thrown, or for an error raised by
+    /// the interpreter or a native, a map of its `type`, `message`, `line`
+    /// and error `code`.
+    pub fn value(self) -> Literal {
+        if let Some(thrown) = self.thrown {
+            return thrown;
+        }
+        let error_type = self.kind.error_type().unwrap_or("Error");
+        let entries = BTreeMap::from([
+            ("type".to_string(), Literal::String(error_type.to_string())),
+            ("message".to_string(), Literal::String(self.message)),
+            ("line".to_string(), Literal::Number(self.line as f64)),
+            (
+                "code".to_string(),
+                Literal::String(Code::from(self.kind).as_str().to_string()),
+            ),
+        ]);
+        Literal::Map(Shared::new(entries))
+    }
+
+    /// The type `catch (e: Type)` matches: the `type` of an error made with
+    /// the `error` native, or the built-in type of the interpreter's own.
+    pub fn error_type(&self) -> Option<String> {
+        match &self.thrown {
+            Some(thrown) => error_type(thrown),
+            None => self.kind.error_type().map(str::to_string),
+        }
+    }
+
+    /// Whether a `catch` for `error_type` catches it. Every error is an
+    /// `Error`.
+    pub fn is_a(&self, error_type: &str) -> bool {
+        error_type == "Error" || self.error_type().as_deref() == Some(error_type)
+    }
+}
+
+impl RuntimeErrorKind {
+    /// The built-in error type scripts catch these errors by.
+    pub fn error_type(self) -> Option<&'static str> {
+        match self {
+            RuntimeErrorKind::Type | RuntimeErrorKind::Constant => Some("TypeError"),
+            RuntimeErrorKind::UndefinedVariable => Some("NameError"),
+            RuntimeErrorKind::Index => Some("IndexError"),
+            RuntimeErrorKind::NotCallable | RuntimeErrorKind::Arity => Some("CallError"),
+            RuntimeErrorKind::Native => Some("NativeError"),
+            RuntimeErrorKind::Output => Some("OutputError"),
+            RuntimeErrorKind::Import => Some("ImportError"),
+            RuntimeErrorKind::DivisionByZero => Some("ZeroDivisionError"),
+            RuntimeErrorKind::Thrown
+            | RuntimeErrorKind::Panic
+            | RuntimeErrorKind::Interrupted
+            | RuntimeErrorKind::Limit
+            | RuntimeErrorKind::Exit(_) => None,
+        }
+    }
+}
+
+/// The `type` of an error map, as made by the `error` native.
+fn error_type(value: &Literal) -> Option<String> {
+    match value {
+        Literal::Map(entries) => match entries.get("type") {
+            Some(Literal::String(error_type)) => Some(error_type.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FrameKind {
+    Script,
+    Block,
+}
+
+/// An entry on the interpreter's call stack. `entry_line` is where the frame
+/// was entered from, `line` is the line currently executing inside it.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub entry_line: usize,
+    pub line: usize,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            FrameKind::Script => write!(f, "in script at line {}", self.line),
+            FrameKind::Block => write!(
+                f,
+                "in block at line {}, entered from line {}",
+                self.line, self.entry_line
+            ),
+        }
+    }
+}
+
+/// Resource limits for a run. Both backends honour the timeout; the nesting
+/// depth only applies to the tree-walker, which keeps a frame per block.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub max_depth: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+/// A timeout turned into the instant it expires at.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    at: Instant,
+    timeout: Duration,
+}
+
+impl Deadline {
+    pub fn start(timeout: Duration) -> Self {
+        Deadline {
+            at: Instant::now() + timeout,
+            timeout,
+        }
+    }
+
+    pub fn at(&self) -> Instant {
+        self.at
+    }
+
+    pub fn check(&self, line: usize, span: Span) -> Result<(), RuntimeError> {
+        if Instant::now() < self.at {
+            return Ok(());
+        }
+        Err(RuntimeError::at(
+            line,
+            span,
+            RuntimeErrorKind::Limit,
+            format!("Timed out after {} ms.", self.timeout.as_millis()),
+        ))
+    }
+}
 
 pub struct Interpreter {
-    environment: HashMap<String, Literal>,
+    /// Innermost scope last; the first entry holds the globals.
+    environment: Vec<HashMap<String, Literal>>,
+    /// Globals declared with `const`. The parser rejects assignments to
+    /// other constants, which are only visible in the source declaring them.
+    constants: HashSet<String>,
+    /// Globals defined by the host, which [`Self::reset`] goes back to.
+    host: HashMap<String, Literal>,
+    frames: Vec<Frame>,
+    pub statements: u64,
+    allocations: u64,
+    memory: Memory,
+    elapsed: Duration,
+    max_depth: Option<usize>,
+    deadline: Option<Deadline>,
+    tracer: Option<Tracer>,
+    hooks: Vec<Box<Hook>>,
+    /// Statements executed per line, when coverage is being recorded.
+    hits: Option<HashMap<usize, u64>>,
+    output: OutputFormat,
+    /// Whether dividing by zero is an error.
+    checked_division: bool,
+    /// Whether assigning to an undefined variable defines a global.
+    implicit_globals: bool,
+    /// Whether modules and `eval` are scanned with optional semicolons.
+    optional_semicolons: bool,
+    /// Whether `print` is a keyword in modules and `eval`.
+    print_keyword: bool,
+    /// Shared with the standard natives.
+    context: Context,
+    /// Modules imported so far, by canonical path, so each runs once.
+    modules: HashMap<PathBuf, Literal>,
+    /// The file being run, which `./` and `../` imports in it are relative to.
+    script: Option<PathBuf>,
+    /// Modules whose imports are running, outermost first.
+    importing: Vec<PathBuf>,
+    /// Directories searched for bare imports, after the working directory.
+    search_path: Vec<PathBuf>,
+    /// What `defer` scheduled, per running block, innermost last.
+    deferred: Vec<Vec<Expression>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
+        let context = Context::new(Input::stdin(), Output::stdout());
+        let host: HashMap<_, _> = natives::standard(&context)
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
         Interpreter {
-            environment: HashMap::new(),
+            environment: vec![host.clone()],
+            constants: HashSet::new(),
+            host,
+            frames: vec![Frame {
+                kind: FrameKind::Script,
+                entry_line: 0,
+                line: 1,
+            }],
+            statements: 0,
+            allocations: 0,
+            memory: Memory::default(),
+            elapsed: Duration::ZERO,
+            max_depth: None,
+            deadline: None,
+            tracer: None,
+            hooks: vec![],
+            hits: None,
+            output: OutputFormat::default(),
+            checked_division: false,
+            implicit_globals: false,
+            optional_semicolons: false,
+            print_keyword: true,
+            context,
+            modules: HashMap::new(),
+            script: None,
+            importing: vec![],
+            search_path: vec![],
+            deferred: vec![],
         }
     }
 
-    pub fn interpret(&mut self, statements: Vec<Statement>) -> Result<(), &'static str> {
-        for statement in statements {
-            self.execute(statement)?;
+    /// An interpreter whose `print` writes to `writer` instead of stdout.
+    pub fn with_output(writer: impl Write + MaybeSend + 'static, flush: Flush) -> Self {
+        let mut interpreter = Self::new();
+        interpreter.set_output(writer, flush);
+        interpreter
+    }
+
+    /// Sends what `print` writes to `writer` from now on.
+    pub fn set_output(&mut self, writer: impl Write + MaybeSend + 'static, flush: Flush) {
+        *self.context.output.lock() = Output::new(writer, flush);
+    }
+
+    /// Makes `random` and `randomInt` return the same numbers on every run.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.context.seed(seed);
+    }
+
+    pub fn set_output_format(&mut self, output: OutputFormat) {
+        self.output = output;
+        self.context.set_output_format(output);
+    }
+
+    /// Makes dividing by zero a runtime error, rather than giving an
+    /// infinity or NaN that shows up far from its cause.
+    pub fn set_checked_division(&mut self, checked: bool) {
+        self.checked_division = checked;
+    }
+
+    /// Makes assigning to a variable that is not declared anywhere define it
+    /// as a global, as sloppy JavaScript does, rather than raise an error.
+    pub fn set_implicit_globals(&mut self, implicit: bool) {
+        self.implicit_globals = implicit;
+    }
+
+    /// Lets imported modules and `eval` leave out semicolons, as
+    /// [`Scanner::with_optional_semicolons`] describes. Scripts the host
+    /// scans itself are up to it.
+    pub fn set_optional_semicolons(&mut self, optional: bool) {
+        self.optional_semicolons = optional;
+    }
+
+    /// Like [`Self::set_optional_semicolons`], for
+    /// [`Scanner::with_print_keyword`].
+    pub fn set_print_keyword(&mut self, keyword: bool) {
+        self.print_keyword = keyword;
+    }
+
+    /// Which values `!` and the natives take as false.
+    pub fn set_compat(&mut self, compat: Compat) {
+        self.context.set_compat(compat);
+    }
+
+    /// Starts counting executed statements per line for [`Self::take_hits`].
+    pub fn record_coverage(&mut self) {
+        self.hits = Some(HashMap::new());
+    }
+
+    /// Line counts recorded since the last call.
+    pub fn take_hits(&mut self) -> HashMap<usize, u64> {
+        self.hits.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Reports every statement and assignment to `tracer` from now on.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Calls `hook` before each statement and expression from now on, after
+    /// the hooks added before it. Only the tree-walker has hooks.
+    ///
+    /// ```
+    /// # use rustyinterpreter::{hook::Node, Lox};
+    /// let mut lox = Lox::new();
+    /// lox.interpreter().add_hook(|interpreter, step| {
+    ///     if let Node::Statement(_) = step.node {
+    ///         eprintln!("line {}: x = {:?}", step.line, interpreter.lookup("x"));
+    ///     }
+    /// });
+    /// lox.run("var x = 1;\n{ var x = 2; x = 3; }").unwrap();
+    /// ```
+    pub fn add_hook(&mut self, hook: impl FnMut(&Interpreter, &Step) + MaybeSend + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Reads input from `reader` instead of stdin.
+    pub fn with_input(self, reader: impl BufRead + MaybeSend + 'static) -> Self {
+        self.context.input.replace(reader);
+        self
+    }
+
+    /// Leaves out the standard natives `sandbox` does not allow.
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        for name in natives::denied(sandbox) {
+            self.host.remove(name);
+            self.environment[0].remove(name);
+        }
+        self
+    }
+
+    /// Records the results of the nondeterministic standard natives to
+    /// `log`, or replays them from it.
+    pub fn with_log(mut self, log: &Log) -> Self {
+        for name in natives::nondeterministic() {
+            if let Some(Literal::Native(native)) = self.host.get(name).cloned() {
+                self.define_global(name, log.wrap(native));
+            }
+        }
+        self
+    }
+
+    /// The interpreter's input, for natives and the REPL to read from.
+    pub fn input(&self) -> Input {
+        self.context.input.clone()
+    }
+
+    /// Applies `limits`; the timeout counts from now.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.max_depth = limits.max_depth;
+        self.deadline = limits.timeout.map(Deadline::start);
+        self.context
+            .set_deadline(self.deadline.map(|deadline| deadline.at()));
+    }
+
+    /// Frames that were active when the last error was raised, innermost first.
+    /// Empty when the error happened at the top level of the script.
+    pub fn stack_trace(&self) -> Vec<&Frame> {
+        if self.frames.len() < 2 {
+            return vec![];
+        }
+        self.frames.iter().rev().collect()
+    }
+
+    /// Runs `statements` at the top level. Block scopes are dropped again even
+    /// when an error escapes them; frames are kept for [`Self::stack_trace`].
+    pub fn interpret(&mut self, statements: Vec<Statement>) -> Result<(), RuntimeError> {
+        self.frames.truncate(1);
+        let start = Instant::now();
+        let result = self.execute_all(statements);
+        self.elapsed += start.elapsed();
+        let line = self.frames.last().map_or(0, |frame| frame.line);
+        let flushed = self
+            .context
+            .output
+            .lock()
+            .flush()
+            .map_err(|err| output_error(line, err));
+        let result = result.and(flushed);
+        if let Err(err) = &result {
+            self.trace(err.line, Event::Error(&err.message));
+        }
+        while self.environment.len() > 1 {
+            self.pop_scope();
+        }
+        result
+    }
+
+    /// Defines a global before any script runs, such as `ARGS`. It survives
+    /// [`Self::reset`].
+    pub fn define_global(&mut self, name: &str, value: Literal) {
+        self.host.insert(name.to_string(), value.clone());
+        self.constants.remove(name);
+        self.environment[0].insert(name.to_string(), value);
+    }
+
+    /// Forgets the globals scripts defined and undoes their assignments to
+    /// the host's, so that the next script starts as the first one did.
+    /// Without a reset, each run sees the globals left by the ones before.
+    pub fn reset(&mut self) {
+        self.environment = vec![self.host.clone()];
+        self.constants.clear();
+        self.frames.truncate(1);
+        self.memory.clear();
+        self.modules.clear();
+        self.importing.clear();
+        self.deferred.clear();
+    }
+
+    /// Tells the interpreter which file the next script comes from, so that
+    /// its `./` and `../` imports resolve next to it rather than in the
+    /// working directory.
+    pub fn set_script(&mut self, path: impl AsRef<Path>) {
+        self.script = fs::canonicalize(path).ok();
+    }
+
+    /// Resolves imports in the working directory again, as before
+    /// [`Self::set_script`].
+    pub fn clear_script(&mut self) {
+        self.script = None;
+    }
+
+    /// The file running now: the module being imported, or else the script
+    /// given to [`Self::set_script`].
+    pub fn current_file(&self) -> Option<&Path> {
+        self.importing
+            .last()
+            .or(self.script.as_ref())
+            .map(PathBuf::as_path)
+    }
+
+    /// Directories where bare imports such as `import "collections";` are
+    /// looked for, in order, when the working directory does not have them.
+    pub fn set_search_path(&mut self, dirs: Vec<PathBuf>) {
+        self.search_path = dirs;
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            steps: self.statements,
+            allocations: self.allocations,
+            peak_memory: self.memory.peak,
+            elapsed: self.elapsed,
+        }
+    }
+
+    /// Makes `function` callable from scripts as the global `name`, taking
+    /// exactly `arity` arguments. An `Err` it returns becomes a runtime error.
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Literal]) -> NativeResult + MaybeSync + 'static,
+    ) {
+        self.define_global(name, Literal::native(name, arity, function));
+    }
+
+    /// The globals, encoded with [`snapshot::encode`] for [`Self::restore`].
+    pub fn snapshot(&self) -> Vec<u8> {
+        snapshot::encode(self.globals())
+    }
+
+    /// Defines the globals saved by [`Self::snapshot`], replacing any that
+    /// are already defined with the same name.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        for (name, value) in snapshot::decode(bytes)? {
+            self.constants.remove(&name);
+            self.environment[0].insert(name, value);
         }
         Ok(())
     }
 
-    fn execute(&mut self, statement: Statement) -> Result<(), &'static str> {
+    pub fn global(&self, name: &str) -> Option<&Literal> {
+        self.environment[0].get(name)
+    }
+
+    /// The variable `name` as seen from the innermost scope.
+    pub fn lookup(&self, name: &str) -> Option<&Literal> {
+        self.environment
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+    }
+
+    /// Every variable in scope, sorted by name, leaving out those that
+    /// inner scopes shadow.
+    pub fn visible(&self) -> Vec<(&String, &Literal)> {
+        let mut visible = HashMap::new();
+        for scope in &self.environment {
+            visible.extend(scope.iter());
+        }
+        let mut visible: Vec<_> = visible.into_iter().collect();
+        visible.sort_by_key(|(name, _)| *name);
+        visible
+    }
+
+    /// Global variables and their values, sorted by name.
+    pub fn globals(&self) -> Vec<(&String, &Literal)> {
+        let mut globals: Vec<_> = self.environment[0].iter().collect();
+        globals.sort_by_key(|(name, _)| *name);
+        globals
+    }
+
+    /// Like [`Self::globals`], without those that still hold what the host
+    /// defined them to, such as natives and the prelude.
+    pub fn script_globals(&self) -> Vec<(&String, &Literal)> {
+        let mut globals = self.globals();
+        globals.retain(|(name, value)| {
+            !self
+                .host
+                .get(*name)
+                .is_some_and(|host| match (host, value) {
+                    // So that `NaN` counts as unchanged.
+                    (Literal::Number(a), Literal::Number(b)) => a.to_bits() == b.to_bits(),
+                    _ => host.identical(value),
+                })
+        });
+        globals
+    }
+
+    fn execute(&mut self, statement: Statement) -> Result<(), RuntimeError> {
+        if interrupt::take() {
+            return Err(RuntimeError::at_line(
+                self.frames.last().map_or(0, |frame| frame.line),
+                RuntimeErrorKind::Interrupted,
+                "Interrupted.",
+            ));
+        }
+        if let Some(deadline) = &self.deadline {
+            let line = self.frames.last().map_or(0, |frame| frame.line);
+            deadline.check(line, Span::default())?;
+        }
+        self.statements += 1;
+        if let Some(hits) = &mut self.hits {
+            *hits.entry(statement.line()).or_default() += 1;
+        }
+        self.trace(statement.line(), Event::Statement(&statement));
+        if !self.hooks.is_empty() {
+            self.run_hooks(Node::Statement(&statement), statement.line());
+        }
         match statement {
-            Statement::Print(expr) => match self.evaluate(&expr)? {
-                Literal::Number(n) => println!("{}", n),
-                val => println!("{}", val),
-            },
-            Statement::Expression(expr) => {
+            Statement::Print { expr, line } => {
+                let value = self.evaluate(&expr)?;
+                let text = self.output.show(&value);
+                self.context
+                    .output
+                    .lock()
+                    .print(&text)
+                    .map_err(|err| output_error(line, err))?;
+            }
+            Statement::Expression { expr, .. } => {
                 self.evaluate(&expr)?;
             }
-            Statement::Variable { name, init } => {
+            Statement::Variable {
+                name,
+                init,
+                constant,
+                ..
+            } => {
                 let value = match init {
                     Some(expr) => self.evaluate(&expr)?,
                     None => Literal::Nil,
                 };
-                self.environment.insert(name.lexeme, value);
+                self.trace(
+                    name.line_num,
+                    Event::Assign {
+                        name: &name.lexeme,
+                        value: &value,
+                    },
+                );
+                self.memory.add(&value);
+                if self.environment.len() == 1 {
+                    if constant {
+                        self.constants.insert(name.lexeme.clone());
+                    } else {
+                        self.constants.remove(&name.lexeme);
+                    }
+                }
+                if let Some(old) = self.scope().insert(name.lexeme, value) {
+                    self.memory.remove(&old);
+                }
+            }
+            Statement::Block { statements, line } => {
+                self.set_line(line);
+                self.execute_block(statements, line)?;
             }
-            Statement::Block(statements) => {
-                self.execute_block(statements)?;
+            Statement::Import { path, name, line } => {
+                self.set_line(line);
+                let module = self.import(&path, line)?;
+                self.memory.add(&module);
+                if let Some(old) = self.scope().insert(name, module) {
+                    self.memory.remove(&old);
+                }
+            }
+            Statement::Defer { expr, .. } => match self.deferred.last_mut() {
+                Some(deferred) => deferred.push(expr),
+                // Outside any block, as in a host's `evaluate`, there is
+                // nothing to wait for.
+                None => {
+                    self.evaluate(&expr)?;
+                }
+            },
+            Statement::Throw { keyword, value } => {
+                let value = self.evaluate(&value)?;
+                let message = match (&value, error_type(&value)) {
+                    (Literal::Map(entries), Some(error_type)) => match entries.get("message") {
+                        Some(message) => format!("Uncaught {error_type}: {message}"),
+                        None => format!("Uncaught {error_type}"),
+                    },
+                    _ => format!("Uncaught exception: {}", self.output.show(&value)),
+                };
+                let mut err = RuntimeError::new(&keyword, RuntimeErrorKind::Thrown, message);
+                err.thrown = Some(value);
+                return Err(err);
+            }
+            Statement::Try {
+                body,
+                catches,
+                finally,
+                line,
+            } => {
+                self.set_line(line);
+                let mut trace = vec![];
+                let result = self.unwinding(&mut trace, |this| this.execute_block(body, line));
+                let catch = match &result {
+                    Err(err) if err.catchable() => catches.into_iter().find(|catch| {
+                        catch
+                            .error_type
+                            .as_ref()
+                            .map_or(true, |error_type| err.is_a(&error_type.lexeme))
+                    }),
+                    _ => None,
+                };
+                let result = match (result, catch) {
+                    (Err(err), Some(Catch { name, body, .. })) => {
+                        self.unwinding(&mut trace, |this| {
+                            let value = err.value();
+                            this.memory.add(&value);
+                            this.environment.push(HashMap::from([(name.lexeme, value)]));
+                            this.execute_block(body, name.line_num)?;
+                            this.pop_scope();
+                            Ok(())
+                        })
+                    }
+                    (result, _) => result,
+                };
+                // An error in `finally` replaces the one being unwound.
+                if let Some(finally) = finally {
+                    self.execute_block(finally, line)?;
+                }
+                if result.is_err() {
+                    self.frames.extend(trace);
+                }
+                result?;
             }
         }
         Ok(())
     }
 
-    pub fn evaluate(&mut self, expr: &Expression) -> Result<Literal, &'static str> {
+    /// Runs `run`, and if it fails, drops the scopes and frames it left
+    /// behind so that the run can go on where it started. The frames go to
+    /// `trace`, to restore if the error escapes after all.
+    fn unwinding(
+        &mut self,
+        trace: &mut Vec<Frame>,
+        run: impl FnOnce(&mut Self) -> Result<(), RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        let (scopes, frames) = (self.environment.len(), self.frames.len());
+        let result = run(self);
+        if result.is_err() {
+            while self.environment.len() > scopes {
+                self.pop_scope();
+            }
+            *trace = self.frames.split_off(frames);
+        }
+        result
+    }
+
+    /// Runs the module at `path` in a scope of its own, next to the globals,
+    /// and gives its top-level variables as a map. Errors in the module are
+    /// reported at the import, naming the module's file and line.
+    fn import(&mut self, path: &str, line: usize) -> Result<Literal, RuntimeError> {
+        let failed =
+            |message: String| RuntimeError::at_line(line, RuntimeErrorKind::Import, message);
+        let resolved = fs::canonicalize(self.resolve(path))
+            .map_err(|err| failed(format!("Cannot import '{path}': {err}.")))?;
+        if let Some(module) = self.modules.get(&resolved) {
+            return Ok(module.clone());
+        }
+        let chain: Vec<&PathBuf> = self.script.iter().chain(&self.importing).collect();
+        if let Some(start) = chain.iter().position(|&file| *file == resolved) {
+            let cycle: Vec<String> = chain[start..]
+                .iter()
+                .chain([&&resolved])
+                .map(|file| shown(file))
+                .collect();
+            return Err(failed(format!("Import cycle: {}.", cycle.join(" -> "))));
+        }
+        let source = fs::read_to_string(&resolved)
+            .map_err(|err| failed(format!("Cannot import '{path}': {err}.")))?;
+        let mut diagnostics = Diagnostics::new(Format::Plain);
+        let tokens = Scanner::new(&source)
+            .with_optional_semicolons(self.optional_semicolons)
+            .with_print_keyword(self.print_keyword)
+            .scan_tokens(&mut diagnostics);
+        let statements = match diagnostics.iter().next() {
+            Some(diagnostic) => Err(diagnostic.clone()),
+            None => Parser::new(&tokens).parse(),
+        }
+        .map_err(|diagnostic| {
+            failed(format!(
+                "In '{path}' at line {}: {}",
+                diagnostic.line, diagnostic.message
+            ))
+        })?;
+
+        let outer = self.environment.split_off(1);
+        let frames = self.frames.len();
+        self.environment.push(HashMap::new());
+        self.frames.push(Frame {
+            kind: FrameKind::Script,
+            entry_line: line,
+            line: 1,
+        });
+        self.importing.push(resolved.clone());
+        let result = self.execute_all(statements);
+        self.importing.pop();
+        while self.environment.len() > 2 {
+            self.pop_scope();
+        }
+        let scope = self.environment.pop().unwrap_or_default();
+        self.environment.extend(outer);
+        self.frames.truncate(frames);
+        result.map_err(|err| match err.kind {
+            RuntimeErrorKind::Exit(_) => err,
+            kind => RuntimeError::at_line(
+                line,
+                kind,
+                format!("In '{path}' at line {}: {}", err.line, err.message),
+            ),
+        })?;
+
+        scope.values().for_each(|value| self.memory.remove(value));
+        let module = map(scope.iter());
+        self.modules.insert(resolved, module.clone());
+        Ok(module)
+    }
+
+    /// Where the import of `path` from the file running now points.
+    fn resolve(&self, path: &str) -> PathBuf {
+        let importer = self.importing.last().or(self.script.as_ref());
+        resolve_import(path, importer.map(PathBuf::as_path), &self.search_path)
+    }
+
+    /// Does what a native called at `paren` asked for, giving the call's
+    /// value.
+    fn answer(&mut self, request: Request, paren: &Token) -> Result<Literal, RuntimeError> {
+        match request {
+            Request::Exit(status) => Err(exited(paren.line_num, paren.span, status)),
+            Request::Panic(message) => Err(panicked(paren.line_num, paren.span, &message)),
+            Request::Eval(source) => self.eval(&source, paren),
+            Request::Globals => Ok(map(self.environment[0].iter())),
+            Request::Locals => Ok(map(self.environment[1..].iter().flatten())),
+            Request::Defined(name) => Ok(Literal::Boolean(self.lookup(&name).is_some())),
+        }
+    }
+
+    /// Runs `source` for the `eval` native called at `paren`, in the scope of
+    /// the call: an expression gives its value, statements give nil and
+    /// declare their variables there. Syntax errors are runtime errors of
+    /// the call.
+    fn eval(&mut self, source: &str, paren: &Token) -> Result<Literal, RuntimeError> {
+        let mut diagnostics = Diagnostics::new(Format::Plain);
+        let tokens = Scanner::new(source)
+            .with_optional_semicolons(self.optional_semicolons)
+            .with_print_keyword(self.print_keyword)
+            .scan_tokens(&mut diagnostics);
+        let syntax_error = |diagnostic: &Diagnostic| {
+            RuntimeError::new(
+                paren,
+                RuntimeErrorKind::Native,
+                format!("eval: {}", diagnostic.message),
+            )
+        };
+        if let Some(diagnostic) = diagnostics.iter().next() {
+            return Err(syntax_error(diagnostic));
+        }
+        if let Ok(expression) = Parser::new(&tokens).complete_expression() {
+            return self.evaluate(&expression);
+        }
+        let statements = Parser::new(&tokens)
+            .parse()
+            .map_err(|diagnostic| syntax_error(&diagnostic))?;
+        for statement in statements {
+            self.execute(statement)?;
+        }
+        Ok(Literal::Nil)
+    }
+
+    /// Evaluates `expr` with `variables` defined in a scope of their own,
+    /// which is dropped afterwards.
+    pub fn evaluate_with(
+        &mut self,
+        expr: &Expression,
+        variables: HashMap<String, Literal>,
+    ) -> Result<Literal, RuntimeError> {
+        self.environment.push(variables);
+        let result = self.evaluate(expr);
+        self.environment.pop();
+        result
+    }
+
+    pub fn evaluate(&mut self, expr: &Expression) -> Result<Literal, RuntimeError> {
+        if !self.hooks.is_empty() {
+            let line = self.frames.last().map_or(0, |frame| frame.line);
+            self.run_hooks(Node::Expression(expr), line);
+        }
         let literal = match expr {
             Expression::Literal(l) => l.clone(),
             Expression::Group(expr) => self.evaluate(expr)?,
             Expression::Unary { op, expr } => {
                 let literal = self.evaluate(expr)?;
+                self.set_line(op.line_num);
                 match op.token_type {
-                    TokenType::BANG => match literal {
-                        Literal::Boolean(b) => Literal::Boolean(!b),
-                        Literal::Number(n) => Literal::Boolean(n == 0.0),
-                        Literal::String(s) => Literal::Boolean(s.is_empty()),
-                        Literal::Nil => Literal::Boolean(true),
-                    },
+                    TokenType::BANG => Literal::Boolean(!self.context.truthy(&literal)),
                     TokenType::MINUS => match literal {
                         Literal::Number(n) => Literal::Number(-n),
-                        _ => return Err("Operand must be a number."),
+                        _ => return Err(RuntimeError::type_error(op, "Operand must be a number.")),
                     },
                     _ => unreachable!(),
                 }
@@ -66,25 +928,37 @@ impl Interpreter {
             Expression::Binary { op, left, right } => {
                 let left = self.evaluate(left)?;
                 let right = self.evaluate(right)?;
+                self.set_line(op.line_num);
                 match op.token_type {
                     TokenType::STAR => match (left, right) {
                         (Literal::Number(l), Literal::Number(r)) => Literal::Number(l * r),
-                        _ => return Err("Operands must be numbers."),
+                        _ => return Err(RuntimeError::type_error(op, "Operands must be numbers.")),
                     },
                     TokenType::SLASH => match (left, right) {
+                        (Literal::Number(_), Literal::Number(r))
+                            if r == 0.0 && self.checked_division =>
+                        {
+                            return Err(division_by_zero(op.line_num, op.span))
+                        }
                         (Literal::Number(l), Literal::Number(r)) => Literal::Number(l / r),
-                        _ => return Err("Operands must be numbers."),
+                        _ => return Err(RuntimeError::type_error(op, "Operands must be numbers.")),
                     },
                     TokenType::PLUS => match (left, right) {
                         (Literal::Number(l), Literal::Number(r)) => Literal::Number(l + r),
                         (Literal::String(l), Literal::String(r)) => {
+                            self.allocations += 1;
                             Literal::String(format!("{}{}", l, r))
                         }
-                        _ => return Err("Operands must be two numbers or two strings."),
+                        _ => {
+                            return Err(RuntimeError::type_error(
+                                op,
+                                "Operands must be two numbers or two strings.",
+                            ))
+                        }
                     },
                     TokenType::MINUS => match (left, right) {
                         (Literal::Number(l), Literal::Number(r)) => Literal::Number(l - r),
-                        _ => return Err("Operands must be numbers."),
+                        _ => return Err(RuntimeError::type_error(op, "Operands must be numbers.")),
                     },
                     TokenType::LESS
                     | TokenType::LESS_EQUAL
@@ -93,55 +967,295 @@ impl Interpreter {
                         (Literal::Number(l), Literal::Number(r)) => {
                             Literal::Boolean(compare_number(&op.token_type, l, r))
                         }
-                        _ => return Err("Operands must be numbers."),
+                        _ => return Err(RuntimeError::type_error(op, "Operands must be numbers.")),
                     },
                     TokenType::EQUAL_EQUAL => Literal::Boolean(left == right),
                     TokenType::BANG_EQUAL => Literal::Boolean(left != right),
                     _ => todo!(),
                 }
             }
-            Expression::Variable(var) => self.get_variable(var)?,
+            Expression::Variable(var) => {
+                self.set_line(var.line_num);
+                self.get_variable(var)?
+            }
             Expression::Assign { name, right } => {
                 let value = self.evaluate(right)?;
+                self.set_line(name.line_num);
                 self.reassign_variable(name, &value)?;
+                self.trace(
+                    name.line_num,
+                    Event::Assign {
+                        name: &name.lexeme,
+                        value: &value,
+                    },
+                );
                 value
             }
+            Expression::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                self.set_line(bracket.line_num);
+                self::index(&object, &index)
+                    .map_err(|(kind, message)| RuntimeError::new(bracket, kind, message))?
+            }
+            Expression::Call {
+                callee,
+                paren,
+                args,
+            } => {
+                let callee = self.evaluate(callee)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.evaluate(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.set_line(paren.line_num);
+                let result = call(&callee, &args)
+                    .map_err(|(kind, message)| RuntimeError::new(paren, kind, message))?;
+                let result = match self.context.take_request() {
+                    Some(request) => self.answer(request, paren)?,
+                    None => result,
+                };
+                if metrics::allocates(&result) {
+                    self.allocations += 1;
+                }
+                // Natives that wait, such as `sleep`, return early when the
+                // run is interrupted or times out.
+                if interrupt::take() {
+                    return Err(RuntimeError::new(
+                        paren,
+                        RuntimeErrorKind::Interrupted,
+                        "Interrupted.",
+                    ));
+                }
+                if let Some(deadline) = &self.deadline {
+                    deadline.check(paren.line_num, paren.span)?;
+                }
+                result
+            }
         };
         Ok(literal)
     }
 
-    fn execute_block(&mut self, statements: Vec<Statement>) -> Result<(), &'static str> {
-        let previous = self.environment.clone();
-        for statement in statements {
-            self.execute(statement)?;
+    fn execute_block(
+        &mut self,
+        statements: Vec<Statement>,
+        line: usize,
+    ) -> Result<(), RuntimeError> {
+        if let Some(max_depth) = self.max_depth {
+            if self.frames.len() > max_depth {
+                return Err(RuntimeError::at_line(
+                    line,
+                    RuntimeErrorKind::Limit,
+                    format!("Maximum nesting depth of {} exceeded.", max_depth),
+                ));
+            }
         }
-        self.environment = previous;
+        self.environment.push(HashMap::new());
+        self.frames.push(Frame {
+            kind: FrameKind::Block,
+            entry_line: line,
+            line,
+        });
+        self.trace(line, Event::EnterBlock);
+        self.execute_all(statements)?;
+        let line = self.frames.last().map_or(line, |frame| frame.line);
+        self.trace(line, Event::ExitBlock);
+        self.frames.pop();
+        self.pop_scope();
         Ok(())
     }
 
-    fn get_variable(&self, var: &Token) -> Result<Literal, &'static str> {
+    /// Runs the statements of a block, then what they deferred, last first.
+    /// The deferred expressions run even when a statement fails, and the
+    /// first error wins. They run in the block's scope, even when the error
+    /// came from a block nested in it.
+    fn execute_all(&mut self, statements: Vec<Statement>) -> Result<(), RuntimeError> {
+        let (scopes, depth) = (self.environment.len(), self.frames.len());
+        self.deferred.push(vec![]);
+        let result = statements
+            .into_iter()
+            .try_for_each(|statement| self.execute(statement));
+        let deferred = self.deferred.pop().unwrap_or_default();
+        // The stack trace is of where the error happened, not of the cleanup.
+        let frames = result.is_err().then(|| self.frames.clone());
+        if result.is_err() {
+            while self.environment.len() > scopes {
+                self.pop_scope();
+            }
+            self.frames.truncate(depth);
+        }
+        let result = deferred.iter().rev().fold(result, |result, expr| {
+            let ran = self.evaluate(expr).map(drop);
+            result.and(ran)
+        });
+        if let Some(frames) = frames {
+            self.frames = frames;
+        }
+        result
+    }
+
+    fn pop_scope(&mut self) {
+        if let Some(scope) = self.environment.pop() {
+            scope.values().for_each(|value| self.memory.remove(value));
+        }
+    }
+
+    fn trace(&mut self, line: usize, event: Event) {
+        let depth = self.frames.len() - 1;
+        if let Some(tracer) = &mut self.tracer {
+            tracer.event(line, depth, event);
+        }
+    }
+
+    fn run_hooks(&mut self, node: Node, line: usize) {
+        let step = Step {
+            node,
+            line,
+            depth: self.frames.len() - 1,
+        };
+        let mut hooks = std::mem::take(&mut self.hooks);
+        for hook in &mut hooks {
+            hook(self, &step);
+        }
+        self.hooks = hooks;
+    }
+
+    fn scope(&mut self) -> &mut HashMap<String, Literal> {
+        self.environment.last_mut().unwrap()
+    }
+
+    fn set_line(&mut self, line: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+        }
+    }
+
+    fn get_variable(&self, var: &Token) -> Result<Literal, RuntimeError> {
         let lexeme = &var.lexeme;
-        match self.environment.get(lexeme.as_str()) {
+        match self
+            .environment
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(lexeme.as_str()))
+        {
             Some(value) => Ok(value.clone()),
-            None => {
-                let msg = format!("Undefined variable '{}'.\n[line {}]", lexeme, var.line_num);
-                Err(Box::leak(msg.into_boxed_str()))
-            }
+            None => Err(undefined_variable(var)),
         }
     }
 
-    fn reassign_variable(&mut self, var: &Token, value: &Literal) -> Result<(), &'static str> {
+    fn reassign_variable(&mut self, var: &Token, value: &Literal) -> Result<(), RuntimeError> {
         let lexeme = &var.lexeme;
-        if self.environment.contains_key(lexeme.as_str()) {
-            self.environment.insert(lexeme.clone(), value.clone());
+        let scope = self
+            .environment
+            .iter_mut()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, scope)| Some((depth, scope.get_mut(lexeme.as_str())?)));
+        if let Some((depth, slot)) = scope {
+            if depth == 0 && self.constants.contains(lexeme) {
+                return Err(assigned_constant(var.line_num, var.span, lexeme));
+            }
+            self.memory.remove(slot);
+            self.memory.add(value);
+            *slot = value.clone();
+            Ok(())
+        } else if self.implicit_globals {
+            self.memory.add(value);
+            self.environment[0].insert(lexeme.clone(), value.clone());
             Ok(())
         } else {
-            let msg = format!("Undefined variable '{}'.\n[line {}]", lexeme, var.line_num);
-            Err(Box::leak(msg.into_boxed_str()))
+            Err(undefined_variable(var))
         }
     }
 }
 
+fn undefined_variable(var: &Token) -> RuntimeError {
+    RuntimeError::new(
+        var,
+        RuntimeErrorKind::UndefinedVariable,
+        format!("Undefined variable '{}'.", var.lexeme),
+    )
+}
+
+/// Variables as a map for scripts. Later entries win, so inner scopes
+/// shadow outer ones.
+pub fn map<'a>(variables: impl Iterator<Item = (&'a String, &'a Literal)>) -> Literal {
+    let entries = variables
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    Literal::Map(Shared::new(entries))
+}
+
+/// The error that unwinds the run when the script calls `exit`.
+pub fn exited(line: usize, span: Span, status: i32) -> RuntimeError {
+    RuntimeError::at(
+        line,
+        span,
+        RuntimeErrorKind::Exit(status),
+        format!("Exited with status {status}."),
+    )
+}
+
+/// `file` as messages name it: relative to the working directory when it
+/// is inside it, and absolute otherwise. Files that do not exist are named
+/// by where their directory is.
+pub fn shown(file: &Path) -> String {
+    let canonical = fs::canonicalize(file).ok().or_else(|| {
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty());
+        let dir = fs::canonicalize(dir.unwrap_or(Path::new("."))).ok()?;
+        Some(dir.join(file.file_name()?))
+    });
+    let file = canonical.as_deref().unwrap_or(file);
+    let cwd = std::env::current_dir().and_then(fs::canonicalize);
+    let relative = cwd
+        .ok()
+        .and_then(|cwd| file.strip_prefix(cwd).ok().map(Path::to_path_buf));
+    relative.as_deref().unwrap_or(file).display().to_string()
+}
+
+/// For dividing by zero with checked division, shared by both backends.
+pub fn division_by_zero(line: usize, span: Span) -> RuntimeError {
+    RuntimeError::at(
+        line,
+        span,
+        RuntimeErrorKind::DivisionByZero,
+        "Division by zero.",
+    )
+}
+
+/// For assigning to a constant, shared by both backends.
+pub fn assigned_constant(line: usize, span: Span, name: &str) -> RuntimeError {
+    RuntimeError::at(
+        line,
+        span,
+        RuntimeErrorKind::Constant,
+        format!("Can't assign to constant '{name}'."),
+    )
+}
+
+/// The error `panic` ends the run with.
+pub fn panicked(line: usize, span: Span, message: &str) -> RuntimeError {
+    RuntimeError::at(
+        line,
+        span,
+        RuntimeErrorKind::Panic,
+        format!("Panic: {message}"),
+    )
+}
+
+/// For when `print` cannot write, shared by both backends.
+pub fn output_error(line: usize, err: io::Error) -> RuntimeError {
+    RuntimeError::at_line(
+        line,
+        RuntimeErrorKind::Output,
+        format!("Failed to write output: {err}."),
+    )
+}
+
 fn compare_number(op: &TokenType, l: f64, r: f64) -> bool {
     match op {
         TokenType::EQUAL_EQUAL => l == r,
@@ -153,3 +1267,83 @@ fn compare_number(op: &TokenType, l: f64, r: f64) -> bool {
         _ => unreachable!(),
     }
 }
+
+/// `object[index]`, shared by both backends. Errors come back as a kind and
+/// message for the caller to attach a location to.
+pub fn index(object: &Literal, index: &Literal) -> Result<Literal, (RuntimeErrorKind, String)> {
+    let error = |kind, message: &str| Err((kind, message.to_string()));
+    match (object, index) {
+        (Literal::List(items), Literal::Number(n)) => {
+            if n.fract() != 0.0 || *n < 0.0 || *n >= items.len() as f64 {
+                return error(RuntimeErrorKind::Index, "List index out of range.");
+            }
+            Ok(items[*n as usize].clone())
+        }
+        (Literal::List(_), _) => error(RuntimeErrorKind::Type, "List index must be a number."),
+        (Literal::Map(entries), Literal::String(key)) => match entries.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => error(RuntimeErrorKind::Index, &format!("Undefined key '{key}'.")),
+        },
+        (Literal::Map(_), _) => error(RuntimeErrorKind::Type, "Map key must be a string."),
+        _ => error(
+            RuntimeErrorKind::Type,
+            "Only lists and maps can be indexed.",
+        ),
+    }
+}
+
+/// `callee(args)`, shared by both backends like [`index()`].
+pub fn call(callee: &Literal, args: &[Literal]) -> Result<Literal, (RuntimeErrorKind, String)> {
+    let Literal::Native(native) = callee else {
+        return Err((
+            RuntimeErrorKind::NotCallable,
+            "Can only call functions and classes.".to_string(),
+        ));
+    };
+    if native.variadic && args.len() < native.arity {
+        return Err((
+            RuntimeErrorKind::Arity,
+            format!(
+                "Expected at least {} arguments but got {}.",
+                native.arity,
+                args.len()
+            ),
+        ));
+    }
+    if !native.variadic && args.len() != native.arity {
+        return Err((
+            RuntimeErrorKind::Arity,
+            format!(
+                "Expected {} arguments but got {}.",
+                native.arity,
+                args.len()
+            ),
+        ));
+    }
+    (native.function)(args).map_err(|message| (RuntimeErrorKind::Native, message))
+}
+
+/// Where the import of `path` from the file `importer` points: `./` and
+/// `../` paths are relative to the importing file, other relative paths to
+/// the working directory or else a directory of `search_path`. Without an
+/// extension, `.lox` is tried too. When no file is found, the first place
+/// looked.
+pub fn resolve_import(path: &str, importer: Option<&Path>, search_path: &[PathBuf]) -> PathBuf {
+    let relative = path.starts_with("./") || path.starts_with("../");
+    let candidates: Vec<PathBuf> = match importer.and_then(|file| file.parent()) {
+        Some(dir) if relative => vec![dir.join(path)],
+        _ if relative || Path::new(path).is_absolute() => vec![PathBuf::from(path)],
+        _ => std::iter::once(PathBuf::from(path))
+            .chain(search_path.iter().map(|dir| dir.join(path)))
+            .collect(),
+    };
+    let with_extension = |file: &PathBuf| match file.extension() {
+        Some(_) => None,
+        None => Some(file.with_extension("lox")),
+    };
+    candidates
+        .iter()
+        .flat_map(|file| std::iter::once(file.clone()).chain(with_extension(file)))
+        .find(|file| file.is_file())
+        .unwrap_or_else(|| candidates[0].clone())
+}
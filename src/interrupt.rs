@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Routes SIGINT to a flag polled by the interpreter, so Ctrl-C stops the
+/// running script between statements instead of killing the process.
+pub fn install() {
+    set_handler(handler());
+}
+
+/// Like [`install`] until the guard is dropped, when Ctrl-C goes back to
+/// what it did before, such as ending the process at a REPL prompt.
+pub fn catch() -> Guard {
+    Guard {
+        previous: set_handler(handler()),
+    }
+}
+
+pub struct Guard {
+    previous: usize,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        set_handler(self.previous);
+    }
+}
+
+#[cfg(unix)]
+fn handler() -> usize {
+    extern "C" fn handle(_: i32) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+    handle as extern "C" fn(i32) as usize
+}
+
+/// Installs `handler` for SIGINT and returns the one it replaces.
+#[cfg(unix)]
+fn set_handler(handler: usize) -> usize {
+    const SIGINT: i32 = 2;
+
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+    }
+
+    unsafe { signal(SIGINT, handler) }
+}
+
+#[cfg(not(unix))]
+fn handler() -> usize {
+    0
+}
+
+#[cfg(not(unix))]
+fn set_handler(_: usize) -> usize {
+    0
+}
+
+/// Cheap check for hot loops; does not clear the flag.
+pub fn pending() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Returns whether an interrupt arrived since the last call, clearing it.
+pub fn take() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+/// Stops the running script at its next statement, as Ctrl-C would.
+pub fn raise() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
@@ -0,0 +1,158 @@
+//! A Lox interpreter, following the book
+//! [Crafting Interpreters](https://craftinginterpreters.com/).
+//!
+//! [`Lox`] runs source text on the tree-walking [`Interpreter`] and is all
+//! most embedders need:
+//!
+//! ```
+//! let mut lox = rustyinterpreter::Lox::new();
+//! lox.run("var greeting = \"hi\";").unwrap();
+//! lox.run("print greeting;").unwrap();
+//! ```
+//!
+//! The phases are also available on their own: [`scanner`], [`parser`],
+//! [`interpreter`], and the bytecode [`compiler`] and [`vm`].
+//!
+//! With the `sync` feature, [`Lox`] and [`vm::Vm`] are `Send`, so that each
+//! worker thread can own its own; see [`shared`].
+
+pub mod abi;
+pub mod artifact;
+pub mod chunk;
+pub mod codes;
+pub mod compiler;
+pub mod convert;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
+pub mod deps;
+pub mod diagnostics;
+pub mod doc;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+pub mod grammar;
+pub mod highlight;
+pub mod hook;
+pub mod input;
+pub mod interpreter;
+pub mod interrupt;
+mod json;
+pub mod lsp;
+pub mod metrics;
+pub mod natives;
+pub mod optimizer;
+pub mod output;
+pub mod parser;
+pub mod plugin;
+pub mod prelude;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod repl;
+pub mod replay;
+pub mod scanner;
+pub mod shared;
+pub mod snapshot;
+pub mod stats;
+pub mod trace;
+pub mod transpile;
+pub mod vm;
+
+use std::io::{BufRead, Write};
+
+use diagnostics::{Diagnostic, Diagnostics, Format};
+use grammar::Literal;
+use interpreter::Interpreter;
+use output::Flush;
+use parser::Parser;
+use scanner::Scanner;
+use shared::MaybeSend;
+
+/// A session: each call to [`Lox::run`] sees the globals left by earlier
+/// ones, until [`Lox::reset`].
+#[derive(Default)]
+pub struct Lox {
+    interpreter: Interpreter,
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A session whose `print` writes to `writer`, such as an
+    /// [`output::Capture`], instead of stdout.
+    pub fn with_output(writer: impl Write + MaybeSend + 'static, flush: Flush) -> Self {
+        Lox {
+            interpreter: Interpreter::with_output(writer, flush),
+        }
+    }
+
+    /// Reads input from `reader` instead of stdin.
+    pub fn with_input(mut self, reader: impl BufRead + MaybeSend + 'static) -> Self {
+        self.interpreter = self.interpreter.with_input(reader);
+        self
+    }
+
+    /// Scans, parses and runs `source`. Syntax errors are all reported and
+    /// nothing runs; a runtime error stops the program where it happened.
+    pub fn run(&mut self, source: &str) -> Result<(), Vec<Diagnostic>> {
+        let mut diagnostics = Diagnostics::new(Format::Plain);
+        let tokens = Scanner::new(source).scan_tokens(&mut diagnostics);
+        let statements = Parser::new(&tokens).parse_all();
+        let mut errors: Vec<Diagnostic> = diagnostics.iter().cloned().collect();
+        match statements {
+            Err(parse_errors) => errors.extend(parse_errors),
+            Ok(_) if !errors.is_empty() => {}
+            Ok(statements) => {
+                return self
+                    .interpreter
+                    .interpret(statements)
+                    .map_err(|err| vec![err.into()])
+            }
+        }
+        Err(errors)
+    }
+
+    /// Evaluates the single expression in `source` and returns its value.
+    /// `variables` are visible to this expression only, on top of the
+    /// session's globals:
+    ///
+    /// ```
+    /// # use rustyinterpreter::{grammar::Literal, Lox};
+    /// let mut lox = Lox::new();
+    /// let total = lox.evaluate("price * (1 + rate)", [("price", 10.0), ("rate", 0.5)]);
+    /// assert_eq!(total.unwrap(), Literal::Number(15.0));
+    /// ```
+    pub fn evaluate<K: Into<String>, V: Into<Literal>>(
+        &mut self,
+        source: &str,
+        variables: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<Literal, Vec<Diagnostic>> {
+        let mut diagnostics = Diagnostics::new(Format::Plain);
+        let tokens = Scanner::new(source).scan_tokens(&mut diagnostics);
+        if diagnostics.has_errors() {
+            return Err(diagnostics.iter().cloned().collect());
+        }
+        let expression = Parser::new(&tokens)
+            .complete_expression()
+            .map_err(|diagnostic| vec![diagnostic])?;
+        let variables = variables
+            .into_iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        self.interpreter
+            .evaluate_with(&expression, variables)
+            .map_err(|err| vec![err.into()])
+    }
+
+    /// Starts the session over: globals defined by scripts are forgotten,
+    /// while those defined through [`Lox::interpreter`] stay.
+    pub fn reset(&mut self) {
+        self.interpreter.reset();
+    }
+
+    /// The interpreter behind the session, to define globals or set limits.
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+}
@@ -0,0 +1,621 @@
+//! A language server speaking the Language Server Protocol over stdio, for
+//! editors. Documents are analysed on every change: the scanner and parser
+//! report diagnostics, and a walk of the syntax tree resolves each variable
+//! to its declaration for go-to-definition, hover and document symbols.
+//!
+//! Lox has no functions yet, so the symbols are the variables, constants,
+//! `catch` bindings and imports of a document.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::diagnostics::{Diagnostic, Diagnostics, Format};
+use crate::grammar::*;
+use crate::json::{self, Value};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_REQUEST: i32 = -32600;
+
+/// Serves one client until it sends `exit` or closes the input. Gives the
+/// exit status the protocol asks for: 0 after a `shutdown` request, 1
+/// otherwise.
+///
+/// ```
+/// # use rustyinterpreter::lsp::Server;
+/// let request = r#"{"jsonrpc":"2.0","id":1,"method":"shutdown"}"#;
+/// let input = format!("Content-Length: {}\r\n\r\n{request}", request.len());
+/// let mut output = vec![];
+/// let status = Server::new().serve(input.as_bytes(), &mut output).unwrap();
+/// assert_eq!(status, 0);
+/// assert!(String::from_utf8(output).unwrap().ends_with(r#"{"jsonrpc":"2.0","id":1,"result":null}"#));
+/// ```
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    optional_semicolons: bool,
+    print_keyword: bool,
+    shutdown: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Server {
+            print_keyword: true,
+            ..Self::default()
+        }
+    }
+
+    /// Analyses documents as if they had the optional-semicolons pragma.
+    pub fn with_optional_semicolons(mut self, optional: bool) -> Self {
+        self.optional_semicolons = optional;
+        self
+    }
+
+    pub fn with_print_keyword(mut self, keyword: bool) -> Self {
+        self.print_keyword = keyword;
+        self
+    }
+
+    pub fn serve(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+        while let Some(body) = read_message(&mut input)? {
+            let Some(message) = json::parse(&body) else {
+                send(
+                    &mut output,
+                    &error(Value::Null, INVALID_REQUEST, "invalid JSON"),
+                )?;
+                continue;
+            };
+            let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+            if method == "exit" {
+                break;
+            }
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+            match message.get("id") {
+                Some(id) => {
+                    let response = match self.request(method, &params) {
+                        Some(result) => Value::object([
+                            ("jsonrpc", "2.0".into()),
+                            ("id", id.clone()),
+                            ("result", result),
+                        ]),
+                        None => error(id.clone(), METHOD_NOT_FOUND, "unknown method"),
+                    };
+                    send(&mut output, &response)?;
+                }
+                None => {
+                    for notification in self.notify(method, &params) {
+                        send(&mut output, &notification)?;
+                    }
+                }
+            }
+        }
+        Ok(if self.shutdown { 0 } else { 1 })
+    }
+
+    /// The result of a request, or `None` for a method the server lacks.
+    fn request(&mut self, method: &str, params: &Value) -> Option<Value> {
+        let result = match method {
+            "initialize" => Value::object([
+                (
+                    "capabilities",
+                    Value::object([
+                        ("textDocumentSync", 1.into()),
+                        ("definitionProvider", true.into()),
+                        ("hoverProvider", true.into()),
+                        ("documentSymbolProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Value::object([
+                        ("name", env!("CARGO_PKG_NAME").into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ]),
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+            "textDocument/definition" => {
+                let (uri, document, offset) = self.position(params)?;
+                document
+                    .declaration_at(offset)
+                    .map_or(Value::Null, |symbol| {
+                        Value::object([("uri", uri.into()), ("range", document.range(symbol.span))])
+                    })
+            }
+            "textDocument/hover" => {
+                let (_, document, offset) = self.position(params)?;
+                document.hover(offset).map_or(Value::Null, |(span, text)| {
+                    Value::object([
+                        (
+                            "contents",
+                            Value::object([("kind", "markdown".into()), ("value", text.into())]),
+                        ),
+                        ("range", document.range(span)),
+                    ])
+                })
+            }
+            "textDocument/documentSymbol" => {
+                let document = params
+                    .at(&["textDocument", "uri"])
+                    .and_then(Value::as_str)
+                    .and_then(|uri| self.documents.get(uri))?;
+                Value::Array(
+                    document
+                        .symbols
+                        .iter()
+                        .map(|symbol| {
+                            let range = document.range(symbol.span);
+                            Value::object([
+                                ("name", symbol.name.as_str().into()),
+                                ("kind", symbol.kind.code().into()),
+                                ("range", range.clone()),
+                                ("selectionRange", range),
+                            ])
+                        })
+                        .collect(),
+                )
+            }
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// Handles a notification, giving the ones to send back.
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let Some(uri) = params
+            .at(&["textDocument", "uri"])
+            .and_then(Value::as_str)
+            .map(str::to_string)
+        else {
+            return vec![];
+        };
+        let text = match method {
+            "textDocument/didOpen" => params.at(&["textDocument", "text"]),
+            // The server asks for full sync, so the last change is the text.
+            "textDocument/didChange" => match params.get("contentChanges") {
+                Some(Value::Array(changes)) => changes.last().and_then(|change| change.get("text")),
+                _ => None,
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish(&uri, vec![])];
+            }
+            _ => return vec![],
+        };
+        let Some(text) = text.and_then(Value::as_str) else {
+            return vec![];
+        };
+        let document = Document::analyse(
+            text.to_string(),
+            self.optional_semicolons,
+            self.print_keyword,
+        );
+        let diagnostics = document
+            .diagnostics
+            .iter()
+            .map(|diagnostic| document.diagnostic(diagnostic))
+            .collect();
+        self.documents.insert(uri.clone(), document);
+        vec![publish(&uri, diagnostics)]
+    }
+
+    /// The document and byte offset a `TextDocumentPositionParams` names.
+    fn position<'a>(&'a self, params: &'a Value) -> Option<(&'a str, &'a Document, usize)> {
+        let uri = params.at(&["textDocument", "uri"])?.as_str()?;
+        let document = self.documents.get(uri)?;
+        let line = params.at(&["position", "line"])?.as_usize()?;
+        let character = params.at(&["position", "character"])?.as_usize()?;
+        Some((uri, document, document.offset(line, character)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Module,
+    Variable,
+    Constant,
+}
+
+impl Kind {
+    /// The `SymbolKind` of the protocol.
+    fn code(self) -> usize {
+        match self {
+            Kind::Module => 2,
+            Kind::Variable => 13,
+            Kind::Constant => 14,
+        }
+    }
+}
+
+struct Symbol {
+    name: String,
+    kind: Kind,
+    /// Where the name is declared.
+    span: Span,
+    /// The initialiser, when it is a literal.
+    value: Option<Literal>,
+    /// The `///` comment above the declaration.
+    doc: Option<String>,
+}
+
+struct Document {
+    text: String,
+    tokens: Vec<Token>,
+    diagnostics: Vec<Diagnostic>,
+    symbols: Vec<Symbol>,
+    /// Each use of a name and the symbol it resolves to.
+    references: Vec<(Span, usize)>,
+}
+
+impl Document {
+    fn analyse(text: String, optional_semicolons: bool, print_keyword: bool) -> Self {
+        let mut diagnostics = Diagnostics::new(Format::Plain);
+        let tokens = Scanner::new(&text)
+            .with_optional_semicolons(optional_semicolons)
+            .with_print_keyword(print_keyword)
+            .scan_tokens(&mut diagnostics);
+        let mut diagnostics: Vec<Diagnostic> = diagnostics.iter().cloned().collect();
+        let mut resolver = Resolver {
+            tokens: &tokens,
+            symbols: vec![],
+            references: vec![],
+            scopes: vec![HashMap::new()],
+            unresolved: vec![],
+        };
+        match Parser::new(&tokens).parse_all() {
+            Ok(statements) => resolver.statements(&statements),
+            Err(errors) => diagnostics.extend(errors),
+        }
+        resolver.finish();
+        let Resolver {
+            symbols,
+            references,
+            ..
+        } = resolver;
+        Document {
+            text,
+            tokens,
+            diagnostics,
+            symbols,
+            references,
+        }
+    }
+
+    /// The symbol declared or used at `offset`.
+    fn declaration_at(&self, offset: usize) -> Option<&Symbol> {
+        let contains = |span: &Span| span.start <= offset && offset < span.end;
+        self.symbols
+            .iter()
+            .find(|symbol| contains(&symbol.span))
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|(span, _)| contains(span))
+                    .map(|&(_, symbol)| &self.symbols[symbol])
+            })
+    }
+
+    fn hover(&self, offset: usize) -> Option<(Span, String)> {
+        let token = self
+            .tokens
+            .iter()
+            .find(|token| token.span.start <= offset && offset < token.span.end)?;
+        let value = match token.token_type {
+            TokenType::NUMBER | TokenType::STRING => token.literal.clone(),
+            TokenType::TRUE => Some(Literal::Boolean(true)),
+            TokenType::FALSE => Some(Literal::Boolean(false)),
+            TokenType::NIL => Some(Literal::Nil),
+            _ => None,
+        };
+        if let Some(value) = value {
+            return Some((token.span, describe(&value)));
+        }
+        let symbol = self.declaration_at(offset)?;
+        let keyword = match symbol.kind {
+            Kind::Module => "import",
+            Kind::Variable => "var",
+            Kind::Constant => "const",
+        };
+        let mut text = format!("```lox\n{keyword} {}\n```", symbol.name);
+        if let Some(value) = &symbol.value {
+            text.push_str(&format!("\n\n{}", describe(value)));
+        }
+        if let Some(doc) = &symbol.doc {
+            text.push_str(&format!("\n\n{doc}"));
+        }
+        Some((token.span, text))
+    }
+
+    fn diagnostic(&self, diagnostic: &Diagnostic) -> Value {
+        let mut message = diagnostic.message.clone();
+        for note in &diagnostic.notes {
+            message.push_str(&format!("\n{note}"));
+        }
+        Value::object([
+            ("range", self.range(diagnostic.span)),
+            ("severity", 1.into()),
+            ("code", diagnostic.code.as_str().into()),
+            ("source", "lox".into()),
+            ("message", message.into()),
+        ])
+    }
+
+    fn range(&self, span: Span) -> Value {
+        Value::object([
+            ("start", self.position(span.start)),
+            ("end", self.position(span.end.max(span.start))),
+        ])
+    }
+
+    /// The protocol's position of a byte offset: a line from 0, and a
+    /// column in UTF-16 code units.
+    fn position(&self, offset: usize) -> Value {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &self.text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Value::object([
+            ("line", before.matches('\n').count().into()),
+            (
+                "character",
+                before[line_start..].encode_utf16().count().into(),
+            ),
+        ])
+    }
+
+    /// The byte offset of a protocol position, clamped to the line.
+    fn offset(&self, line: usize, character: usize) -> usize {
+        let line_start = self
+            .text
+            .split_inclusive('\n')
+            .take(line)
+            .map(str::len)
+            .sum::<usize>();
+        let mut units = 0;
+        for (i, c) in self.text[line_start..].char_indices() {
+            if units >= character || c == '\n' {
+                return line_start + i;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+}
+
+fn describe(value: &Literal) -> String {
+    let shown = match value {
+        Literal::String(s) => json::string(s),
+        value => value.to_string(),
+    };
+    format!("{} `{shown}`", value.type_name())
+}
+
+/// Resolves names the way the interpreter does: to the innermost enclosing
+/// declaration, and otherwise to a global declared anywhere in the document,
+/// since globals are looked up when the code runs.
+struct Resolver<'a> {
+    tokens: &'a [Token],
+    symbols: Vec<Symbol>,
+    references: Vec<(Span, usize)>,
+    scopes: Vec<HashMap<String, usize>>,
+    /// Uses with no declaration in scope yet, for [`Resolver::finish`].
+    unresolved: Vec<(Span, String)>,
+}
+
+impl Resolver<'_> {
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.scopes.push(HashMap::new());
+        self.statements(statements);
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression { expr, .. } | Statement::Print { expr, .. } => {
+                self.expression(expr)
+            }
+            Statement::Defer { expr, .. } => self.expression(expr),
+            Statement::Throw { value, .. } => self.expression(value),
+            Statement::Variable {
+                name,
+                init,
+                constant,
+                doc,
+            } => {
+                if let Some(init) = init {
+                    self.expression(init);
+                }
+                let value = match init {
+                    Some(Expression::Literal(value)) => Some(value.clone()),
+                    None => Some(Literal::Nil),
+                    Some(_) => None,
+                };
+                let kind = if *constant {
+                    Kind::Constant
+                } else {
+                    Kind::Variable
+                };
+                self.declare(&name.lexeme, kind, name.span, value, doc.clone());
+            }
+            Statement::Block { statements, .. } => self.block(statements),
+            Statement::Import { name, line, .. } => {
+                // The statement keeps no tokens: point at the alias after
+                // `as`, or else at the path.
+                let mut on_line = self.tokens.iter().filter(|token| token.line_num == *line);
+                let span = on_line
+                    .clone()
+                    .find(|token| {
+                        token.token_type == TokenType::IDENTIFIER && token.lexeme == *name
+                    })
+                    .or_else(|| on_line.find(|token| token.token_type == TokenType::STRING))
+                    .map_or(Span::default(), |token| token.span);
+                self.declare(name, Kind::Module, span, None, None);
+            }
+            Statement::Try {
+                body,
+                catches,
+                finally,
+                ..
+            } => {
+                self.block(body);
+                for catch in catches {
+                    self.scopes.push(HashMap::new());
+                    self.declare(
+                        &catch.name.lexeme,
+                        Kind::Variable,
+                        catch.name.span,
+                        None,
+                        None,
+                    );
+                    self.statements(&catch.body);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Literal(_) => {}
+            Expression::Group(expr) | Expression::Unary { expr, .. } => self.expression(expr),
+            Expression::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Variable(name) => self.reference(name),
+            Expression::Assign { name, right } => {
+                self.expression(right);
+                self.reference(name);
+            }
+            Expression::Index { object, index, .. } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expression::Call { callee, args, .. } => {
+                self.expression(callee);
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+        }
+    }
+
+    fn declare(
+        &mut self,
+        name: &str,
+        kind: Kind,
+        span: Span,
+        value: Option<Literal>,
+        doc: Option<String>,
+    ) {
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            span,
+            value,
+            doc,
+        });
+        let index = self.symbols.len() - 1;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), index);
+        }
+    }
+
+    fn reference(&mut self, name: &Token) {
+        match self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+        {
+            Some(&symbol) => self.references.push((name.span, symbol)),
+            None => self.unresolved.push((name.span, name.lexeme.clone())),
+        }
+    }
+
+    /// Resolves the remaining uses against the globals declared later on.
+    fn finish(&mut self) {
+        for (span, name) in std::mem::take(&mut self.unresolved) {
+            if let Some(&symbol) = self.scopes[0].get(&name) {
+                self.references.push((span, symbol));
+            }
+        }
+    }
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    Value::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Value::object([
+                ("uri", uri.into()),
+                ("diagnostics", Value::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn error(id: Value, code: i32, message: &str) -> Value {
+    Value::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Value::object([
+                ("code", Value::Number(code.into())),
+                ("message", message.into()),
+            ]),
+        ),
+    ])
+}
+
+/// Reads the body of the next message, or `None` at the end of the input.
+/// The debug adapter frames its messages the same way.
+pub(crate) fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+pub(crate) fn send(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
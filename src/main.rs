@@ -1,123 +1,1230 @@
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::{Duration, Instant};
 
-mod grammar;
-mod interpreter;
-mod parser;
-mod scanner;
+mod bench;
+mod config;
 
-use grammar::*;
-use interpreter::Interpreter;
-use parser::Parser;
-use scanner::Scanner;
+use config::{ConfigError, Setting};
+use rustyinterpreter::chunk::Chunk;
+use rustyinterpreter::codes::Code;
+use rustyinterpreter::compiler::Compiler;
+use rustyinterpreter::coverage::{self, FileCoverage};
+use rustyinterpreter::dap::Adapter;
+use rustyinterpreter::debugger::Debugger;
+use rustyinterpreter::diagnostics::{Diagnostic, Diagnostics, Format};
+use rustyinterpreter::grammar::*;
+use rustyinterpreter::interpreter::{
+    resolve_import, shown, Interpreter, Limits, RuntimeError, RuntimeErrorKind,
+};
+use rustyinterpreter::lsp::Server;
+use rustyinterpreter::natives::Sandbox;
+use rustyinterpreter::parser::Parser;
+use rustyinterpreter::repl::Repl;
+use rustyinterpreter::replay::Log;
+use rustyinterpreter::scanner::Scanner;
+use rustyinterpreter::stats::CodeStats;
+use rustyinterpreter::trace::{self, Tracer};
+use rustyinterpreter::transpile::Transpiler;
+use rustyinterpreter::vm::Vm;
+use rustyinterpreter::{artifact, deps, doc, highlight, interrupt, optimizer, plugin, prelude};
 
-fn tokenize(input: &str) {
-    let mut scanner = Scanner::new(input);
-    let tokens = scanner.scan_tokens();
+#[derive(PartialEq)]
+enum Backend {
+    TreeWalk,
+    Vm,
+}
+
+struct Options {
+    diagnostics: Format,
+    backend: Backend,
+    cache: bool,
+    optimize: bool,
+    stats: bool,
+    output: Option<String>,
+    /// Program text given with `-e`, used instead of a file.
+    inline: Option<String>,
+    /// Further `.lox` files named right after the first, run in order in the
+    /// same session.
+    files: Vec<String>,
+    /// Everything after the file names, exposed to the script as `ARGS`.
+    args: Vec<String>,
+    iterations: usize,
+    warmup: usize,
+    prompt: Option<String>,
+    limits: Limits,
+    /// `None` stops after the first phase with errors.
+    max_errors: Option<usize>,
+    /// Set by `--trace` or `--trace-format=`.
+    trace: Option<trace::Format>,
+    coverage: bool,
+    /// Whether `run` stops at the debugger's prompt.
+    debug: bool,
+    output_format: OutputFormat,
+    /// How `highlight` marks up the source, also set by `--format=`; `doc`
+    /// writes HTML for `Html` and Markdown otherwise.
+    highlight: highlight::Style,
+    /// How `deps` prints the import graph, also set by `--format=`.
+    graph: deps::Style,
+    /// Which values count as false.
+    compat: Compat,
+    /// Whether dividing by zero is a runtime error.
+    checked_division: bool,
+    /// Whether assigning to an undefined variable defines a global.
+    implicit_globals: bool,
+    /// Whether line ends can stand in for semicolons.
+    optional_semicolons: bool,
+    /// Whether `print` is the statement keyword, rather than only a native.
+    print_keyword: bool,
+    sandbox: Sandbox,
+    /// Seed for the random natives; the clock when unset.
+    seed: Option<u64>,
+    /// Set by `--record=` or `--replay=`.
+    log: Option<Log>,
+    /// Shared libraries named by `--plugin`.
+    plugins: Vec<String>,
+    /// The natives the plugins define, once loaded.
+    natives: Vec<(String, Literal)>,
+    /// Whether scripts start with the globals of the prelude.
+    prelude: bool,
+    /// Directories searched for bare imports: those from `--lox-path=`, then
+    /// those from `LOX_PATH`.
+    lox_path: Vec<PathBuf>,
+    /// The script commands run when given no file, from the manifest.
+    entry: Option<String>,
+}
+
+/// Options start from their defaults, then take settings from the config
+/// files, then from `RUSTYINT_*` environment variables, then from the
+/// command line.
+fn parse_args(args: &[String], settings: &[Setting]) -> (Options, Vec<String>) {
+    let mut options = Options {
+        diagnostics: Format::detect(),
+        backend: Backend::TreeWalk,
+        cache: false,
+        optimize: false,
+        stats: false,
+        output: None,
+        inline: None,
+        files: vec![],
+        args: vec![],
+        iterations: 10,
+        warmup: 3,
+        prompt: None,
+        limits: Limits::default(),
+        max_errors: None,
+        trace: None,
+        coverage: false,
+        debug: false,
+        output_format: OutputFormat::default(),
+        highlight: highlight::Style::default(),
+        graph: deps::Style::default(),
+        compat: Compat::default(),
+        checked_division: false,
+        implicit_globals: false,
+        optional_semicolons: false,
+        print_keyword: true,
+        sandbox: Sandbox::default(),
+        seed: None,
+        log: None,
+        plugins: vec![],
+        natives: vec![],
+        prelude: true,
+        lox_path: vec![],
+        entry: None,
+    };
+    if let Err(err) = apply_config(&mut options, settings) {
+        eprintln!("{}", err);
+        exit(78);
+    }
+    if let Err(message) = apply_env(&mut options) {
+        eprintln!("{}", message);
+        exit(78);
+    }
+    let mut positional = vec![];
+    let mut lox_path = vec![];
+    let mut more_files = true;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // Program, command and file name; `-e` takes the file name's place.
+        let script_start = if options.inline.is_some() { 2 } else { 3 };
+        if positional.len() >= script_start {
+            if more_files && script_start == 3 && arg.ends_with(".lox") {
+                options.files.push(arg.clone());
+                continue;
+            }
+            // `--` ends the list of files without being passed on.
+            if std::mem::take(&mut more_files) && arg == "--" {
+                continue;
+            }
+            options.args.push(arg.clone());
+        } else if let Some(value) = arg.strip_prefix("--diagnostics=") {
+            options.diagnostics = Format::from_flag(value).unwrap_or_else(|| {
+                eprintln!("Unknown diagnostics format: {}", value);
+                exit(64);
+            });
+        } else if let Some(value) = arg.strip_prefix("--format=") {
+            if let Some(style) = highlight::Style::from_flag(value) {
+                options.highlight = style;
+            } else if let Some(style) = deps::Style::from_flag(value) {
+                options.graph = style;
+            } else {
+                options.output_format = OutputFormat::from_flag(value).unwrap_or_else(|| {
+                    eprintln!("Unknown output format: {}", value);
+                    exit(64);
+                });
+            }
+        } else if let Some(value) = arg.strip_prefix("--compat=") {
+            options.compat = Compat::from_flag(value).unwrap_or_else(|| {
+                eprintln!("Unknown compatibility mode: {}", value);
+                exit(64);
+            });
+        } else if let Some(value) = arg.strip_prefix("--sandbox=") {
+            options.sandbox = Sandbox::from_flag(value).unwrap_or_else(|| {
+                eprintln!("Unknown sandbox: {}", value);
+                exit(64);
+            });
+        } else if let Some(value) = arg.strip_prefix("--seed=") {
+            options.seed = Some(value.parse().unwrap_or_else(|_| {
+                eprintln!("Invalid value for --seed: {}", value);
+                exit(64);
+            }));
+        } else if let Some(path) = arg.strip_prefix("--record=") {
+            options.log = Some(Log::record(path).unwrap_or_else(|err| {
+                eprintln!("Failed to create {}: {}", path, err);
+                exit(73);
+            }));
+        } else if let Some(path) = arg.strip_prefix("--replay=") {
+            options.log = Some(Log::replay(path).unwrap_or_else(|err| {
+                eprintln!("Failed to read {}: {}", path, err);
+                exit(66);
+            }));
+        } else if arg == "-o" {
+            options.output = Some(args.next().cloned().unwrap_or_else(|| {
+                eprintln!("Missing path after -o");
+                exit(64);
+            }));
+        } else if let Some(value) = arg.strip_prefix("--iterations=") {
+            options.iterations = count_flag("--iterations", value);
+        } else if let Some(value) = arg.strip_prefix("--warmup=") {
+            options.warmup = count_flag("--warmup", value);
+        } else if arg == "--plugin" {
+            options
+                .plugins
+                .push(args.next().cloned().unwrap_or_else(|| {
+                    eprintln!("Missing path after --plugin");
+                    exit(64);
+                }));
+        } else if arg == "-e" {
+            options.inline = Some(args.next().cloned().unwrap_or_else(|| {
+                eprintln!("Missing program after -e");
+                exit(64);
+            }));
+        } else if arg == "--no-fail-fast" {
+            options.max_errors = options.max_errors.or(Some(DEFAULT_MAX_ERRORS));
+        } else if let Some(value) = arg.strip_prefix("--max-errors=") {
+            options.max_errors = Some(count_flag("--max-errors", value));
+        } else if arg == "--trace" {
+            options.trace = options.trace.or(Some(trace::Format::Text));
+        } else if let Some(value) = arg.strip_prefix("--trace-format=") {
+            options.trace = Some(trace::Format::from_flag(value).unwrap_or_else(|| {
+                eprintln!("Unknown trace format: {}", value);
+                exit(64);
+            }));
+        } else if let Some(value) = arg.strip_prefix("--lox-path=") {
+            lox_path.extend(env::split_paths(value));
+        } else if arg == "--checked-division" {
+            options.checked_division = true;
+        } else if arg == "--implicit-globals" {
+            options.implicit_globals = true;
+        } else if arg == "--optional-semicolons" {
+            options.optional_semicolons = true;
+        } else if let Some(value) = arg.strip_prefix("--print=") {
+            options.print_keyword = print_flag(value).unwrap_or_else(|| {
+                eprintln!("Unknown print mode: {}", value);
+                exit(64);
+            });
+        } else if arg == "--no-prelude" {
+            options.prelude = false;
+        } else if arg == "--coverage" {
+            options.coverage = true;
+        } else if arg == "--debug" {
+            options.debug = true;
+        } else if arg == "--stats" {
+            options.stats = true;
+        } else if arg == "-O" {
+            options.optimize = true;
+        } else if arg == "--cache" {
+            options.cache = true;
+            options.backend = Backend::Vm;
+        } else if let Some(value) = arg.strip_prefix("--backend=") {
+            options.backend = match value {
+                "tree" => Backend::TreeWalk,
+                "vm" => Backend::Vm,
+                _ => {
+                    eprintln!("Unknown backend: {}", value);
+                    exit(64);
+                }
+            };
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+    options.lox_path.splice(0..0, lox_path);
+    (options, positional)
+}
+
+fn apply_config(options: &mut Options, settings: &[Setting]) -> Result<(), ConfigError> {
+    use config::Value;
+    for setting in settings {
+        let mismatch = || {
+            ConfigError::at(
+                setting,
+                format!("`{}` cannot be {}", setting.key, setting.value.type_name()),
+            )
+        };
+        // Paths in a file are relative to its directory.
+        let path = |path: &str| {
+            let dir = setting.file.parent().unwrap_or(Path::new(""));
+            dir.join(path)
+        };
+        let paths = |values: &[Value]| {
+            values
+                .iter()
+                .map(|value| match value {
+                    Value::String(value) => Ok(path(value)),
+                    _ => Err(ConfigError::at(
+                        setting,
+                        format!("`{}` must list strings", setting.key),
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let count = |n: i64| {
+            usize::try_from(n)
+                .map_err(|_| ConfigError::at(setting, format!("`{}` must be >= 0", setting.key)))
+        };
+        match (setting.key.as_str(), &setting.value) {
+            ("backend", Value::String(value)) => {
+                options.backend = match value.as_str() {
+                    "tree" => Backend::TreeWalk,
+                    "vm" => Backend::Vm,
+                    _ => {
+                        return Err(ConfigError::at(
+                            setting,
+                            format!("unknown backend `{value}`"),
+                        ))
+                    }
+                }
+            }
+            ("diagnostics", Value::String(value)) => {
+                options.diagnostics = Format::from_flag(value).ok_or_else(|| {
+                    ConfigError::at(setting, format!("unknown diagnostics format `{value}`"))
+                })?
+            }
+            ("format", Value::String(value)) => {
+                options.output_format = OutputFormat::from_flag(value).ok_or_else(|| {
+                    ConfigError::at(setting, format!("unknown output format `{value}`"))
+                })?
+            }
+            ("compat", Value::String(value)) => {
+                options.compat = Compat::from_flag(value).ok_or_else(|| {
+                    ConfigError::at(setting, format!("unknown compatibility mode `{value}`"))
+                })?
+            }
+            ("sandbox", Value::String(value)) => {
+                options.sandbox = Sandbox::from_flag(value)
+                    .ok_or_else(|| ConfigError::at(setting, format!("unknown sandbox `{value}`")))?
+            }
+            ("seed", Value::Integer(n)) => options.seed = Some(count(*n)? as u64),
+            ("optimize", Value::Boolean(value)) => options.optimize = *value,
+            ("cache", Value::Boolean(value)) => {
+                options.cache = *value;
+                if *value {
+                    options.backend = Backend::Vm;
+                }
+            }
+            ("stats", Value::Boolean(value)) => options.stats = *value,
+            ("prelude", Value::Boolean(value)) => options.prelude = *value,
+            ("checked_division", Value::Boolean(value)) => options.checked_division = *value,
+            ("implicit_globals", Value::Boolean(value)) => options.implicit_globals = *value,
+            ("optional_semicolons", Value::Boolean(value)) => options.optional_semicolons = *value,
+            ("print", Value::String(value)) => {
+                options.print_keyword = print_flag(value).ok_or_else(|| {
+                    ConfigError::at(setting, format!("unknown print mode `{value}`"))
+                })?;
+            }
+            ("bench.iterations", Value::Integer(n)) => options.iterations = count(*n)?,
+            ("bench.warmup", Value::Integer(n)) => options.warmup = count(*n)?,
+            ("repl.prompt", Value::String(value)) => options.prompt = Some(value.clone()),
+            ("limits.max_depth", Value::Integer(n)) => options.limits.max_depth = Some(count(*n)?),
+            ("limits.timeout_ms", Value::Integer(n)) => {
+                options.limits.timeout = Some(Duration::from_millis(count(*n)? as u64))
+            }
+            ("project.entry", Value::String(value)) => {
+                options.entry = Some(path(value).display().to_string())
+            }
+            ("project.lox_path", Value::Array(values)) => options.lox_path.extend(paths(values)?),
+            ("project.plugins", Value::Array(values)) => options
+                .plugins
+                .extend(paths(values)?.iter().map(|path| path.display().to_string())),
+            (
+                "backend"
+                | "diagnostics"
+                | "format"
+                | "compat"
+                | "sandbox"
+                | "seed"
+                | "optimize"
+                | "cache"
+                | "stats"
+                | "prelude"
+                | "checked_division"
+                | "implicit_globals"
+                | "print"
+                | "optional_semicolons"
+                | "bench.iterations"
+                | "bench.warmup"
+                | "repl.prompt"
+                | "limits.max_depth"
+                | "limits.timeout_ms"
+                | "project.entry"
+                | "project.lox_path"
+                | "project.plugins",
+                _,
+            ) => return Err(mismatch()),
+            // Such as `name` and `version`, which describe the project for
+            // other tools.
+            (key, _) if key.starts_with("project.") => {}
+            (key, _) => return Err(ConfigError::at(setting, format!("unknown setting `{key}`"))),
+        }
+    }
+    Ok(())
+}
+
+fn apply_env(options: &mut Options) -> Result<(), String> {
+    let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+    let invalid = |name: &str, value: &str| format!("{}: invalid value `{}`", name, value);
+    if let Some(value) = var("RUSTYINT_BACKEND") {
+        options.backend = match value.as_str() {
+            "tree" => Backend::TreeWalk,
+            "vm" => Backend::Vm,
+            _ => return Err(invalid("RUSTYINT_BACKEND", &value)),
+        };
+    }
+    if let Some(value) = var("RUSTYINT_MAX_DEPTH") {
+        let depth = value
+            .parse()
+            .map_err(|_| invalid("RUSTYINT_MAX_DEPTH", &value))?;
+        options.limits.max_depth = Some(depth);
+    }
+    if let Some(value) = var("RUSTYINT_TIMEOUT_MS") {
+        let ms = value
+            .parse()
+            .map_err(|_| invalid("RUSTYINT_TIMEOUT_MS", &value))?;
+        options.limits.timeout = Some(Duration::from_millis(ms));
+    }
+    if let Some(value) = env::var_os("LOX_PATH") {
+        options.lox_path.extend(env::split_paths(&value));
+    }
+    Ok(())
+}
+
+/// What every program starts with: `ARGS`, and the natives from plugins.
+fn globals(options: &Options) -> Vec<(String, Literal)> {
+    let mut globals = if options.prelude {
+        prelude::globals()
+    } else {
+        vec![]
+    };
+    globals.push(("ARGS".to_string(), Literal::from(options.args.clone())));
+    globals.extend(options.natives.iter().cloned());
+    globals
+}
+
+/// Plugins are native code the sandbox cannot restrict, and loading one
+/// already runs it, so they are refused under any sandbox.
+fn load_plugins(options: &mut Options) {
+    if !options.plugins.is_empty() && options.sandbox != Sandbox::None {
+        eprintln!("Plugins cannot be loaded under --sandbox.");
+        exit(64);
+    }
+    for path in &options.plugins {
+        match plugin::load(path) {
+            Ok(natives) => options.natives.extend(natives),
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(66);
+            }
+        }
+    }
+}
+
+/// Parses the value of `--print=`: whether `print` stays a keyword.
+fn print_flag(value: &str) -> Option<bool> {
+    match value {
+        "statement" => Some(true),
+        "function" => Some(false),
+        _ => None,
+    }
+}
+
+fn count_flag(flag: &str, value: &str) -> usize {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid value for {}: {}", flag, value);
+        exit(64);
+    })
+}
+
+fn tokenize(filename: &str, input: &str, options: &Options) {
+    let mut diagnostics = new_diagnostics(options);
+    let mut scanner = Scanner::new(input)
+        .with_optional_semicolons(options.optional_semicolons)
+        .with_print_keyword(options.print_keyword);
+    let tokens = scanner.scan_tokens(&mut diagnostics);
     for token in tokens {
         println!("{}", token);
     }
-    if scanner.error {
+    diagnostics.emit(filename, input);
+    if diagnostics.has_errors() {
         exit(65);
     }
 }
 
-fn parse(input: &str) {
-    let mut scanner = Scanner::new(input);
-    let tokens = scanner.scan_tokens();
-    if scanner.error {
+fn highlight(filename: &str, input: &str, options: &Options) {
+    let mut diagnostics = new_diagnostics(options);
+    let mut scanner = Scanner::new(input)
+        .with_optional_semicolons(options.optional_semicolons)
+        .with_print_keyword(options.print_keyword);
+    let tokens = scanner.scan_tokens(&mut diagnostics);
+    print!(
+        "{}",
+        highlight::highlight(input, &tokens, options.highlight)
+    );
+    diagnostics.emit(filename, input);
+    if diagnostics.has_errors() {
         exit(65);
     }
+}
 
-    let mut parser = Parser::new(&tokens);
-    match parser.expression() {
-        Ok(expression) => println!("{expression}"),
-        Err(msg) => {
-            eprintln!("{}", msg);
-            exit(65);
+/// A file read for `doc` or `deps`, with where each of its imports, at any
+/// depth, resolved to.
+struct Loaded {
+    file: PathBuf,
+    statements: Vec<Statement>,
+    imports: Vec<LoadedImport>,
+}
+
+struct LoadedImport {
+    path: String,
+    resolved: PathBuf,
+    /// Where the imported file is among those loaded, if it could be read.
+    module: Option<usize>,
+}
+
+/// Parses the script and, following their imports, the modules it uses,
+/// each once, in the order they are first imported. Imports of files that
+/// cannot be read are recorded without being followed.
+fn load_modules(filename: &str, input: &str, options: &Options) -> Vec<Loaded> {
+    let mut diagnostics = new_diagnostics(options);
+    let mut sources = vec![(PathBuf::from(filename), input.to_string())];
+    let mut files = vec![fs::canonicalize(filename).unwrap_or_else(|_| filename.into())];
+    let mut modules: Vec<Loaded> = vec![];
+    while let Some((file, source)) = sources.get(modules.len()).cloned() {
+        let statements = parse_program(
+            &file.display().to_string(),
+            &source,
+            &mut diagnostics,
+            options,
+        );
+        let mut imports = vec![];
+        for path in deps::imports(&statements) {
+            let resolved = resolve_import(path, Some(&file), &options.lox_path);
+            let canonical = fs::canonicalize(&resolved).unwrap_or_else(|_| resolved.clone());
+            let module = match files.iter().position(|file| *file == canonical) {
+                Some(n) => Some(n),
+                None => match fs::read_to_string(&resolved) {
+                    Ok(source) => {
+                        files.push(canonical);
+                        sources.push((resolved.components().collect(), source));
+                        Some(files.len() - 1)
+                    }
+                    Err(_) => None,
+                },
+            };
+            imports.push(LoadedImport {
+                path: path.to_string(),
+                resolved: resolved.components().collect(),
+                module,
+            });
         }
+        modules.push(Loaded {
+            file,
+            statements,
+            imports,
+        });
     }
+    modules
 }
 
-fn evaluate(input: &str) {
-    let mut scanner = Scanner::new(input);
-    let tokens = scanner.scan_tokens();
-    if scanner.error {
+/// Documents the script and the modules it uses.
+fn doc(filename: &str, input: &str, options: &Options) {
+    let loaded = load_modules(filename, input, options);
+    let mut modules = vec![];
+    for module in &loaded {
+        let mut documented =
+            doc::Module::new(module.file.display().to_string(), &module.statements);
+        for import in &mut documented.imports {
+            import.module = module
+                .imports
+                .iter()
+                .find(|loaded| loaded.path == import.path)
+                .and_then(|loaded| loaded.module);
+        }
+        modules.push(documented);
+    }
+    if options.highlight == highlight::Style::Html {
+        print!("{}", doc::html(&modules));
+    } else {
+        print!("{}", doc::markdown(&modules));
+    }
+}
+
+/// Prints the import graph of the script, and fails with 65 if it has a
+/// cycle, which running the script would stop at.
+fn deps(filename: &str, input: &str, options: &Options) {
+    let loaded = load_modules(filename, input, options);
+    let mut graph = deps::Graph::new();
+    for module in &loaded {
+        graph.add_file(shown(&module.file), true);
+    }
+    let mut missing: Vec<(PathBuf, usize)> = vec![];
+    for (from, module) in loaded.iter().enumerate() {
+        for import in &module.imports {
+            let to = match import.module {
+                Some(n) => n,
+                None => match missing.iter().find(|(file, _)| *file == import.resolved) {
+                    Some(&(_, n)) => n,
+                    None => {
+                        let n = graph.add_file(shown(&import.resolved), false);
+                        missing.push((import.resolved.clone(), n));
+                        n
+                    }
+                },
+            };
+            graph.add_import(from, to);
+        }
+    }
+    match options.graph {
+        deps::Style::Tree => print!("{}", graph.tree()),
+        deps::Style::Dot => print!("{}", graph.dot()),
+    }
+    let cycles = graph.cycles();
+    for cycle in &cycles {
+        eprintln!("Import cycle: {}.", cycle.join(" -> "));
+    }
+    if !cycles.is_empty() {
+        exit(65);
+    }
+}
+
+fn stats(filename: &str, input: &str, options: &Options) {
+    let mut diagnostics = new_diagnostics(options);
+    let statements = parse_program(filename, input, &mut diagnostics, options);
+    // Scanning again, now that parse_program has reported any errors.
+    let tokens = Scanner::new(input)
+        .with_optional_semicolons(options.optional_semicolons)
+        .with_print_keyword(options.print_keyword)
+        .scan_tokens(&mut Diagnostics::new(Format::Plain));
+    let stats = CodeStats::measure(input, &tokens, &statements);
+    println!("{}", stats.report());
+}
+
+fn new_diagnostics(options: &Options) -> Diagnostics {
+    let diagnostics = Diagnostics::new(options.diagnostics);
+    match options.max_errors {
+        Some(max_errors) => diagnostics.collect_all(max_errors),
+        None => diagnostics,
+    }
+}
+
+/// Scans `input`. Unless every error is being collected, scan errors are
+/// reported and end the process here.
+fn scan(
+    filename: &str,
+    input: &str,
+    diagnostics: &mut Diagnostics,
+    options: &Options,
+) -> Vec<Token> {
+    let mut scanner = Scanner::new(input)
+        .with_optional_semicolons(options.optional_semicolons)
+        .with_print_keyword(options.print_keyword);
+    let tokens = scanner.scan_tokens(diagnostics);
+    if diagnostics.has_errors() && diagnostics.fail_fast() {
+        diagnostics.emit(filename, input);
         exit(65);
     }
+    tokens
+}
+
+fn fail(
+    filename: &str,
+    input: &str,
+    diagnostics: &mut Diagnostics,
+    diagnostic: Diagnostic,
+    code: i32,
+) -> ! {
+    // `exit` is not an error to the user.
+    if diagnostic.code != Code::Exit {
+        diagnostics.push(diagnostic);
+    }
+    diagnostics.emit(filename, input);
+    exit(code);
+}
+
+fn parse(filename: &str, input: &str, options: &Options) {
+    let mut diagnostics = new_diagnostics(options);
+    let tokens = scan(filename, input, &mut diagnostics, options);
+
+    let mut parser = Parser::new(&tokens);
+    match parser.expression() {
+        Ok(expression) => println!("{expression}"),
+        Err(diagnostic) => fail(filename, input, &mut diagnostics, diagnostic, 65),
+    }
+}
+
+fn evaluate(filename: &str, input: &str, options: &Options) {
+    let mut diagnostics = new_diagnostics(options);
+    let tokens = scan(filename, input, &mut diagnostics, options);
 
     let mut parser = Parser::new(&tokens);
     let expr = match parser.expression() {
         Ok(expr) => expr,
-        Err(msg) => {
-            eprintln!("{}", msg);
-            exit(65);
-        }
+        Err(diagnostic) => fail(filename, input, &mut diagnostics, diagnostic, 65),
     };
 
-    let mut interpreter = Interpreter::new();
+    let mut interpreter = Interpreter::new().with_sandbox(options.sandbox);
+    for (name, value) in globals(options) {
+        interpreter.define_global(&name, value);
+    }
+    interpreter.set_compat(options.compat);
+    interpreter.set_checked_division(options.checked_division);
+    interpreter.set_implicit_globals(options.implicit_globals);
+    interpreter.set_optional_semicolons(options.optional_semicolons);
+    interpreter.set_print_keyword(options.print_keyword);
     match interpreter.evaluate(&expr) {
-        Ok(val) => match val {
-            Literal::Number(n) => println!("{}", n),
-            _ => println!("{}", val),
-        },
-        Err(msg) => {
-            eprintln!("{}", msg);
-            exit(70);
-        }
+        Ok(value) => println!("{}", options.output_format.show(&value)),
+        Err(err) => fail(filename, input, &mut diagnostics, err.into(), 70),
     }
 }
 
-fn run(input: &str) {
-    let mut scanner = Scanner::new(input);
-    let tokens = scanner.scan_tokens();
-    if scanner.error {
+fn parse_program(
+    filename: &str,
+    input: &str,
+    diagnostics: &mut Diagnostics,
+    options: &Options,
+) -> Vec<Statement> {
+    let tokens = scan(filename, input, diagnostics, options);
+    let mut parser = Parser::new(&tokens);
+    if diagnostics.fail_fast() {
+        return match parser.parse() {
+            Ok(statements) => statements,
+            Err(diagnostic) => fail(filename, input, diagnostics, diagnostic, 65),
+        };
+    }
+    let statements = parser.parse_all().unwrap_or_else(|errors| {
+        for diagnostic in errors {
+            diagnostics.push(diagnostic);
+        }
+        vec![]
+    });
+    if diagnostics.has_errors() {
+        diagnostics.emit(filename, input);
         exit(65);
     }
+    statements
+}
 
-    let mut parser = Parser::new(&tokens);
-    let statements = match parser.parse() {
-        Ok(statements) => statements,
-        Err(msg) => {
-            eprintln!("{}", msg);
+fn compile(filename: &str, input: &str, diagnostics: &mut Diagnostics, options: &Options) -> Chunk {
+    let statements = parse_program(filename, input, diagnostics, options);
+    let mut chunk = match Compiler::new().compile(&statements) {
+        Ok(chunk) => chunk,
+        Err(diagnostic) => fail(filename, input, diagnostics, diagnostic, 65),
+    };
+    if options.optimize {
+        optimizer::optimize(&mut chunk);
+    }
+    chunk
+}
+
+/// Reuses `<file>.rlxc` when it was compiled from the same source with the
+/// same options, otherwise compiles and (best effort) refreshes it.
+fn compile_cached(
+    filename: &str,
+    input: &str,
+    diagnostics: &mut Diagnostics,
+    options: &Options,
+) -> Chunk {
+    let path = Path::new(filename).with_extension(artifact::EXTENSION);
+    let source_hash = artifact::hash(input);
+    let compiled = artifact::Options {
+        optimized: options.optimize,
+        optional_semicolons: options.optional_semicolons,
+        print_keyword: options.print_keyword,
+    };
+    let cached = fs::read(&path)
+        .ok()
+        .and_then(|bytes| artifact::decode(&bytes).ok())
+        .filter(|artifact| artifact.source_hash == source_hash && artifact.options == compiled);
+    if let Some(artifact) = cached {
+        return artifact.chunk;
+    }
+    let chunk = compile(filename, input, diagnostics, options);
+    let _ = fs::write(&path, artifact::encode(&chunk, source_hash, compiled));
+    chunk
+}
+
+fn new_vm(options: &Options) -> Vm {
+    let mut vm = Vm::new().with_sandbox(options.sandbox);
+    if let Some(log) = &options.log {
+        vm = vm.with_log(log);
+    }
+    if let Some(seed) = options.seed {
+        vm.set_seed(seed);
+    }
+    for (name, value) in globals(options) {
+        vm.define_global(&name, value);
+    }
+    vm.set_limits(options.limits);
+    vm.set_output_format(options.output_format);
+    vm.set_compat(options.compat);
+    vm.set_checked_division(options.checked_division);
+    vm.set_implicit_globals(options.implicit_globals);
+    vm
+}
+
+fn new_interpreter(options: &Options) -> Interpreter {
+    let mut interpreter = Interpreter::new().with_sandbox(options.sandbox);
+    if let Some(log) = &options.log {
+        interpreter = interpreter.with_log(log);
+    }
+    if let Some(seed) = options.seed {
+        interpreter.set_seed(seed);
+    }
+    for (name, value) in globals(options) {
+        interpreter.define_global(&name, value);
+    }
+    interpreter.set_limits(options.limits);
+    interpreter.set_output_format(options.output_format);
+    interpreter.set_compat(options.compat);
+    interpreter.set_checked_division(options.checked_division);
+    interpreter.set_implicit_globals(options.implicit_globals);
+    interpreter.set_optional_semicolons(options.optional_semicolons);
+    interpreter.set_print_keyword(options.print_keyword);
+    interpreter.set_search_path(options.lox_path.clone());
+    interpreter
+}
+
+fn run_chunk(
+    filename: &str,
+    input: &str,
+    diagnostics: &mut Diagnostics,
+    options: &Options,
+    vm: &mut Vm,
+    chunk: &Chunk,
+) {
+    if let Err(err) = vm.run(chunk) {
+        print_stats(vm, options);
+        let code = exit_code(&err);
+        fail(filename, input, diagnostics, err.into(), code);
+    }
+}
+
+fn print_stats(vm: &Vm, options: &Options) {
+    if options.stats {
+        eprintln!("{}", vm.metrics().report("instructions"));
+        let cache = vm.global_cache;
+        eprintln!(
+            "global cache: {} hits, {} misses ({:.1}% hit rate)",
+            cache.hits,
+            cache.misses,
+            cache.hit_rate() * 100.0
+        );
+        #[cfg(feature = "jit")]
+        eprintln!("jit: {} chunks compiled", vm.compiled_chunks());
+    }
+}
+
+fn run_artifact(filename: &str, options: &Options) {
+    interrupt::install();
+    let mut diagnostics = new_diagnostics(options);
+    let bytes = fs::read(filename).unwrap_or_else(|_| {
+        eprintln!("Failed to read file {}", filename);
+        exit(66);
+    });
+    match artifact::decode(&bytes) {
+        Ok(artifact) => {
+            let mut vm = new_vm(options);
+            run_chunk(
+                filename,
+                "",
+                &mut diagnostics,
+                options,
+                &mut vm,
+                &artifact.chunk,
+            );
+            print_stats(&vm, options);
+        }
+        Err(err) => {
+            eprintln!("{}: {}", filename, err);
             exit(65);
         }
+    }
+}
+
+/// Runs the file and any further files given with it, in order, sharing one
+/// set of globals. All of them are compiled before the first one runs.
+fn run(filename: &str, input: &str, options: &Options) {
+    interrupt::install();
+    let mut diagnostics = new_diagnostics(options);
+    let mut sources = vec![(filename.to_string(), input.to_string())];
+    for file in &options.files {
+        sources.push((file.clone(), read_source(file)));
+    }
+    if options.backend == Backend::Vm {
+        for (flag, set) in [
+            ("--trace", options.trace.is_some()),
+            ("--coverage", options.coverage),
+            ("--debug", options.debug),
+        ] {
+            if set {
+                eprintln!("{} is only supported by the tree-walking backend", flag);
+                exit(64);
+            }
+        }
+    }
+    if options.backend == Backend::Vm {
+        let chunks: Vec<Chunk> = sources
+            .iter()
+            .map(|(filename, input)| {
+                if options.cache {
+                    compile_cached(filename, input, &mut diagnostics, options)
+                } else {
+                    compile(filename, input, &mut diagnostics, options)
+                }
+            })
+            .collect();
+        let mut vm = new_vm(options);
+        for ((filename, input), chunk) in sources.iter().zip(&chunks) {
+            run_chunk(filename, input, &mut diagnostics, options, &mut vm, chunk);
+        }
+        print_stats(&vm, options);
+        return;
+    }
+
+    let programs: Vec<Vec<Statement>> = sources
+        .iter()
+        .map(|(filename, input)| parse_program(filename, input, &mut diagnostics, options))
+        .collect();
+
+    let mut interpreter = new_interpreter(options);
+    if let Some(format) = options.trace {
+        interpreter.set_tracer(Tracer::new(format));
+    }
+    if options.coverage {
+        interpreter.record_coverage();
+    }
+    if options.debug {
+        Debugger::new(interpreter.input())
+            .with_output_format(options.output_format)
+            .attach(&mut interpreter);
+    }
+    let mut coverage = vec![];
+    for ((filename, input), statements) in sources.iter().zip(programs) {
+        let mut file = FileCoverage::new(filename, input, &statements);
+        interpreter.set_script(filename);
+        let result = interpreter.interpret(statements);
+        if options.coverage {
+            file.record(&interpreter.take_hits());
+            coverage.push(file);
+            if result.is_err() || coverage.len() == sources.len() {
+                report_coverage(&coverage);
+            }
+        }
+        if let Err(err) = result {
+            if options.stats {
+                eprintln!("{}", interpreter.metrics().report("statements"));
+            }
+            let code = exit_code(&err);
+            let notes = interpreter
+                .stack_trace()
+                .iter()
+                .map(|frame| frame.to_string())
+                .collect();
+            fail(
+                filename,
+                input,
+                &mut diagnostics,
+                Diagnostic::from(err).with_notes(notes),
+                code,
+            )
+        }
+    }
+    if options.stats {
+        eprintln!("{}", interpreter.metrics().report("statements"));
+    }
+}
+
+/// Prints the summary on stderr and writes the reports.
+fn report_coverage(files: &[FileCoverage]) {
+    eprintln!("{}", coverage::summary(files));
+    if let Err(err) = coverage::write(files) {
+        eprintln!(
+            "Failed to write coverage reports to {}: {}",
+            coverage::DIR,
+            err
+        );
+    }
+}
+
+fn disassemble(filename: &str, input: &str, options: &Options) {
+    let mut diagnostics = new_diagnostics(options);
+    let chunk = compile(filename, input, &mut diagnostics, options);
+    print!("{}", chunk.disassemble(filename, input));
+}
+
+/// What `bench` runs the program on.
+enum Engine {
+    Vm(Vm, Chunk),
+    TreeWalk(Interpreter),
+}
+
+/// Runs the script `--warmup` times untimed, then `--iterations` times timed,
+/// and reports on stderr. Parsing and compiling happen once up front and are
+/// not part of the measurement.
+fn bench(filename: &str, input: &str, options: &Options) {
+    let mut diagnostics = new_diagnostics(options);
+    // One engine for every iteration, set up as `run` would, so neither its
+    // setup nor the prelude is timed; it is reset before each iteration.
+    let mut engine = match options.backend {
+        Backend::Vm => Engine::Vm(
+            new_vm(options),
+            compile(filename, input, &mut diagnostics, options),
+        ),
+        Backend::TreeWalk => {
+            let mut interpreter = new_interpreter(options);
+            interpreter.set_script(filename);
+            Engine::TreeWalk(interpreter)
+        }
+    };
+    let statements = parse_program(filename, input, &mut diagnostics, options);
+    let mut samples = vec![];
+    for iteration in 0..options.warmup + options.iterations {
+        let program = statements.clone();
+        let before = match &mut engine {
+            Engine::Vm(vm, _) => {
+                vm.reset();
+                if let Some(seed) = options.seed {
+                    vm.set_seed(seed);
+                }
+                vm.set_limits(options.limits);
+                vm.instructions
+            }
+            Engine::TreeWalk(interpreter) => {
+                interpreter.reset();
+                if let Some(seed) = options.seed {
+                    interpreter.set_seed(seed);
+                }
+                interpreter.set_limits(options.limits);
+                interpreter.statements
+            }
+        };
+        let allocations = bench::allocations();
+        let start = Instant::now();
+        let (result, steps) = match &mut engine {
+            Engine::Vm(vm, chunk) => (vm.run(chunk), vm.instructions),
+            Engine::TreeWalk(interpreter) => {
+                (interpreter.interpret(program), interpreter.statements)
+            }
+        };
+        let time = start.elapsed();
+        if let Err(err) = result {
+            let code = exit_code(&err);
+            fail(filename, input, &mut diagnostics, err.into(), code);
+        }
+        if iteration >= options.warmup {
+            samples.push(bench::Sample {
+                time,
+                steps: steps - before,
+                allocations: bench::allocations() - allocations,
+            });
+        }
+    }
+    let unit = match options.backend {
+        Backend::Vm => "instructions executed",
+        Backend::TreeWalk => "statements executed",
     };
+    eprintln!("{}", bench::report(filename, unit, &samples));
+}
+
+fn compile_to_rust(filename: &str, input: &str, options: &Options) {
+    let mut diagnostics = new_diagnostics(options);
+    let statements = parse_program(filename, input, &mut diagnostics, options);
+    let program = Transpiler::new(options.output_format)
+        .with_compat(options.compat)
+        .with_checked_division(options.checked_division)
+        .with_implicit_globals(options.implicit_globals)
+        .transpile(filename, &statements);
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None => Path::new(filename)
+            .with_extension("rs")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    if let Err(err) = fs::write(&output, program) {
+        eprintln!("Failed to write {}: {}", output, err);
+        exit(74);
+    }
+}
 
-    let mut interpreter = Interpreter::new();
-    match interpreter.interpret(statements) {
-        Ok(_) => {}
-        Err(msg) => {
-            eprintln!("{}", msg);
-            exit(70);
+fn exit_code(err: &RuntimeError) -> i32 {
+    match err.kind {
+        RuntimeErrorKind::Interrupted => 130,
+        RuntimeErrorKind::Exit(status) => status,
+        _ => 70,
+    }
+}
+
+fn explain(code: &str) {
+    match Code::lookup(code) {
+        Some(code) => println!("{}\n\n{}", code, code.explanation()),
+        None => {
+            eprintln!("Unknown error code: {}", code);
+            exit(64);
         }
     }
 }
 
+/// How many errors `--no-fail-fast` shows when `--max-errors` is not given.
+const DEFAULT_MAX_ERRORS: usize = 20;
+
+/// Stands in for the file name in diagnostics for `-e` programs.
+const INLINE: &str = "-e";
+/// Passed instead of a file name to read the program from standard input.
+const STDIN: &str = "-";
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        writeln!(io::stderr(), "Usage: {} tokenize <filename>", args[0]).unwrap();
+    let settings = config::load().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(78);
+    });
+    let (mut options, args) = parse_args(&env::args().collect::<Vec<_>>(), &settings);
+    load_plugins(&mut options);
+    if args.get(1).is_some_and(|command| command == "repl") {
+        let mut repl = Repl::new(options.diagnostics);
+        if let Some(prompt) = options.prompt.take() {
+            repl = repl.with_prompt(prompt);
+        }
+        repl = repl
+            .with_output_format(options.output_format)
+            .with_compat(options.compat)
+            .with_checked_division(options.checked_division)
+            .with_implicit_globals(options.implicit_globals)
+            .with_optional_semicolons(options.optional_semicolons)
+            .with_print_keyword(options.print_keyword)
+            .with_sandbox(options.sandbox)
+            .with_search_path(options.lox_path.clone())
+            .with_globals(globals(&options));
+        exit(repl.run());
+    }
+    if args.get(1).is_some_and(|command| command == "lsp") {
+        let status = Server::new()
+            .with_optional_semicolons(options.optional_semicolons)
+            .with_print_keyword(options.print_keyword)
+            .serve(io::stdin().lock(), io::stdout());
+        exit(status.unwrap_or_else(|err| {
+            eprintln!("Language server failed: {err}");
+            1
+        }));
+    }
+    if args.get(1).is_some_and(|command| command == "dap") {
+        let adapter = Adapter::new(new_interpreter(&options))
+            .with_optional_semicolons(options.optional_semicolons)
+            .with_print_keyword(options.print_keyword);
+        if let Err(err) = adapter.serve(io::BufReader::new(io::stdin()), io::stdout()) {
+            eprintln!("Debug adapter failed: {err}");
+            exit(1);
+        }
+        return;
+    }
+    if let (Some(command), Some(source)) = (args.get(1), options.inline.take()) {
+        // There is no file to keep a cached artifact next to.
+        options.cache = false;
+        dispatch(command, INLINE, &source, &options);
+        return;
+    }
+    let command = args.get(1);
+    let filename = match args.get(2) {
+        Some(filename) => Some(filename.clone()),
+        // With a manifest, commands take its entry point when given no file.
+        None if command.is_some_and(|command| command != "explain") => options.entry.clone(),
+        None => None,
+    };
+    let (Some(command), Some(filename)) = (command, filename) else {
+        eprintln!("Usage: {} tokenize <filename>", args[0]);
+        return;
+    };
+    if command == "explain" {
+        explain(&filename);
         return;
     }
 
-    let command = &args[1];
-    let filename = &args[2];
-    let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-        writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
+    let filename = &filename;
+    if filename == STDIN {
+        let mut source = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut source) {
+            eprintln!("Failed to read standard input: {}", err);
+            exit(66);
+        }
+        options.cache = false;
+        dispatch(command, "<stdin>", &source, &options);
+        return;
+    }
+    if command == "run" && filename.ends_with(&format!(".{}", artifact::EXTENSION)) {
+        run_artifact(filename, &options);
+        return;
+    }
+
+    let file_contents = read_source(filename);
+    dispatch(command, filename, &file_contents, &options);
+}
+
+fn read_source(filename: &str) -> String {
+    fs::read_to_string(filename).unwrap_or_else(|_| {
+        eprintln!("Failed to read file {}", filename);
         String::new()
-    });
+    })
+}
 
-    match command.as_str() {
-        "tokenize" => tokenize(&file_contents),
-        "parse" => parse(&file_contents),
-        "evaluate" => evaluate(&file_contents),
-        "run" => run(&file_contents),
+fn dispatch(command: &str, filename: &str, file_contents: &str, options: &Options) {
+    match command {
+        "tokenize" => tokenize(filename, file_contents, options),
+        "highlight" => highlight(filename, file_contents, options),
+        "doc" => doc(filename, file_contents, options),
+        "stats" => stats(filename, file_contents, options),
+        "deps" => deps(filename, file_contents, options),
+        "parse" => parse(filename, file_contents, options),
+        "evaluate" => evaluate(filename, file_contents, options),
+        "run" => run(filename, file_contents, options),
+        "disassemble" => disassemble(filename, file_contents, options),
+        "bench" => bench(filename, file_contents, options),
+        "compile" => compile_to_rust(filename, file_contents, options),
         _ => {
-            writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
-            return;
+            eprintln!("Unknown command: {}", command);
         }
     }
 }
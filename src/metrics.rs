@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use crate::grammar::Literal;
+
+/// Resources used by the runs of an interpreter or VM so far, for quotas
+/// and performance analysis.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Metrics {
+    /// Statements executed by the interpreter, or instructions by the VM.
+    pub steps: u64,
+    /// Strings, lists and maps created by concatenation or returned by
+    /// natives.
+    pub allocations: u64,
+    /// The most bytes held by variables at once, as estimated by [`size`].
+    /// The VM only counts its globals, since locals live on its stack.
+    pub peak_memory: usize,
+    pub elapsed: Duration,
+}
+
+impl Metrics {
+    /// One line per counter, for `--stats`.
+    pub fn report(&self, unit: &str) -> String {
+        format!(
+            "{unit}: {}\nallocations: {}\npeak memory: {} bytes\ntime: {:.3} ms",
+            self.steps,
+            self.allocations,
+            self.peak_memory,
+            self.elapsed.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Bytes held by variables, now and at the most.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Memory {
+    live: usize,
+    pub peak: usize,
+}
+
+impl Memory {
+    pub fn add(&mut self, value: &Literal) {
+        self.live += size(value);
+        self.peak = self.peak.max(self.live);
+    }
+
+    pub fn remove(&mut self, value: &Literal) {
+        self.live = self.live.saturating_sub(size(value));
+    }
+
+    /// Forgets what is live, when the variables themselves are dropped.
+    pub fn clear(&mut self) {
+        self.live = 0;
+    }
+}
+
+/// Estimated bytes taken by `value`: its own and those it owns. A list or
+/// map held by several variables is counted for each of them.
+pub fn size(value: &Literal) -> usize {
+    std::mem::size_of::<Literal>()
+        + match value {
+            Literal::String(s) => s.len(),
+            Literal::List(items) => items.iter().map(size).sum(),
+            Literal::Map(entries) => entries
+                .iter()
+                .map(|(key, value)| key.len() + size(value))
+                .sum(),
+            _ => 0,
+        }
+}
+
+/// Whether producing `value` allocated, for [`Metrics::allocations`].
+pub fn allocates(value: &Literal) -> bool {
+    matches!(
+        value,
+        Literal::String(_) | Literal::List(_) | Literal::Map(_)
+    )
+}
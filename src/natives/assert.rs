@@ -0,0 +1,48 @@
+//! Assertions for scripts that test themselves. A failed assertion is a
+//! runtime error at the call.
+
+use super::{Context, Spec};
+use crate::grammar::*;
+
+pub const NATIVES: &[Spec] = &[
+    Spec {
+        variadic: true,
+        ..Spec::pure("assert", 1, assert)
+    },
+    Spec::pure("assertEquals", 2, assert_equals),
+];
+
+/// Strings quoted, so that `"1"` and `1` tell apart.
+fn show(value: &Literal) -> String {
+    match value {
+        Literal::String(s) => format!("{s:?}"),
+        value => OutputFormat::Lox.show(value),
+    }
+}
+
+/// `assert(condition)` or `assert(condition, message)`: fails when the
+/// condition is falsey.
+fn assert(context: &Context, args: &[Literal]) -> NativeResult {
+    let message = match args {
+        [_] => None,
+        [_, Literal::String(message)] => Some(message.as_str()),
+        [_, other] => return Err(format!("Expected string but got {}.", other.type_name())),
+        _ => return Err(format!("Expected 1 or 2 arguments but got {}.", args.len())),
+    };
+    match (context.truthy(&args[0]), message) {
+        (true, _) => Ok(Literal::Nil),
+        (false, Some(message)) => Err(format!("Assertion failed: {message}")),
+        (false, None) => Err(format!("Assertion failed: {} is falsey.", show(&args[0]))),
+    }
+}
+
+fn assert_equals(_: &Context, args: &[Literal]) -> NativeResult {
+    if args[0] == args[1] {
+        return Ok(Literal::Nil);
+    }
+    Err(format!(
+        "Assertion failed: expected {} but got {}.",
+        show(&args[0]),
+        show(&args[1])
+    ))
+}
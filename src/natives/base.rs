@@ -0,0 +1,262 @@
+//! The natives of the reference implementation, and those every script
+//! needs: timing, input and output, conversions between types, identity,
+//! errors to throw, panicking, exiting and `eval`.
+
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::{Capability, Context, Request, Spec};
+use crate::grammar::*;
+use crate::interrupt;
+use crate::shared::Shared;
+
+pub const NATIVES: &[Spec] = &[
+    Spec {
+        name: "clock",
+        arity: 0,
+        variadic: false,
+        capability: Capability::Pure,
+        deterministic: false,
+        function: clock,
+    },
+    Spec::pure("sleep", 1, sleep),
+    Spec {
+        name: "input",
+        arity: 0,
+        variadic: false,
+        capability: Capability::Input,
+        deterministic: false,
+        function: input,
+    },
+    Spec {
+        variadic: true,
+        ..Spec::pure("print", 0, print)
+    },
+    Spec {
+        variadic: true,
+        ..Spec::pure("printWith", 2, print_with)
+    },
+    Spec::pure("type", 1, type_of),
+    Spec::pure("str", 1, str),
+    Spec::pure("num", 1, num),
+    Spec::pure("bool", 1, bool),
+    Spec::pure("truthy", 1, bool),
+    Spec::pure("identical", 2, identical),
+    Spec {
+        variadic: true,
+        ..Spec::pure("parseInt", 1, parse_int)
+    },
+    Spec::pure("parseFloat", 1, parse_float),
+    Spec::pure("error", 2, error),
+    Spec::pure("panic", 1, panic),
+    Spec::pure("exit", 1, exit),
+    Spec::pure("eval", 1, eval),
+];
+
+/// Seconds since the Unix epoch, for timing scripts.
+fn clock(_: &Context, _: &[Literal]) -> NativeResult {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|err| err.to_string())?;
+    Ok(Literal::Number(now.as_secs_f64()))
+}
+
+/// Pauses for the given number of milliseconds. Returns early when the run
+/// is interrupted or times out, for the caller to raise the error.
+fn sleep(context: &Context, args: &[Literal]) -> NativeResult {
+    let ms = f64::try_from(args[0].clone())?;
+    if !(ms >= 0.0 && ms.is_finite()) {
+        return Err("sleep() needs a non-negative number of milliseconds.".to_string());
+    }
+    // Short slices, so that Ctrl-C is noticed promptly.
+    const SLICE: Duration = Duration::from_millis(10);
+    let mut until = Instant::now() + Duration::from_secs_f64(ms / 1000.0);
+    if let Some(deadline) = context.deadline() {
+        until = until.min(deadline);
+    }
+    loop {
+        let now = Instant::now();
+        if now >= until || interrupt::pending() {
+            return Ok(Literal::Nil);
+        }
+        thread::sleep((until - now).min(SLICE));
+    }
+}
+
+/// The arguments as the `print` statement shows them, separated by spaces,
+/// on a line of their own. Being a value, it can be passed around and
+/// called where only an expression fits.
+fn print(context: &Context, args: &[Literal]) -> NativeResult {
+    write(context, args, " ", "\n")
+}
+
+/// Like `print`, with the separator and what ends the output given first,
+/// as in `printWith(", ", "", a, b)`.
+fn print_with(context: &Context, args: &[Literal]) -> NativeResult {
+    let separator = String::try_from(args[0].clone())?;
+    let end = String::try_from(args[1].clone())?;
+    write(context, &args[2..], &separator, &end)
+}
+
+fn write(context: &Context, values: &[Literal], separator: &str, end: &str) -> NativeResult {
+    let values: Vec<_> = values.iter().map(|value| context.show(value)).collect();
+    context
+        .output
+        .lock()
+        .write(&format!("{}{end}", values.join(separator)))
+        .map_err(|err| format!("Failed to write output: {err}."))?;
+    Ok(Literal::Nil)
+}
+
+/// The next line of input without its line ending, or nil at its end.
+fn input(context: &Context, _: &[Literal]) -> NativeResult {
+    match context.input.read_line() {
+        Ok(line) => Ok(line.map_or(Literal::Nil, Literal::String)),
+        Err(err) => Err(format!("Failed to read input: {err}.")),
+    }
+}
+
+/// The name of the argument's runtime type, as error messages give it.
+fn type_of(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(Literal::String(args[0].type_name().to_string()))
+}
+
+/// The argument as `print` shows it, without `--format`.
+fn str(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(Literal::String(OutputFormat::Lox.show(&args[0])))
+}
+
+/// The argument as a number: strings are parsed as number literals, with
+/// surrounding whitespace and an optional sign, giving nil when they are
+/// not one; booleans are 1 or 0. Other types raise an error.
+fn num(_: &Context, args: &[Literal]) -> NativeResult {
+    match &args[0] {
+        Literal::Number(n) => Ok(Literal::Number(*n)),
+        Literal::Boolean(b) => Ok(Literal::Number(if *b { 1.0 } else { 0.0 })),
+        Literal::String(s) => Ok(parse_number(s).map_or(Literal::Nil, Literal::Number)),
+        other => Err(format!("Cannot convert {} to a number.", other.type_name())),
+    }
+}
+
+fn parse_number(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (digits, None),
+    };
+    let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    if !is_digits(whole) || !fraction.map_or(true, is_digits) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// `parseInt(s)` or `parseInt(s, radix)`: a whole number written in the
+/// radix, 10 by default, with surrounding whitespace and an optional sign.
+/// Nil when the whole string is not one, rather than the digits it starts
+/// with.
+fn parse_int(_: &Context, args: &[Literal]) -> NativeResult {
+    let radix = match args {
+        [_] => 10,
+        [_, Literal::Number(radix)] if (2.0..=36.0).contains(radix) && radix.fract() == 0.0 => {
+            *radix as u32
+        }
+        [_, Literal::Number(_)] => {
+            return Err("Radix must be a whole number from 2 to 36.".to_string())
+        }
+        [_, other] => return Err(format!("Expected number but got {}.", other.type_name())),
+        _ => return Err(format!("Expected 1 or 2 arguments but got {}.", args.len())),
+    };
+    let s = String::try_from(args[0].clone())?;
+    let s = s.trim();
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    // `from_str_radix` would take a second sign.
+    if digits.starts_with(['+', '-']) {
+        return Ok(Literal::Nil);
+    }
+    Ok(match u64::from_str_radix(digits, radix) {
+        Ok(n) if negative => Literal::Number(-(n as f64)),
+        Ok(n) => Literal::Number(n as f64),
+        Err(_) => Literal::Nil,
+    })
+}
+
+/// A number with an optional fraction and exponent, such as `1e3` or
+/// `-.5`, or `Infinity` or `NaN` as `print` writes them. Nil when the
+/// string is not one.
+fn parse_float(_: &Context, args: &[Literal]) -> NativeResult {
+    let s = String::try_from(args[0].clone())?;
+    let s = s.trim();
+    let unsigned = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let sign = if s.starts_with('-') { -1.0 } else { 1.0 };
+    let is_number = |b: u8| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-');
+    let number = match unsigned {
+        "Infinity" => Some(sign * f64::INFINITY),
+        "NaN" => Some(f64::NAN),
+        _ if !unsigned.starts_with(['-', '+']) && unsigned.bytes().all(is_number) => {
+            unsigned.parse::<f64>().ok().map(|n| sign * n)
+        }
+        _ => None,
+    };
+    Ok(number.map_or(Literal::Nil, Literal::Number))
+}
+
+/// Whether the argument is truthy, as `!` takes it under the `--compat=`
+/// mode. It is also named `truthy`, for scripts asking about the rule
+/// rather than converting.
+fn bool(context: &Context, args: &[Literal]) -> NativeResult {
+    Ok(Literal::Boolean(context.truthy(&args[0])))
+}
+
+/// Whether both arguments are the same list or map, where `==` only tells
+/// whether they hold equal items.
+fn identical(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(Literal::Boolean(args[0].identical(&args[1])))
+}
+
+/// An error of a type the script names, to `throw` and catch by that type:
+/// a map of the type and the message.
+fn error(_: &Context, args: &[Literal]) -> NativeResult {
+    let type_name = String::try_from(args[0].clone())?;
+    let message = String::try_from(args[1].clone())?;
+    let entries = BTreeMap::from([
+        ("type".to_string(), Literal::String(type_name)),
+        ("message".to_string(), Literal::String(message)),
+    ]);
+    Ok(Literal::Map(Shared::new(entries)))
+}
+
+/// Ends the run with a runtime error no `catch` can stop, for bugs rather
+/// than expected failures.
+fn panic(context: &Context, args: &[Literal]) -> NativeResult {
+    context.request(Request::Panic(args[0].to_string()));
+    Ok(Literal::Nil)
+}
+
+/// Stops the script with an exit status. The interpreter or VM unwinds
+/// from the call as it does for an error, rather than the process exiting
+/// here, so that output is flushed and hosts keep running.
+fn exit(context: &Context, args: &[Literal]) -> NativeResult {
+    let status = match &args[0] {
+        Literal::Number(n) if n.fract() == 0.0 && (0.0..=255.0).contains(n) => *n as i32,
+        Literal::Number(_) => {
+            return Err("Exit status must be a whole number from 0 to 255.".into())
+        }
+        other => return Err(format!("Expected number but got {}.", other.type_name())),
+    };
+    context.request(Request::Exit(status));
+    Ok(Literal::Nil)
+}
+
+/// Runs source in the caller's scope: the value of an expression, or nil
+/// after statements. Only the tree-walker supports it.
+fn eval(context: &Context, args: &[Literal]) -> NativeResult {
+    let source = String::try_from(args[0].clone())?;
+    context.request(Request::Eval(source));
+    Ok(Literal::Nil)
+}
@@ -0,0 +1,159 @@
+//! Strings as UTF-8 bytes, base64 and hex. Encoding takes a string, which
+//! is encoded as UTF-8, or a list of bytes as numbers from 0 to 255.
+//! Decoding gives back a string when the bytes are valid UTF-8, and the
+//! list of bytes otherwise. `len` counts characters; `byteLength` counts
+//! the bytes they take.
+//!
+//! ```
+//! # use rustyinterpreter::{grammar::Literal, Lox};
+//! let mut lox = Lox::new();
+//! let mut eval = |source| lox.evaluate(source, [("s", "héllo")]).unwrap();
+//! assert_eq!(eval("base64Encode(s)"), Literal::from("aMOpbGxv"));
+//! assert_eq!(eval("hexEncode(s)"), Literal::from("68c3a96c6c6f"));
+//! assert_eq!(eval(r#"base64Decode("aMOpbGxv")"#), Literal::from("héllo"));
+//! assert_eq!(eval(r#"len(hexDecode("ff00"))"#), Literal::Number(2.0));
+//! assert_eq!(eval("byteLength(s)"), Literal::Number(6.0));
+//! assert_eq!(eval("fromBytes(toBytes(s))"), Literal::from("héllo"));
+//! ```
+
+use super::{Context, Spec};
+use crate::grammar::*;
+use crate::shared::Shared;
+
+pub const NATIVES: &[Spec] = &[
+    Spec::pure("byteLength", 1, byte_length),
+    Spec::pure("toBytes", 1, to_bytes),
+    Spec::pure("fromBytes", 1, from_bytes),
+    Spec::pure("base64Encode", 1, base64_encode),
+    Spec::pure("base64Decode", 1, base64_decode),
+    Spec::pure("hexEncode", 1, hex_encode),
+    Spec::pure("hexDecode", 1, hex_decode),
+];
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn bytes(value: &Literal) -> Result<Vec<u8>, String> {
+    match value {
+        Literal::String(s) => Ok(s.as_bytes().to_vec()),
+        Literal::List(items) => items
+            .iter()
+            .map(|item| match item {
+                Literal::Number(n) if (0.0..=255.0).contains(n) && n.fract() == 0.0 => Ok(*n as u8),
+                _ => Err("Bytes must be whole numbers from 0 to 255.".to_string()),
+            })
+            .collect(),
+        other => Err(format!(
+            "Expected string or list but got {}.",
+            other.type_name()
+        )),
+    }
+}
+
+fn list(bytes: Vec<u8>) -> Literal {
+    Literal::List(Shared::new(
+        bytes
+            .into_iter()
+            .map(|byte| Literal::Number(f64::from(byte)))
+            .collect(),
+    ))
+}
+
+fn decoded(bytes: Vec<u8>) -> Literal {
+    match String::from_utf8(bytes) {
+        Ok(s) => Literal::String(s),
+        Err(err) => list(err.into_bytes()),
+    }
+}
+
+fn text(value: &Literal) -> Result<&str, String> {
+    match value {
+        Literal::String(s) => Ok(s),
+        other => Err(format!("Expected string but got {}.", other.type_name())),
+    }
+}
+
+fn byte_length(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(Literal::Number(text(&args[0])?.len() as f64))
+}
+
+/// The string's UTF-8 bytes as a list of numbers.
+fn to_bytes(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(list(text(&args[0])?.as_bytes().to_vec()))
+}
+
+/// The string a list of bytes encodes as UTF-8; an error when they are not
+/// valid UTF-8.
+fn from_bytes(_: &Context, args: &[Literal]) -> NativeResult {
+    let Literal::List(_) = &args[0] else {
+        return Err(format!("Expected list but got {}.", args[0].type_name()));
+    };
+    String::from_utf8(bytes(&args[0])?)
+        .map(Literal::String)
+        .map_err(|_| "Bytes are not valid UTF-8.".to_string())
+}
+
+/// The standard alphabet, padded with `=`.
+fn base64_encode(_: &Context, args: &[Literal]) -> NativeResult {
+    let bytes = bytes(&args[0])?;
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | u32::from(*byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    Ok(Literal::String(out))
+}
+
+/// Padding is optional.
+fn base64_decode(_: &Context, args: &[Literal]) -> NativeResult {
+    let invalid = || "Invalid base64.".to_string();
+    let text = text(&args[0])?.trim_end_matches('=');
+    let mut bytes = vec![];
+    for chunk in text.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return Err(invalid());
+        }
+        let mut group = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let digit = ALPHABET.iter().position(|a| a == c).ok_or_else(invalid)?;
+            group |= (digit as u32) << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            bytes.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(decoded(bytes))
+}
+
+/// Lowercase, two digits per byte.
+fn hex_encode(_: &Context, args: &[Literal]) -> NativeResult {
+    let hex = bytes(&args[0])?
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(Literal::String(hex))
+}
+
+/// Either case.
+fn hex_decode(_: &Context, args: &[Literal]) -> NativeResult {
+    let text = text(&args[0])?;
+    if text.len() % 2 != 0 {
+        return Err("Hex needs an even number of digits.".to_string());
+    }
+    let bytes = (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| "Invalid hex.".to_string())
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(decoded(bytes))
+}
@@ -0,0 +1,171 @@
+//! Files and directories, behind the `fs` feature.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+
+use super::{Capability, Context, Spec};
+use crate::grammar::*;
+use crate::shared::Shared;
+
+pub const NATIVES: &[Spec] = &[
+    Spec {
+        name: "readFile",
+        arity: 1,
+        variadic: false,
+        capability: Capability::FsRead,
+        deterministic: false,
+        function: read_file,
+    },
+    Spec {
+        name: "writeFile",
+        arity: 2,
+        variadic: false,
+        capability: Capability::FsWrite,
+        deterministic: true,
+        function: write_file,
+    },
+    Spec {
+        name: "appendFile",
+        arity: 2,
+        variadic: false,
+        capability: Capability::FsWrite,
+        deterministic: true,
+        function: append_file,
+    },
+    Spec {
+        name: "exists",
+        arity: 1,
+        variadic: false,
+        capability: Capability::FsRead,
+        deterministic: false,
+        function: exists,
+    },
+    Spec {
+        name: "listDir",
+        arity: 1,
+        variadic: false,
+        capability: Capability::FsRead,
+        deterministic: false,
+        function: list_dir,
+    },
+    Spec {
+        name: "stat",
+        arity: 1,
+        variadic: false,
+        capability: Capability::FsRead,
+        deterministic: false,
+        function: stat,
+    },
+    Spec {
+        name: "mkdir",
+        arity: 1,
+        variadic: false,
+        capability: Capability::FsWrite,
+        deterministic: true,
+        function: mkdir,
+    },
+    Spec {
+        name: "remove",
+        arity: 1,
+        variadic: false,
+        capability: Capability::FsWrite,
+        deterministic: true,
+        function: remove,
+    },
+];
+
+fn path(value: &Literal) -> Result<&str, String> {
+    match value {
+        Literal::String(path) => Ok(path),
+        other => Err(format!("Expected string but got {}.", other.type_name())),
+    }
+}
+
+/// The whole file as a string.
+fn read_file(_: &Context, args: &[Literal]) -> NativeResult {
+    let path = path(&args[0])?;
+    fs::read_to_string(path)
+        .map(Literal::String)
+        .map_err(|err| format!("Cannot read '{path}': {err}."))
+}
+
+/// Replaces the file's contents with a string, creating it if needed.
+fn write_file(_: &Context, args: &[Literal]) -> NativeResult {
+    let path = path(&args[0])?;
+    let contents = String::try_from(args[1].clone())?;
+    fs::write(path, contents).map_err(|err| format!("Cannot write '{path}': {err}."))?;
+    Ok(Literal::Nil)
+}
+
+/// Adds a string to the end of the file, creating it if needed.
+fn append_file(_: &Context, args: &[Literal]) -> NativeResult {
+    let path = path(&args[0])?;
+    let contents = String::try_from(args[1].clone())?;
+    fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|err| format!("Cannot write '{path}': {err}."))?;
+    Ok(Literal::Nil)
+}
+
+fn exists(_: &Context, args: &[Literal]) -> NativeResult {
+    let path = path(&args[0])?;
+    Ok(Literal::Boolean(std::path::Path::new(path).exists()))
+}
+
+/// The names in a directory, sorted.
+fn list_dir(_: &Context, args: &[Literal]) -> NativeResult {
+    let path = path(&args[0])?;
+    let mut names = fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>, std::io::Error>>()
+        })
+        .map_err(|err| format!("Cannot list '{path}': {err}."))?;
+    names.sort();
+    Ok(Literal::List(Shared::new(
+        names.into_iter().map(Literal::String).collect(),
+    )))
+}
+
+/// A map of `size` in bytes, `isFile`, `isDir` and `modified`, in seconds
+/// since the Unix epoch.
+fn stat(_: &Context, args: &[Literal]) -> NativeResult {
+    let path = path(&args[0])?;
+    let metadata = fs::metadata(path).map_err(|err| format!("Cannot stat '{path}': {err}."))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(Literal::Nil, |since| Literal::Number(since.as_secs_f64()));
+    let entries = BTreeMap::from([
+        ("size".to_string(), Literal::Number(metadata.len() as f64)),
+        ("isFile".to_string(), Literal::Boolean(metadata.is_file())),
+        ("isDir".to_string(), Literal::Boolean(metadata.is_dir())),
+        ("modified".to_string(), modified),
+    ]);
+    Ok(Literal::Map(Shared::new(entries)))
+}
+
+/// Creates a directory and any missing parents.
+fn mkdir(_: &Context, args: &[Literal]) -> NativeResult {
+    let path = path(&args[0])?;
+    fs::create_dir_all(path).map_err(|err| format!("Cannot create '{path}': {err}."))?;
+    Ok(Literal::Nil)
+}
+
+/// Removes a file or an empty directory.
+fn remove(_: &Context, args: &[Literal]) -> NativeResult {
+    let path = path(&args[0])?;
+    let removed = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir(path),
+        _ => fs::remove_file(path),
+    };
+    removed.map_err(|err| format!("Cannot remove '{path}': {err}."))?;
+    Ok(Literal::Nil)
+}
@@ -0,0 +1,124 @@
+//! Formatting values into strings. In the template, `{}` stands for the
+//! next argument and `{N}` for the argument at position N, counting from
+//! 0. A precision after a colon, as in `{:.2}` or `{1:.3}`, rounds numbers
+//! to that many decimals. `{{` and `}}` are literal braces.
+//!
+//! ```
+//! # use rustyinterpreter::{grammar::Literal, Lox};
+//! let mut lox = Lox::new();
+//! let value = lox
+//!     .evaluate(r#"format("x={}, y={:.2}, {{{0}}}", 1, y)"#, [("y", 2.345)])
+//!     .unwrap();
+//! assert_eq!(value, Literal::from("x=1, y=2.35, {1}"));
+//! ```
+
+use super::{Capability, Context, Spec};
+use crate::grammar::*;
+
+pub const NATIVES: &[Spec] = &[
+    Spec {
+        name: "format",
+        arity: 1,
+        variadic: true,
+        capability: Capability::Pure,
+        deterministic: true,
+        function: format,
+    },
+    Spec {
+        name: "printf",
+        arity: 1,
+        variadic: true,
+        capability: Capability::Pure,
+        deterministic: true,
+        function: printf,
+    },
+];
+
+fn format(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(Literal::String(render(&args[0], &args[1..])?))
+}
+
+/// Like `format`, but writes the result where `print` does, without a line
+/// ending.
+fn printf(context: &Context, args: &[Literal]) -> NativeResult {
+    let text = render(&args[0], &args[1..])?;
+    context
+        .output
+        .lock()
+        .write(&text)
+        .map_err(|err| format!("Failed to write output: {err}."))?;
+    Ok(Literal::Nil)
+}
+
+fn render(template: &Literal, args: &[Literal]) -> Result<String, String> {
+    let Literal::String(template) = template else {
+        return Err(format!("Expected string but got {}.", template.type_name()));
+    };
+    let mut out = String::new();
+    let mut next = 0;
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                out.push('}');
+            }
+            '}' => return Err("Unmatched '}' in format string.".to_string()),
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| "Unclosed '{' in format string.".to_string())?;
+                let placeholder = &rest[..end];
+                chars = rest[end + 1..].chars();
+                let (position, precision) = parse(placeholder)
+                    .ok_or_else(|| format!("Invalid placeholder '{{{placeholder}}}'."))?;
+                let index = position.unwrap_or_else(|| {
+                    next += 1;
+                    next - 1
+                });
+                let value = args.get(index).ok_or_else(|| {
+                    format!(
+                        "Format string needs {} arguments but got {}.",
+                        index + 1,
+                        args.len()
+                    )
+                })?;
+                match (precision, value) {
+                    (Some(precision), Literal::Number(n)) if n.is_finite() => {
+                        out.push_str(&format!("{n:.precision$}"))
+                    }
+                    (_, value @ Literal::Number(_)) | (None, value) => {
+                        out.push_str(&OutputFormat::Lox.show(value))
+                    }
+                    (Some(_), other) => {
+                        return Err(format!(
+                            "Precision needs a number but got {}.",
+                            other.type_name()
+                        ))
+                    }
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// The position and precision in `N:.P`, both optional.
+fn parse(placeholder: &str) -> Option<(Option<usize>, Option<usize>)> {
+    let (position, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+    let position = match position {
+        "" => None,
+        digits => Some(digits.parse().ok()?),
+    };
+    let precision = match spec {
+        "" => None,
+        spec => Some(spec.strip_prefix('.')?.parse().ok()?),
+    };
+    Some((position, precision))
+}
@@ -0,0 +1,72 @@
+//! Hashing values, for scripts that bucket or deduplicate them.
+
+use super::{Context, Spec};
+use crate::grammar::*;
+
+pub const NATIVES: &[Spec] = &[Spec::pure("hash", 1, hash)];
+
+/// A whole number below 2^53, the same for values that are equal and on
+/// every run. Natives hash by name.
+fn hash(_: &Context, args: &[Literal]) -> NativeResult {
+    let mut hasher = Fnv::default();
+    hasher.value(&args[0]);
+    Ok(Literal::Number((hasher.0 >> 11) as f64))
+}
+
+/// 64-bit FNV-1a, which unlike the standard library's hasher is stable
+/// across runs and versions.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn str(&mut self, s: &str) {
+        self.bytes(&(s.len() as u64).to_le_bytes());
+        self.bytes(s.as_bytes());
+    }
+
+    /// A tag byte per type, so that `1` and `"1"` differ.
+    fn value(&mut self, value: &Literal) {
+        match value {
+            Literal::Nil => self.bytes(&[0]),
+            Literal::Boolean(b) => self.bytes(&[1, u8::from(*b)]),
+            Literal::Number(n) => {
+                // -0 equals 0.
+                let n = if *n == 0.0 { 0.0 } else { *n };
+                self.bytes(&[2]);
+                self.bytes(&n.to_bits().to_le_bytes());
+            }
+            Literal::String(s) => {
+                self.bytes(&[3]);
+                self.str(s);
+            }
+            Literal::List(items) => {
+                self.bytes(&[4]);
+                self.bytes(&(items.len() as u64).to_le_bytes());
+                items.iter().for_each(|item| self.value(item));
+            }
+            Literal::Map(entries) => {
+                self.bytes(&[5]);
+                self.bytes(&(entries.len() as u64).to_le_bytes());
+                for (key, value) in entries.iter() {
+                    self.str(key);
+                    self.value(value);
+                }
+            }
+            Literal::Native(native) => {
+                self.bytes(&[6]);
+                self.str(&native.name);
+            }
+        }
+    }
+}
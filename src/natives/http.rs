@@ -0,0 +1,209 @@
+//! `fetch`, a small HTTP/1.1 client behind the `http` feature. Only plain
+//! `http://` URLs are supported: HTTPS needs a TLS implementation, which the
+//! crate does not have.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use super::{Capability, Context, Spec};
+use crate::grammar::*;
+use crate::shared::Shared;
+
+pub const NATIVES: &[Spec] = &[Spec {
+    name: "fetch",
+    arity: 2,
+    variadic: false,
+    capability: Capability::Network,
+    deterministic: false,
+    function: fetch,
+}];
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Request {
+    method: String,
+    headers: Vec<(String, String)>,
+    body: String,
+    timeout: Duration,
+}
+
+fn string(value: &Literal, what: &str) -> Result<String, String> {
+    match value {
+        Literal::String(s) => Ok(s.clone()),
+        other => Err(format!(
+            "Expected string for {what} but got {}.",
+            other.type_name()
+        )),
+    }
+}
+
+/// The options map, or nil for a plain GET: `method`, `headers` (a map of
+/// strings), `body` and `timeout` in milliseconds.
+fn request(options: &Literal) -> Result<Request, String> {
+    let mut request = Request {
+        method: "GET".to_string(),
+        headers: vec![],
+        body: String::new(),
+        timeout: DEFAULT_TIMEOUT,
+    };
+    let entries = match options {
+        Literal::Nil => return Ok(request),
+        Literal::Map(entries) => entries,
+        other => return Err(format!("Expected map but got {}.", other.type_name())),
+    };
+    for (key, value) in entries.iter() {
+        match (key.as_str(), value) {
+            ("method", value) => request.method = string(value, "method")?.to_uppercase(),
+            ("body", value) => request.body = string(value, "body")?,
+            ("headers", Literal::Map(headers)) => {
+                for (name, value) in headers.iter() {
+                    request.headers.push((name.clone(), string(value, name)?));
+                }
+            }
+            ("timeout", Literal::Number(ms)) if *ms > 0.0 && ms.is_finite() => {
+                request.timeout = Duration::from_secs_f64(ms / 1000.0);
+            }
+            ("headers" | "timeout", _) => return Err(format!("Invalid fetch option '{key}'.")),
+            _ => return Err(format!("Unknown fetch option '{key}'.")),
+        }
+    }
+    Ok(request)
+}
+
+/// `(host, port, path)` of an `http://` URL.
+fn parse_url(url: &str) -> Result<(&str, u16, String), String> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        return Err(if url.starts_with("https://") {
+            "HTTPS is not supported.".to_string()
+        } else {
+            format!("Invalid URL '{url}'.")
+        });
+    };
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, ""),
+    };
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{path}")
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .map_err(|_| format!("Invalid port in URL '{url}'."))?,
+        ),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("Invalid URL '{url}'."));
+    }
+    Ok((host, port, path))
+}
+
+/// Sends a request and returns a map of the response's `status`,
+/// `headers`, with lowercase names, and `body`.
+fn fetch(context: &Context, args: &[Literal]) -> NativeResult {
+    let url = string(&args[0], "the URL")?;
+    let request = request(&args[1])?;
+    let (host, port, path) = parse_url(&url)?;
+    let failed = |err: std::io::Error| format!("Failed to fetch '{url}': {err}.");
+
+    // Wait no longer than the run has left.
+    let timeout = match context.deadline() {
+        Some(at) => request
+            .timeout
+            .min(at.saturating_duration_since(Instant::now()))
+            .max(Duration::from_millis(1)),
+        None => request.timeout,
+    };
+    let address = (host, port)
+        .to_socket_addrs()
+        .map_err(failed)?
+        .next()
+        .ok_or_else(|| format!("Failed to fetch '{url}': no address for '{host}'."))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout).map_err(failed)?;
+    stream.set_read_timeout(Some(timeout)).map_err(failed)?;
+    stream.set_write_timeout(Some(timeout)).map_err(failed)?;
+
+    let mut head = format!(
+        "{} {path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n",
+        request.method
+    );
+    for (name, value) in &request.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if !request.body.is_empty() {
+        head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).map_err(failed)?;
+    stream.write_all(request.body.as_bytes()).map_err(failed)?;
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).map_err(failed)?;
+    parse_response(&response).ok_or_else(|| format!("Invalid response from '{url}'."))
+}
+
+fn parse_response(response: &[u8]) -> Option<Literal> {
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&response[..end]).ok()?;
+    let body = &response[end + 4..];
+    let mut lines = head.split("\r\n");
+    let status: f64 = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+
+    let mut headers = BTreeMap::new();
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        headers
+            .entry(name.trim().to_lowercase())
+            .and_modify(|existing: &mut String| {
+                existing.push_str(", ");
+                existing.push_str(value.trim());
+            })
+            .or_insert_with(|| value.trim().to_string());
+    }
+    let body = match headers.get("transfer-encoding") {
+        Some(encoding) if encoding.eq_ignore_ascii_case("chunked") => dechunk(body)?,
+        _ => match headers.get("content-length") {
+            Some(length) => body.get(..length.parse::<usize>().ok()?)?.to_vec(),
+            None => body.to_vec(),
+        },
+    };
+
+    let headers = headers
+        .into_iter()
+        .map(|(name, value)| (name, Literal::String(value)))
+        .collect();
+    let entries = BTreeMap::from([
+        ("status".to_string(), Literal::Number(status)),
+        ("headers".to_string(), Literal::Map(Shared::new(headers))),
+        (
+            "body".to_string(),
+            Literal::String(String::from_utf8_lossy(&body).into_owned()),
+        ),
+    ]);
+    Some(Literal::Map(Shared::new(entries)))
+}
+
+/// The body of a chunked response without the chunk sizes.
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    loop {
+        let line_end = body.windows(2).position(|window| window == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = size.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(out);
+        }
+        out.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
@@ -0,0 +1,48 @@
+//! Math functions and constants. Angles are in radians.
+
+use super::Spec;
+use crate::grammar::*;
+
+pub const NATIVES: &[Spec] = &[
+    Spec::pure("sqrt", 1, |_, args| unary(args, f64::sqrt)),
+    Spec::pure("abs", 1, |_, args| unary(args, f64::abs)),
+    Spec::pure("floor", 1, |_, args| unary(args, f64::floor)),
+    Spec::pure("ceil", 1, |_, args| unary(args, f64::ceil)),
+    // Halves round away from zero.
+    Spec::pure("round", 1, |_, args| unary(args, f64::round)),
+    Spec::pure("min", 2, |_, args| binary(args, f64::min)),
+    Spec::pure("max", 2, |_, args| binary(args, f64::max)),
+    Spec::pure("pow", 2, |_, args| binary(args, f64::powf)),
+    Spec::pure("exp", 1, |_, args| unary(args, f64::exp)),
+    // Natural logarithm.
+    Spec::pure("log", 1, |_, args| unary(args, f64::ln)),
+    Spec::pure("sin", 1, |_, args| unary(args, f64::sin)),
+    Spec::pure("cos", 1, |_, args| unary(args, f64::cos)),
+    Spec::pure("tan", 1, |_, args| unary(args, f64::tan)),
+    Spec::pure("asin", 1, |_, args| unary(args, f64::asin)),
+    Spec::pure("acos", 1, |_, args| unary(args, f64::acos)),
+    Spec::pure("atan", 1, |_, args| unary(args, f64::atan)),
+    Spec::pure("atan2", 2, |_, args| binary(args, f64::atan2)),
+    Spec::pure("isNan", 1, |_, args| test(args, f64::is_nan)),
+    // False for NaN and both infinities.
+    Spec::pure("isFinite", 1, |_, args| test(args, f64::is_finite)),
+];
+
+/// Globals for the numbers that division by zero and the like produce.
+pub const CONSTANTS: &[(&str, f64)] = &[("Infinity", f64::INFINITY), ("NaN", f64::NAN)];
+
+fn unary(args: &[Literal], f: fn(f64) -> f64) -> NativeResult {
+    let x = f64::try_from(args[0].clone())?;
+    Ok(Literal::Number(f(x)))
+}
+
+fn binary(args: &[Literal], f: fn(f64, f64) -> f64) -> NativeResult {
+    let x = f64::try_from(args[0].clone())?;
+    let y = f64::try_from(args[1].clone())?;
+    Ok(Literal::Number(f(x, y)))
+}
+
+fn test(args: &[Literal], f: fn(f64) -> bool) -> NativeResult {
+    let x = f64::try_from(args[0].clone())?;
+    Ok(Literal::Boolean(f(x)))
+}
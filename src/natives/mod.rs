@@ -0,0 +1,263 @@
+//! The standard natives, grouped by topic in the submodules, and the
+//! sandboxes that decide which of them a script gets.
+
+use std::time::Instant;
+
+use crate::grammar::*;
+use crate::input::Input;
+use crate::output::Output;
+use crate::shared::{Lock, Shared};
+use random::Rng;
+
+mod assert;
+mod base;
+#[cfg(feature = "digest")]
+mod digest;
+mod encoding;
+#[cfg(feature = "fs")]
+mod files;
+mod format;
+mod hash;
+#[cfg(feature = "http")]
+mod http;
+mod math;
+mod random;
+mod reflect;
+mod strings;
+mod system;
+#[cfg(feature = "tcp")]
+mod tcp;
+
+/// What a native can reach outside the interpreter, which decides the
+/// sandboxes it is available in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    /// Nothing: computes from its arguments, or reads the clock.
+    Pure,
+    /// Reads files and directories.
+    FsRead,
+    /// Creates, changes or removes files and directories.
+    FsWrite,
+    /// Reads the interpreter's input, stdin by default.
+    Input,
+    /// Reads or changes environment variables and the working directory.
+    Env,
+    /// Runs other programs.
+    Process,
+    /// Connects to other machines.
+    Network,
+}
+
+/// The natives a script is given, picked by `--sandbox`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Sandbox {
+    /// Every native.
+    #[default]
+    None,
+    /// Only pure computation, for untrusted scripts.
+    Pure,
+    /// Pure computation and reading files.
+    FsRead,
+}
+
+impl Sandbox {
+    /// Parses the value of `--sandbox=`.
+    pub fn from_flag(value: &str) -> Option<Self> {
+        match value {
+            "none" => Some(Sandbox::None),
+            "pure" => Some(Sandbox::Pure),
+            "fs-read" => Some(Sandbox::FsRead),
+            _ => None,
+        }
+    }
+
+    pub fn allows(self, capability: Capability) -> bool {
+        match self {
+            Sandbox::None => true,
+            Sandbox::Pure => capability == Capability::Pure,
+            Sandbox::FsRead => matches!(capability, Capability::Pure | Capability::FsRead),
+        }
+    }
+}
+
+/// What a native asks of the interpreter or VM that called it, which it
+/// does once the native returns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// Unwind the run with this status.
+    Exit(i32),
+    /// End the run with this message, past any `catch`.
+    Panic(String),
+    /// Run this source where the call is, and return its value instead.
+    Eval(String),
+    /// Return a map of the global variables.
+    Globals,
+    /// Return a map of the variables in the scopes around the call.
+    Locals,
+    /// Return whether a variable is visible from the call.
+    Defined(String),
+}
+
+/// What natives can reach of the interpreter or VM running them. Clones
+/// share it.
+#[derive(Clone)]
+pub struct Context {
+    pub input: Input,
+    /// Where `print` writes to.
+    pub output: Shared<Lock<Output>>,
+    deadline: Shared<Lock<Option<Instant>>>,
+    rng: Shared<Lock<Rng>>,
+    request: Shared<Lock<Option<Request>>>,
+    compat: Shared<Lock<Compat>>,
+    format: Shared<Lock<OutputFormat>>,
+    #[cfg(feature = "tcp")]
+    sockets: Shared<Lock<tcp::Sockets>>,
+}
+
+impl Context {
+    pub fn new(input: Input, output: Output) -> Self {
+        Context {
+            input,
+            output: Shared::new(Lock::new(output)),
+            deadline: Shared::new(Lock::new(None)),
+            rng: Shared::new(Lock::new(Rng::from_clock())),
+            request: Shared::default(),
+            compat: Shared::default(),
+            format: Shared::default(),
+            #[cfg(feature = "tcp")]
+            sockets: Shared::default(),
+        }
+    }
+
+    /// Restarts the random numbers from `seed`.
+    pub fn seed(&self, seed: u64) {
+        *self.rng.lock() = Rng::new(seed);
+    }
+
+    /// When the run times out, for natives that wait to stop waiting.
+    pub fn set_deadline(&self, at: Option<Instant>) {
+        *self.deadline.lock() = at;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        *self.deadline.lock()
+    }
+
+    pub fn set_compat(&self, compat: Compat) {
+        *self.compat.lock() = compat;
+    }
+
+    pub fn set_output_format(&self, format: OutputFormat) {
+        *self.format.lock() = format;
+    }
+
+    /// `value` as `print` shows it.
+    pub fn show(&self, value: &Literal) -> String {
+        self.format.lock().show(value)
+    }
+
+    /// Whether `value` counts as true, as `!` takes it.
+    pub fn truthy(&self, value: &Literal) -> bool {
+        self.compat.lock().truthy(value)
+    }
+
+    /// What the native that just returned asked for, once.
+    pub fn take_request(&self) -> Option<Request> {
+        self.request.lock().take()
+    }
+
+    fn request(&self, request: Request) {
+        *self.request.lock() = Some(request);
+    }
+}
+
+struct Spec {
+    name: &'static str,
+    arity: usize,
+    /// Whether it also takes more than `arity` arguments.
+    variadic: bool,
+    capability: Capability,
+    /// Whether the result depends only on the arguments, so that record
+    /// and replay can leave the native alone.
+    deterministic: bool,
+    function: fn(&Context, &[Literal]) -> NativeResult,
+}
+
+impl Spec {
+    /// A native that computes from its arguments alone.
+    const fn pure(
+        name: &'static str,
+        arity: usize,
+        function: fn(&Context, &[Literal]) -> NativeResult,
+    ) -> Self {
+        Spec {
+            name,
+            arity,
+            variadic: false,
+            capability: Capability::Pure,
+            deterministic: true,
+            function,
+        }
+    }
+}
+
+const MODULES: &[&[Spec]] = &[
+    assert::NATIVES,
+    base::NATIVES,
+    #[cfg(feature = "digest")]
+    digest::NATIVES,
+    encoding::NATIVES,
+    #[cfg(feature = "fs")]
+    files::NATIVES,
+    format::NATIVES,
+    hash::NATIVES,
+    #[cfg(feature = "http")]
+    http::NATIVES,
+    math::NATIVES,
+    random::NATIVES,
+    reflect::NATIVES,
+    strings::NATIVES,
+    system::NATIVES,
+    #[cfg(feature = "tcp")]
+    tcp::NATIVES,
+];
+
+fn specs() -> impl Iterator<Item = &'static Spec> {
+    MODULES.iter().flat_map(|natives| natives.iter())
+}
+
+/// Natives every session starts with: `clock`, as in the reference
+/// implementation, and our own, along with constants such as `NaN`.
+pub fn standard(context: &Context) -> Vec<(&'static str, Literal)> {
+    let constants = math::CONSTANTS
+        .iter()
+        .map(|&(name, value)| (name, Literal::Number(value)));
+    specs()
+        .map(|spec| {
+            let context = context.clone();
+            let function = spec.function;
+            let call = move |args: &[Literal]| function(&context, args);
+            let native = if spec.variadic {
+                Literal::variadic(spec.name, spec.arity, call)
+            } else {
+                Literal::native(spec.name, spec.arity, call)
+            };
+            (spec.name, native)
+        })
+        .chain(constants)
+        .collect()
+}
+
+/// The standard natives `sandbox` leaves out.
+pub fn denied(sandbox: Sandbox) -> impl Iterator<Item = &'static str> {
+    specs()
+        .filter(move |spec| !sandbox.allows(spec.capability))
+        .map(|spec| spec.name)
+}
+
+/// The standard natives whose results [`crate::replay`] records.
+pub fn nondeterministic() -> impl Iterator<Item = &'static str> {
+    specs()
+        .filter(|spec| !spec.deterministic)
+        .map(|spec| spec.name)
+}
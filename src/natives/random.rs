@@ -0,0 +1,88 @@
+//! Pseudo-random numbers. Each interpreter has its own generator, seeded
+//! from the clock unless `--seed` or `setSeed` gives a seed, in which case
+//! the numbers are the same on every run.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Capability, Context, Spec};
+use crate::grammar::*;
+
+pub const NATIVES: &[Spec] = &[
+    Spec {
+        name: "random",
+        arity: 0,
+        variadic: false,
+        capability: Capability::Pure,
+        deterministic: false,
+        function: random,
+    },
+    Spec {
+        name: "randomInt",
+        arity: 2,
+        variadic: false,
+        capability: Capability::Pure,
+        deterministic: false,
+        function: random_int,
+    },
+    Spec::pure("setSeed", 1, set_seed),
+];
+
+/// SplitMix64: small, fast and good enough for simulations and games, not
+/// for anything that needs to be unpredictable.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn from_clock() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_nanos() as u64);
+        Rng(now)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn integer(value: &Literal) -> Result<f64, String> {
+    match value {
+        Literal::Number(n) if n.fract() == 0.0 => Ok(*n),
+        Literal::Number(_) => Err("Expected a whole number.".to_string()),
+        other => Err(format!("Expected number but got {}.", other.type_name())),
+    }
+}
+
+/// A number in `[0, 1)`.
+fn random(context: &Context, _: &[Literal]) -> NativeResult {
+    Ok(Literal::Number(context.rng.lock().next_f64()))
+}
+
+/// `randomInt(lo, hi)`: a whole number from `lo` to `hi`, both included.
+fn random_int(context: &Context, args: &[Literal]) -> NativeResult {
+    let lo = integer(&args[0])?;
+    let hi = integer(&args[1])?;
+    if lo > hi {
+        return Err(format!("randomInt() needs lo <= hi, got {lo} and {hi}."));
+    }
+    let offset = (context.rng.lock().next_f64() * (hi - lo + 1.0)).floor();
+    Ok(Literal::Number(lo + offset))
+}
+
+fn set_seed(context: &Context, args: &[Literal]) -> NativeResult {
+    let seed = integer(&args[0])?;
+    context.seed(seed as i64 as u64);
+    Ok(Literal::Nil)
+}
@@ -0,0 +1,32 @@
+//! Looking at the variables of the running script, for debugging helpers
+//! and scripts that adapt to what is defined. The interpreter or VM
+//! answers these, since natives cannot see its scopes.
+
+use super::{Context, Request, Spec};
+use crate::grammar::*;
+
+pub const NATIVES: &[Spec] = &[
+    Spec::pure("globals", 0, globals),
+    Spec::pure("locals", 0, locals),
+    Spec::pure("defined", 1, defined),
+];
+
+/// A map of the global variables.
+fn globals(context: &Context, _: &[Literal]) -> NativeResult {
+    context.request(Request::Globals);
+    Ok(Literal::Nil)
+}
+
+/// A map of the variables declared in blocks around the call, inner ones
+/// shadowing outer ones. Only the tree-walker supports it.
+fn locals(context: &Context, _: &[Literal]) -> NativeResult {
+    context.request(Request::Locals);
+    Ok(Literal::Nil)
+}
+
+/// Whether a variable is visible from the call. The VM only sees globals.
+fn defined(context: &Context, args: &[Literal]) -> NativeResult {
+    let name = String::try_from(args[0].clone())?;
+    context.request(Request::Defined(name));
+    Ok(Literal::Nil)
+}
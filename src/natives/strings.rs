@@ -0,0 +1,110 @@
+//! String functions. Lengths and positions count characters, not bytes:
+//!
+//! ```
+//! # use rustyinterpreter::{grammar::Literal, Lox};
+//! let mut lox = Lox::new();
+//! let mut eval = |source| lox.evaluate(source, [("s", "ünïcödé")]).unwrap();
+//! assert_eq!(eval("len(s)"), Literal::Number(7.0));
+//! assert_eq!(eval("upper(substr(s, 2, 3))"), Literal::from("ÏCÖ"));
+//! assert_eq!(eval(r#"split("日本語", "")"#), Literal::from(vec!["日", "本", "語"]));
+//! assert_eq!(eval(r#"replace(s, "ö", "o")"#), Literal::from("ünïcodé"));
+//! ```
+
+use super::{Context, Spec};
+use crate::grammar::*;
+use crate::shared::Shared;
+
+pub const NATIVES: &[Spec] = &[
+    Spec::pure("len", 1, len),
+    Spec::pure("upper", 1, upper),
+    Spec::pure("lower", 1, lower),
+    Spec::pure("substr", 3, substr),
+    Spec::pure("split", 2, split),
+    Spec::pure("trim", 1, trim),
+    Spec::pure("replace", 3, replace),
+    Spec::pure("contains", 2, contains),
+];
+
+fn string(value: &Literal) -> Result<&str, String> {
+    match value {
+        Literal::String(s) => Ok(s),
+        other => Err(format!("Expected string but got {}.", other.type_name())),
+    }
+}
+
+/// A whole, non-negative number, for positions and counts.
+pub fn count(value: &Literal, what: &str) -> Result<usize, String> {
+    match value {
+        Literal::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        Literal::Number(_) => Err(format!("{what} must be a whole number of at least 0.")),
+        other => Err(format!("Expected number but got {}.", other.type_name())),
+    }
+}
+
+/// Characters in a string, items in a list or entries in a map.
+fn len(_: &Context, args: &[Literal]) -> NativeResult {
+    let len = match &args[0] {
+        Literal::List(items) => items.len(),
+        Literal::Map(entries) => entries.len(),
+        other => string(other)?.chars().count(),
+    };
+    Ok(Literal::Number(len as f64))
+}
+
+fn upper(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(Literal::String(string(&args[0])?.to_uppercase()))
+}
+
+fn lower(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(Literal::String(string(&args[0])?.to_lowercase()))
+}
+
+/// `substr(s, start, length)`: up to `length` characters from `start`. A
+/// range running past the end stops there.
+fn substr(_: &Context, args: &[Literal]) -> NativeResult {
+    let s = string(&args[0])?;
+    let start = count(&args[1], "Start")?;
+    let length = count(&args[2], "Length")?;
+    Ok(Literal::String(
+        s.chars().skip(start).take(length).collect(),
+    ))
+}
+
+/// The pieces of `s` between occurrences of `separator`, or its characters
+/// when the separator is empty.
+fn split(_: &Context, args: &[Literal]) -> NativeResult {
+    let s = string(&args[0])?;
+    let separator = string(&args[1])?;
+    let pieces = if separator.is_empty() {
+        s.chars().map(|c| Literal::String(c.to_string())).collect()
+    } else {
+        s.split(separator)
+            .map(|piece| Literal::String(piece.to_string()))
+            .collect()
+    };
+    Ok(Literal::List(Shared::new(pieces)))
+}
+
+fn trim(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(Literal::String(string(&args[0])?.trim().to_string()))
+}
+
+/// `replace(s, from, to)`: every occurrence of `from` replaced.
+fn replace(_: &Context, args: &[Literal]) -> NativeResult {
+    let s = string(&args[0])?;
+    let from = string(&args[1])?;
+    let to = string(&args[2])?;
+    if from.is_empty() {
+        return Err("Cannot replace an empty string.".to_string());
+    }
+    Ok(Literal::String(s.replace(from, to)))
+}
+
+/// Whether a string contains another, or a list contains a value.
+fn contains(_: &Context, args: &[Literal]) -> NativeResult {
+    let found = match &args[0] {
+        Literal::List(items) => items.contains(&args[1]),
+        other => string(other)?.contains(string(&args[1])?),
+    };
+    Ok(Literal::Boolean(found))
+}
@@ -0,0 +1,121 @@
+//! The environment the script runs in: variables, the working directory,
+//! the platform and other programs.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::process::Command;
+
+use super::{Capability, Context, Spec};
+use crate::grammar::*;
+use crate::shared::Shared;
+
+pub const NATIVES: &[Spec] = &[
+    Spec {
+        name: "getEnv",
+        arity: 1,
+        variadic: false,
+        capability: Capability::Env,
+        deterministic: false,
+        function: get_env,
+    },
+    Spec {
+        name: "setEnv",
+        arity: 2,
+        variadic: false,
+        capability: Capability::Env,
+        deterministic: true,
+        function: set_env,
+    },
+    Spec {
+        name: "cwd",
+        arity: 0,
+        variadic: false,
+        capability: Capability::Env,
+        deterministic: false,
+        function: cwd,
+    },
+    Spec::pure("platform", 0, platform),
+    Spec {
+        name: "exec",
+        arity: 2,
+        variadic: false,
+        capability: Capability::Process,
+        deterministic: false,
+        function: exec,
+    },
+];
+
+fn name(value: &Literal) -> Result<&str, String> {
+    match value {
+        Literal::String(name) if name.is_empty() || name.contains(['=', '\0']) => {
+            Err(format!("Invalid environment variable name '{name}'."))
+        }
+        Literal::String(name) => Ok(name),
+        other => Err(format!("Expected string but got {}.", other.type_name())),
+    }
+}
+
+/// The variable's value, or nil when it is not set.
+fn get_env(_: &Context, args: &[Literal]) -> NativeResult {
+    Ok(env::var(name(&args[0])?).map_or(Literal::Nil, Literal::String))
+}
+
+/// Sets a variable for the rest of the process, or unsets it when the value
+/// is nil.
+fn set_env(_: &Context, args: &[Literal]) -> NativeResult {
+    let name = name(&args[0])?;
+    match &args[1] {
+        Literal::Nil => env::remove_var(name),
+        Literal::String(value) if value.contains('\0') => {
+            return Err(format!("Invalid value for environment variable '{name}'."))
+        }
+        Literal::String(value) => env::set_var(name, value),
+        other => return Err(format!("Expected string but got {}.", other.type_name())),
+    }
+    Ok(Literal::Nil)
+}
+
+/// The working directory, which relative paths start from.
+fn cwd(_: &Context, _: &[Literal]) -> NativeResult {
+    env::current_dir()
+        .map(|dir| Literal::String(dir.to_string_lossy().into_owned()))
+        .map_err(|err| format!("Cannot get the working directory: {err}."))
+}
+
+/// The operating system, such as "linux", "macos" or "windows".
+fn platform(_: &Context, _: &[Literal]) -> NativeResult {
+    Ok(Literal::String(env::consts::OS.to_string()))
+}
+
+/// Runs a program with a list of arguments, or nil for none, and waits for
+/// it, returning a map of its `stdout`, `stderr` and exit `code`, which is
+/// nil when it was killed by a signal. The program gets no input.
+fn exec(_: &Context, args: &[Literal]) -> NativeResult {
+    let program = match &args[0] {
+        Literal::String(program) => program,
+        other => return Err(format!("Expected string but got {}.", other.type_name())),
+    };
+    let arguments = match &args[1] {
+        Literal::List(items) => items
+            .iter()
+            .map(|item| String::try_from(item.clone()))
+            .collect::<Result<Vec<_>, _>>()?,
+        Literal::Nil => vec![],
+        other => return Err(format!("Expected list but got {}.", other.type_name())),
+    };
+    let output = Command::new(program)
+        .args(arguments)
+        .output()
+        .map_err(|err| format!("Cannot run '{program}': {err}."))?;
+    let text = |bytes: Vec<u8>| Literal::String(String::from_utf8_lossy(&bytes).into_owned());
+    let code = output
+        .status
+        .code()
+        .map_or(Literal::Nil, |code| Literal::Number(code as f64));
+    let entries = BTreeMap::from([
+        ("stdout".to_string(), text(output.stdout)),
+        ("stderr".to_string(), text(output.stderr)),
+        ("code".to_string(), code),
+    ]);
+    Ok(Literal::Map(Shared::new(entries)))
+}
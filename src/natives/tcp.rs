@@ -0,0 +1,188 @@
+//! TCP sockets, behind the `tcp` feature. Sockets are numbers handed out by
+//! `tcpConnect`, `tcpListen` and `tcpAccept`, and stay open until `close`d
+//! or the interpreter is dropped. Waiting in `recv` and `tcpAccept` stops
+//! on Ctrl-C and when the run times out.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{strings, Capability, Context, Spec};
+use crate::grammar::*;
+use crate::interrupt;
+
+pub const NATIVES: &[Spec] = &[
+    network("tcpConnect", 2, tcp_connect),
+    network("tcpListen", 2, tcp_listen),
+    network("tcpAccept", 1, tcp_accept),
+    network("send", 2, send),
+    network("recv", 2, recv),
+    network("close", 1, close),
+];
+
+const fn network(
+    name: &'static str,
+    arity: usize,
+    function: fn(&Context, &[Literal]) -> NativeResult,
+) -> Spec {
+    Spec {
+        name,
+        arity,
+        variadic: false,
+        capability: Capability::Network,
+        deterministic: false,
+        function,
+    }
+}
+
+/// How long to block at a time, so that Ctrl-C is noticed promptly.
+const SLICE: Duration = Duration::from_millis(10);
+
+enum Socket {
+    Stream(TcpStream),
+    Listener(TcpListener),
+}
+
+/// The sockets of one interpreter, by number.
+#[derive(Default)]
+pub struct Sockets {
+    next: u64,
+    open: HashMap<u64, Socket>,
+}
+
+impl Sockets {
+    fn add(&mut self, socket: Socket) -> Literal {
+        self.next += 1;
+        self.open.insert(self.next, socket);
+        Literal::Number(self.next as f64)
+    }
+}
+
+fn handle(value: &Literal) -> Result<u64, String> {
+    match value {
+        Literal::Number(n) if *n >= 1.0 && n.fract() == 0.0 => Ok(*n as u64),
+        Literal::Number(_) => Err("Invalid socket.".to_string()),
+        other => Err(format!("Expected socket but got {}.", other.type_name())),
+    }
+}
+
+fn address(args: &[Literal]) -> Result<(String, u16), String> {
+    let host = String::try_from(args[0].clone())?;
+    match &args[1] {
+        Literal::Number(port) if (0.0..=65535.0).contains(port) && port.fract() == 0.0 => {
+            Ok((host, *port as u16))
+        }
+        Literal::Number(_) => Err("Port must be a whole number from 0 to 65535.".to_string()),
+        other => Err(format!("Expected number but got {}.", other.type_name())),
+    }
+}
+
+/// Whether to stop waiting: on Ctrl-C, or when the run times out. The
+/// caller then raises the error.
+fn stop(context: &Context) -> bool {
+    interrupt::pending()
+        || context
+            .deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+fn stream(context: &Context, value: &Literal) -> Result<TcpStream, String> {
+    let handle = handle(value)?;
+    match context.sockets.lock().open.get(&handle) {
+        Some(Socket::Stream(stream)) => stream.try_clone().map_err(|err| err.to_string()),
+        Some(Socket::Listener(_)) => Err("Cannot send or receive on a listening socket.".into()),
+        None => Err(format!("Socket {handle} is not open.")),
+    }
+}
+
+/// Connects to `port` on `host`.
+fn tcp_connect(context: &Context, args: &[Literal]) -> NativeResult {
+    let (host, port) = address(args)?;
+    let stream = TcpStream::connect((host.as_str(), port))
+        .map_err(|err| format!("Cannot connect to {host}:{port}: {err}."))?;
+    Ok(context.sockets.lock().add(Socket::Stream(stream)))
+}
+
+/// Listens on `port` of `host`, such as "127.0.0.1" or "0.0.0.0".
+fn tcp_listen(context: &Context, args: &[Literal]) -> NativeResult {
+    let (host, port) = address(args)?;
+    let listener = TcpListener::bind((host.as_str(), port))
+        .map_err(|err| format!("Cannot listen on {host}:{port}: {err}."))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|err| err.to_string())?;
+    Ok(context.sockets.lock().add(Socket::Listener(listener)))
+}
+
+/// Waits for a connection to a listening socket and returns its socket.
+fn tcp_accept(context: &Context, args: &[Literal]) -> NativeResult {
+    let handle = handle(&args[0])?;
+    let listener = match context.sockets.lock().open.get(&handle) {
+        Some(Socket::Listener(listener)) => listener.try_clone().map_err(|err| err.to_string())?,
+        Some(Socket::Stream(_)) => return Err("Can only accept on a listening socket.".into()),
+        None => return Err(format!("Socket {handle} is not open.")),
+    };
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream
+                    .set_nonblocking(false)
+                    .map_err(|err| err.to_string())?;
+                return Ok(context.sockets.lock().add(Socket::Stream(stream)));
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                if stop(context) {
+                    return Ok(Literal::Nil);
+                }
+                thread::sleep(SLICE);
+            }
+            Err(err) => return Err(format!("Failed to accept a connection: {err}.")),
+        }
+    }
+}
+
+/// Writes the whole string.
+fn send(context: &Context, args: &[Literal]) -> NativeResult {
+    let mut stream = stream(context, &args[0])?;
+    let data = String::try_from(args[1].clone())?;
+    stream
+        .write_all(data.as_bytes())
+        .map_err(|err| format!("Failed to send: {err}."))?;
+    Ok(Literal::Nil)
+}
+
+/// Waits for data and returns up to `max` bytes of it, or "" once the other
+/// end has closed the connection.
+fn recv(context: &Context, args: &[Literal]) -> NativeResult {
+    let mut stream = stream(context, &args[0])?;
+    let max = strings::count(&args[1], "The byte count")?;
+    if max == 0 {
+        return Err("recv() needs to read at least 1 byte.".to_string());
+    }
+    stream
+        .set_read_timeout(Some(SLICE))
+        .map_err(|err| err.to_string())?;
+    let mut buf = vec![0; max];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(n) => return Ok(Literal::String(String::from_utf8_lossy(&buf[..n]).into())),
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                if stop(context) {
+                    return Ok(Literal::Nil);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(format!("Failed to receive: {err}.")),
+        }
+    }
+}
+
+fn close(context: &Context, args: &[Literal]) -> NativeResult {
+    let handle = handle(&args[0])?;
+    match context.sockets.lock().open.remove(&handle) {
+        Some(_) => Ok(Literal::Nil),
+        None => Err(format!("Socket {handle} is not open.")),
+    }
+}
@@ -0,0 +1,198 @@
+use crate::chunk::{Chunk, Op};
+use crate::grammar::{Literal, Span};
+
+/// Peephole pass over a compiled chunk. Each instruction is appended to the
+/// output and the tail is rewritten for as long as a pattern matches, so
+/// folds cascade (`- - 1`, `1 + 2 * 3`). Only instructions that cannot fail
+/// are folded, which keeps runtime errors and their lines unchanged.
+/// Whatever is left is then fused into superinstructions where possible.
+///
+/// The chunk has no jumps yet; once it does, rewrites must not span a jump
+/// target.
+pub fn optimize(chunk: &mut Chunk) {
+    let code = std::mem::take(&mut chunk.code);
+    let lines = std::mem::take(&mut chunk.lines);
+    let spans = std::mem::take(&mut chunk.spans);
+    let mut out: Vec<(Op, usize, Span)> = Vec::with_capacity(code.len());
+    for ((op, line), span) in code.into_iter().zip(lines).zip(spans) {
+        out.push((op, line, span));
+        while reduce(&mut out, &mut chunk.constants) {}
+    }
+    for (op, line, span) in out {
+        chunk.write(op, line, span);
+    }
+    compact_constants(chunk);
+}
+
+fn reduce(out: &mut Vec<(Op, usize, Span)>, constants: &mut Vec<Literal>) -> bool {
+    let n = out.len();
+    let (line, span) = (out[n - 1].1, out[n - 1].2);
+    let value = |op: Op, constants: &[Literal]| match op {
+        Op::Constant(index) => Some(constants[index as usize].clone()),
+        Op::Nil => Some(Literal::Nil),
+        Op::True => Some(Literal::Boolean(true)),
+        Op::False => Some(Literal::Boolean(false)),
+        _ => None,
+    };
+
+    // A pure push immediately discarded.
+    if n >= 2 && out[n - 1].0 == Op::Pop && is_pure_push(out[n - 2].0) {
+        out.truncate(n - 2);
+        return true;
+    }
+
+    // Unary operators on a constant.
+    if n >= 2 {
+        if let Some(operand) = value(out[n - 2].0, constants) {
+            let folded = match (out[n - 1].0, operand) {
+                // Only where `--compat=` makes no difference, as chunks are
+                // cached without it.
+                (Op::Not, Literal::Boolean(b)) => Some(Literal::Boolean(!b)),
+                (Op::Not, Literal::Nil) => Some(Literal::Boolean(true)),
+                (Op::Negate, Literal::Number(x)) => Some(Literal::Number(-x)),
+                _ => None,
+            };
+            if let Some(op) = folded.and_then(|folded| push(folded, constants)) {
+                out.truncate(n - 2);
+                out.push((op, line, span));
+                return true;
+            }
+        }
+    }
+
+    // Binary operators on two constants.
+    if n >= 3 {
+        if let (Some(left), Some(right)) = (
+            value(out[n - 3].0, constants),
+            value(out[n - 2].0, constants),
+        ) {
+            let folded = match (out[n - 1].0, left, right) {
+                (Op::Add, Literal::Number(l), Literal::Number(r)) => Some(Literal::Number(l + r)),
+                (Op::Add, Literal::String(l), Literal::String(r)) => {
+                    Some(Literal::String(format!("{}{}", l, r)))
+                }
+                (Op::Subtract, Literal::Number(l), Literal::Number(r)) => {
+                    Some(Literal::Number(l - r))
+                }
+                (Op::Multiply, Literal::Number(l), Literal::Number(r)) => {
+                    Some(Literal::Number(l * r))
+                }
+                // Dividing by zero is left to run time, which may reject it.
+                (Op::Divide, Literal::Number(l), Literal::Number(r)) if r != 0.0 => {
+                    Some(Literal::Number(l / r))
+                }
+                (Op::Equal, l, r) => Some(Literal::Boolean(l == r)),
+                (Op::Greater, Literal::Number(l), Literal::Number(r)) => {
+                    Some(Literal::Boolean(l > r))
+                }
+                (Op::GreaterEqual, Literal::Number(l), Literal::Number(r)) => {
+                    Some(Literal::Boolean(l >= r))
+                }
+                (Op::Less, Literal::Number(l), Literal::Number(r)) => Some(Literal::Boolean(l < r)),
+                (Op::LessEqual, Literal::Number(l), Literal::Number(r)) => {
+                    Some(Literal::Boolean(l <= r))
+                }
+                _ => None,
+            };
+            if let Some(op) = folded.and_then(|folded| push(folded, constants)) {
+                out.truncate(n - 3);
+                out.push((op, line, span));
+                return true;
+            }
+        }
+    }
+
+    // Superinstructions. These run after folding so `1 + 2` still folds, and
+    // take the position of the instruction that can fail.
+    if n >= 2 {
+        let fused = match (out[n - 2].0, out[n - 1].0) {
+            (Op::GetLocal(slot), Op::Add) => Some(Op::AddLocal(slot)),
+            (Op::Constant(index), Op::Add) => Some(Op::AddConstant(index)),
+            (Op::Equal, Op::Not) => Some(Op::NotEqual),
+            _ => None,
+        };
+        if let Some(op) = fused {
+            out.truncate(n - 2);
+            out.push((op, line, span));
+            return true;
+        }
+    }
+
+    false
+}
+
+fn is_pure_push(op: Op) -> bool {
+    matches!(
+        op,
+        Op::Constant(_) | Op::Nil | Op::True | Op::False | Op::GetLocal(_)
+    )
+}
+
+/// The instruction pushing `value`, reusing an existing constant slot.
+fn push(value: Literal, constants: &mut Vec<Literal>) -> Option<Op> {
+    match value {
+        Literal::Nil => Some(Op::Nil),
+        Literal::Boolean(true) => Some(Op::True),
+        Literal::Boolean(false) => Some(Op::False),
+        value => {
+            let index = constants
+                .iter()
+                .position(|existing| same_constant(existing, &value))
+                .unwrap_or_else(|| {
+                    constants.push(value);
+                    constants.len() - 1
+                });
+            u16::try_from(index).ok().map(Op::Constant)
+        }
+    }
+}
+
+fn same_constant(a: &Literal, b: &Literal) -> bool {
+    match (a, b) {
+        (Literal::Number(a), Literal::Number(b)) => a.to_bits() == b.to_bits(),
+        (Literal::String(a), Literal::String(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Drops constants no instruction refers to any more and renumbers the rest.
+fn compact_constants(chunk: &mut Chunk) {
+    let mut used = vec![false; chunk.constants.len()];
+    for op in &chunk.code {
+        if let Some(index) = constant_operand(*op) {
+            used[index as usize] = true;
+        }
+    }
+    let mut remap = vec![0u16; chunk.constants.len()];
+    let mut constants = vec![];
+    for (index, value) in std::mem::take(&mut chunk.constants).into_iter().enumerate() {
+        if used[index] {
+            remap[index] = constants.len() as u16;
+            constants.push(value);
+        }
+    }
+    chunk.constants = constants;
+    for op in &mut chunk.code {
+        *op = match *op {
+            Op::Constant(i) => Op::Constant(remap[i as usize]),
+            Op::AddConstant(i) => Op::AddConstant(remap[i as usize]),
+            Op::GetGlobal(i) => Op::GetGlobal(remap[i as usize]),
+            Op::DefineGlobal(i) => Op::DefineGlobal(remap[i as usize]),
+            Op::DefineConstant(i) => Op::DefineConstant(remap[i as usize]),
+            Op::SetGlobal(i) => Op::SetGlobal(remap[i as usize]),
+            op => op,
+        };
+    }
+}
+
+fn constant_operand(op: Op) -> Option<u16> {
+    match op {
+        Op::Constant(i)
+        | Op::AddConstant(i)
+        | Op::GetGlobal(i)
+        | Op::DefineGlobal(i)
+        | Op::DefineConstant(i)
+        | Op::SetGlobal(i) => Some(i),
+        _ => None,
+    }
+}
@@ -0,0 +1,86 @@
+use std::io::{self, Write};
+
+use crate::shared::{Lock, MaybeSend, Shared};
+
+/// When `print` output is flushed to the writer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flush {
+    /// After every line, so output shows up as soon as it is printed.
+    Line,
+    /// Only when a run ends, successfully or not. Cheaper for writers that
+    /// buffer, such as a `BufWriter` around a file or socket.
+    End,
+}
+
+#[cfg(not(feature = "sync"))]
+pub(crate) type Writer = dyn Write;
+#[cfg(feature = "sync")]
+pub(crate) type Writer = dyn Write + Send;
+
+/// Where `print` writes to.
+pub struct Output {
+    writer: Box<Writer>,
+    flush: Flush,
+}
+
+impl Output {
+    pub fn new(writer: impl Write + MaybeSend + 'static, flush: Flush) -> Self {
+        Output {
+            writer: Box::new(writer),
+            flush,
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(io::stdout(), Flush::Line)
+    }
+
+    pub fn print(&mut self, text: &str) -> io::Result<()> {
+        writeln!(self.writer, "{text}")?;
+        if self.flush == Flush::Line {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes `text` without a line ending. With [`Flush::Line`] it is
+    /// flushed straight away, so that prompts show up before input is read.
+    pub fn write(&mut self, text: &str) -> io::Result<()> {
+        write!(self.writer, "{text}")?;
+        if self.flush == Flush::Line {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A writer that keeps what is written for reading back, for capturing
+/// output in tests and hosts. Clones share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct Capture(Shared<Lock<Vec<u8>>>);
+
+impl Capture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock()).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,50 +1,288 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::codes::Code;
+use crate::diagnostics::Diagnostic;
 use crate::grammar::*;
 
+/// As in the reference implementation, so that the bytecode can keep the
+/// count in a byte.
+const MAX_ARGUMENTS: usize = 255;
+
 pub struct Parser<'a> {
     tokens: &'a [Token],
     current: usize,
+    /// Errors recovered from so far, when parsing with [`Self::parse_all`].
+    errors: Option<Vec<Diagnostic>>,
+    /// The names declared in each enclosing scope, innermost last, and
+    /// whether they are constants, so that assigning to one is reported
+    /// here. Constants from earlier sources are left to run time.
+    scopes: Vec<HashMap<String, bool>>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a [Token]) -> Self {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            current: 0,
+            errors: None,
+            scopes: vec![HashMap::new()],
+        }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Statement>, String> {
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Diagnostic> {
         let mut statements = vec![];
         while !self.end() {
-            statements.push(self.statement()?);
+            statements.extend(self.declaration()?);
         }
         Ok(statements)
     }
 
-    fn statement(&mut self) -> Result<Statement, String> {
+    /// Like [`Self::parse`], but after an error skips to the next statement
+    /// and carries on, so that every syntax error is reported.
+    pub fn parse_all(&mut self) -> Result<Vec<Statement>, Vec<Diagnostic>> {
+        self.errors = Some(vec![]);
+        let statements = self.parse().map_err(|diagnostic| vec![diagnostic])?;
+        match self.errors.take() {
+            Some(errors) if !errors.is_empty() => Err(errors),
+            _ => Ok(statements),
+        }
+    }
+
+    /// A statement, or nothing if it had an error that was recovered from.
+    fn declaration(&mut self) -> Result<Option<Statement>, Diagnostic> {
+        match self.statement() {
+            Ok(statement) => Ok(Some(statement)),
+            Err(diagnostic) => match &mut self.errors {
+                Some(errors) => {
+                    errors.push(diagnostic);
+                    self.synchronize();
+                    Ok(None)
+                }
+                None => Err(diagnostic),
+            },
+        }
+    }
+
+    /// Skips past the statement an error happened in: to just after the next
+    /// `;`, or to the next token that starts or ends a statement.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.end() {
+            if self.previous().token_type == TokenType::SEMICOLON {
+                return;
+            }
+            match self.peek().token_type {
+                TokenType::VAR
+                | TokenType::CONST
+                | TokenType::PRINT
+                | TokenType::DEFER
+                | TokenType::THROW
+                | TokenType::TRY
+                | TokenType::LEFT_BRACE
+                | TokenType::RIGHT_BRACE => return,
+                _ => self.advance(),
+            };
+        }
+    }
+
+    /// Input that must be exactly one expression, with nothing after it.
+    pub fn complete_expression(&mut self) -> Result<Expression, Diagnostic> {
+        let expression = self.expression()?;
+        if !self.end() {
+            return Err(self.error(self.peek(), Code::ExpectToken, "Expect end of expression."));
+        }
+        Ok(expression)
+    }
+
+    /// A lone expression without the trailing `;`, as typed at the REPL,
+    /// or with only the one optional semicolons add.
+    pub fn bare_expression(&mut self) -> Option<Expression> {
+        let expression = self.expression().ok()?;
+        if self.peek().token_type == TokenType::SEMICOLON && self.peek().lexeme.is_empty() {
+            self.advance();
+        }
+        self.end().then_some(expression)
+    }
+
+    fn statement(&mut self) -> Result<Statement, Diagnostic> {
         if self.match_(&[TokenType::VAR]) {
-            return self.variable();
+            self.variable(false)
+        } else if self.match_(&[TokenType::CONST]) {
+            self.variable(true)
         } else if self.match_(&[TokenType::PRINT]) {
-            let expression = self.expression()?;
-            self.consume(&TokenType::SEMICOLON, "Expect ';' after value.")?;
-            Ok(Statement::Print(expression))
-        } else if self.match_(&[TokenType::LEFT_BRACE]) {
-            let mut statements = vec![];
-            while !self.is_cur_match(&TokenType::RIGHT_BRACE) && !self.end() {
-                statements.push(self.statement()?);
+            let start = self.current - 1;
+            match self.print_statement() {
+                // Such as `print(a, b);`, which can only be a call of the
+                // native.
+                Err(diagnostic) if self.tokens[start + 1].token_type == TokenType::LEFT_PAREN => {
+                    self.current = start;
+                    self.expression_statement().map_err(|_| diagnostic)
+                }
+                result => result,
             }
-            self.consume(&TokenType::RIGHT_BRACE, "Expect '}' after block.")?;
-            Ok(Statement::Block(statements))
+        } else if self.at_import() {
+            self.import()
+        } else if self.match_(&[TokenType::DEFER]) {
+            let keyword = self.previous().clone();
+            let expr = self.expression()?;
+            self.consume(
+                &TokenType::SEMICOLON,
+                "Expect ';' after deferred expression.",
+            )?;
+            Ok(Statement::Defer { keyword, expr })
+        } else if self.match_(&[TokenType::THROW]) {
+            let keyword = self.previous().clone();
+            let value = self.expression()?;
+            self.consume(&TokenType::SEMICOLON, "Expect ';' after thrown value.")?;
+            Ok(Statement::Throw { keyword, value })
+        } else if self.match_(&[TokenType::TRY]) {
+            self.try_statement()
+        } else if self.match_(&[TokenType::LEFT_BRACE]) {
+            let line = self.previous().line_num;
+            let statements = self.block()?;
+            Ok(Statement::Block { statements, line })
         } else {
-            let expression = self.expression()?;
-            self.consume(&TokenType::SEMICOLON, "Expect ';' after expression.")?;
-            Ok(Statement::Expression(expression))
+            self.expression_statement()
+        }
+    }
+
+    /// A `print` statement whose keyword was just consumed.
+    fn print_statement(&mut self) -> Result<Statement, Diagnostic> {
+        let line = self.previous().line_num;
+        let expr = self.expression()?;
+        self.consume(&TokenType::SEMICOLON, "Expect ';' after value.")?;
+        Ok(Statement::Print { expr, line })
+    }
+
+    fn expression_statement(&mut self) -> Result<Statement, Diagnostic> {
+        let line = self.peek().line_num;
+        let expr = self.expression()?;
+        self.consume(&TokenType::SEMICOLON, "Expect ';' after expression.")?;
+        Ok(Statement::Expression { expr, line })
+    }
+
+    /// The statements of a block whose `{` was just consumed.
+    fn block(&mut self) -> Result<Vec<Statement>, Diagnostic> {
+        self.scopes.push(HashMap::new());
+        let statements = self.block_statements();
+        self.scopes.pop();
+        statements
+    }
+
+    fn block_statements(&mut self) -> Result<Vec<Statement>, Diagnostic> {
+        let mut statements = vec![];
+        while !self.is_cur_match(&TokenType::RIGHT_BRACE) && !self.end() {
+            statements.extend(self.declaration()?);
         }
+        self.consume(&TokenType::RIGHT_BRACE, "Expect '}' after block.")?;
+        Ok(statements)
     }
 
-    fn variable(&mut self) -> Result<Statement, String> {
+    fn try_statement(&mut self) -> Result<Statement, Diagnostic> {
+        let line = self.previous().line_num;
+        self.consume(&TokenType::LEFT_BRACE, "Expect '{' after 'try'.")?;
+        let body = self.block()?;
+        let mut catches = vec![];
+        while self.match_(&[TokenType::CATCH]) {
+            self.consume(&TokenType::LEFT_PAREN, "Expect '(' after 'catch'.")?;
+            let name = self
+                .consume(&TokenType::IDENTIFIER, "Expect variable name after '('.")?
+                .clone();
+            let error_type = if self.match_(&[TokenType::COLON]) {
+                let error_type =
+                    self.consume(&TokenType::IDENTIFIER, "Expect error type after ':'.")?;
+                Some(error_type.clone())
+            } else {
+                None
+            };
+            self.consume(&TokenType::RIGHT_PAREN, "Expect ')' after catch variable.")?;
+            self.consume(&TokenType::LEFT_BRACE, "Expect '{' after catch clause.")?;
+            self.scopes
+                .push(HashMap::from([(name.lexeme.clone(), false)]));
+            let body = self.block();
+            self.scopes.pop();
+            catches.push(Catch {
+                name,
+                error_type,
+                body: body?,
+            });
+        }
+        let finally = if self.match_(&[TokenType::FINALLY]) {
+            self.consume(&TokenType::LEFT_BRACE, "Expect '{' after 'finally'.")?;
+            Some(self.block()?)
+        } else {
+            None
+        };
+        if catches.is_empty() && finally.is_none() {
+            return Err(self.error(
+                self.peek(),
+                Code::ExpectToken,
+                "Expect 'catch' or 'finally' after try block.",
+            ));
+        }
+        Ok(Statement::Try {
+            body,
+            catches,
+            finally,
+            line,
+        })
+    }
+
+    /// `import` is not a keyword, so that existing scripts can keep using it
+    /// as a name: it starts an import only when a path follows.
+    fn at_import(&self) -> bool {
+        self.peek().token_type == TokenType::IDENTIFIER
+            && self.peek().lexeme == "import"
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|token| token.token_type == TokenType::STRING)
+    }
+
+    fn import(&mut self) -> Result<Statement, Diagnostic> {
+        let line = self.advance().line_num;
+        let path = match &self.advance().literal {
+            Some(Literal::String(path)) => path.clone(),
+            _ => unreachable!("at_import checked for a string"),
+        };
+        let name = if self.peek().token_type == TokenType::IDENTIFIER && self.peek().lexeme == "as"
+        {
+            self.advance();
+            self.consume(&TokenType::IDENTIFIER, "Expect module name after 'as'.")?
+                .lexeme
+                .clone()
+        } else {
+            match module_name(&path) {
+                Some(name) => name,
+                None => {
+                    return Err(self.error(
+                        self.peek(),
+                        Code::ExpectToken,
+                        "Expect 'as' and a name, since the file name is not one.",
+                    ))
+                }
+            }
+        };
+        self.consume(&TokenType::SEMICOLON, "Expect ';' after import.")?;
+        self.declare(&name, false);
+        Ok(Statement::Import { path, name, line })
+    }
+
+    /// A `var` declaration, or a `const` one, which needs a value.
+    fn variable(&mut self, constant: bool) -> Result<Statement, Diagnostic> {
+        let doc = self.previous().doc.clone();
         let name = self
             .consume(&TokenType::IDENTIFIER, "Expect variable name.")?
             .clone();
         let init = if self.match_(&[TokenType::EQUAL]) {
             Some(self.expression()?)
+        } else if constant {
+            return Err(self.error(
+                self.peek(),
+                Code::ExpectToken,
+                "Expect '=' after constant name.",
+            ));
         } else {
             None
         };
@@ -52,10 +290,31 @@ impl<'a> Parser<'a> {
             &TokenType::SEMICOLON,
             "Expect ';' after variable declaration.",
         )?;
-        Ok(Statement::Variable { name, init })
+        self.declare(&name.lexeme, constant);
+        Ok(Statement::Variable {
+            name,
+            init,
+            constant,
+            doc,
+        })
     }
 
-    pub fn expression(&mut self) -> Result<Expression, String> {
+    fn declare(&mut self, name: &str, constant: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), constant);
+        }
+    }
+
+    /// Whether `name` refers to a constant declared in this source.
+    fn is_constant(&self, name: &Token) -> bool {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+            .is_some_and(|&constant| constant)
+    }
+
+    pub fn expression(&mut self) -> Result<Expression, Diagnostic> {
         let expression = self.binary_operation(
             &[TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL],
             Self::comparison,
@@ -63,17 +322,28 @@ impl<'a> Parser<'a> {
         if self.match_(&[TokenType::EQUAL]) {
             let right = self.expression()?;
             if let Expression::Variable(name) = expression {
+                if self.is_constant(&name) {
+                    return Err(self.error(
+                        &name,
+                        Code::AssignToConstant,
+                        &format!("Can't assign to constant '{}'.", name.lexeme),
+                    ));
+                }
                 return Ok(Expression::Assign {
                     name,
                     right: Box::new(right),
                 });
             }
-            return Err(self.error(self.previous(), "Invalid assignment target."));
+            return Err(self.error(
+                self.previous(),
+                Code::InvalidAssignmentTarget,
+                "Invalid assignment target.",
+            ));
         }
         Ok(expression)
     }
 
-    fn comparison(&mut self) -> Result<Expression, String> {
+    fn comparison(&mut self) -> Result<Expression, Diagnostic> {
         self.binary_operation(
             &[
                 TokenType::GREATER,
@@ -85,19 +355,19 @@ impl<'a> Parser<'a> {
         )
     }
 
-    fn term(&mut self) -> Result<Expression, String> {
+    fn term(&mut self) -> Result<Expression, Diagnostic> {
         self.binary_operation(&[TokenType::MINUS, TokenType::PLUS], Self::factor)
     }
 
-    fn factor(&mut self) -> Result<Expression, String> {
+    fn factor(&mut self) -> Result<Expression, Diagnostic> {
         self.binary_operation(&[TokenType::SLASH, TokenType::STAR], Self::unary)
     }
 
     fn binary_operation(
         &mut self,
         operators: &[TokenType],
-        next_precedence: fn(&mut Self) -> Result<Expression, String>,
-    ) -> Result<Expression, String> {
+        next_precedence: fn(&mut Self) -> Result<Expression, Diagnostic>,
+    ) -> Result<Expression, Diagnostic> {
         let mut left = next_precedence(self)?;
         while self.match_(operators) {
             let op = self.previous().clone();
@@ -111,7 +381,7 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
 
-    pub fn unary(&mut self) -> Result<Expression, String> {
+    pub fn unary(&mut self) -> Result<Expression, Diagnostic> {
         if self.match_(&[TokenType::BANG, TokenType::MINUS]) {
             let op = self.previous().clone();
             let expr = self.unary()?;
@@ -120,10 +390,71 @@ impl<'a> Parser<'a> {
                 expr: Box::new(expr),
             });
         }
-        self.primary()
+        self.call()
+    }
+
+    /// Calls and indexing, which chain left to right as in `f(x)[0]`.
+    fn call(&mut self) -> Result<Expression, Diagnostic> {
+        let mut expression = self.primary()?;
+        loop {
+            if self.match_(&[TokenType::LEFT_BRACKET]) {
+                let bracket = self.previous().clone();
+                let index = self.expression()?;
+                self.consume(&TokenType::RIGHT_BRACKET, "Expect ']' after index.")?;
+                expression = Expression::Index {
+                    object: Box::new(expression),
+                    bracket,
+                    index: Box::new(index),
+                };
+            } else if self.match_(&[TokenType::DOT]) {
+                // `a.b` is short for `a["b"]`, for modules and other maps.
+                let dot = self.previous().clone();
+                let name = self
+                    .consume(&TokenType::IDENTIFIER, "Expect property name after '.'.")?
+                    .lexeme
+                    .clone();
+                expression = Expression::Index {
+                    object: Box::new(expression),
+                    bracket: dot,
+                    index: Box::new(Expression::Literal(Literal::String(name))),
+                };
+            } else if self.match_(&[TokenType::LEFT_PAREN]) {
+                let args = self.arguments()?;
+                let paren = self
+                    .consume(&TokenType::RIGHT_PAREN, "Expect ')' after arguments.")?
+                    .clone();
+                expression = Expression::Call {
+                    callee: Box::new(expression),
+                    paren,
+                    args,
+                };
+            } else {
+                return Ok(expression);
+            }
+        }
     }
 
-    pub fn primary(&mut self) -> Result<Expression, String> {
+    fn arguments(&mut self) -> Result<Vec<Expression>, Diagnostic> {
+        let mut args = vec![];
+        if self.is_cur_match(&TokenType::RIGHT_PAREN) {
+            return Ok(args);
+        }
+        loop {
+            if args.len() == MAX_ARGUMENTS {
+                return Err(self.error(
+                    self.peek(),
+                    Code::TooManyArguments,
+                    "Can't have more than 255 arguments.",
+                ));
+            }
+            args.push(self.expression()?);
+            if !self.match_(&[TokenType::COMMA]) {
+                return Ok(args);
+            }
+        }
+    }
+
+    pub fn primary(&mut self) -> Result<Expression, Diagnostic> {
         if self.match_(&[TokenType::FALSE]) {
             return Ok(Expression::Literal(Literal::Boolean(false)));
         }
@@ -142,7 +473,9 @@ impl<'a> Parser<'a> {
             ));
         }
 
-        if self.match_(&[TokenType::IDENTIFIER]) {
+        // Within an expression, `print` names the native, as in
+        // `defer print("done");`.
+        if self.match_(&[TokenType::IDENTIFIER, TokenType::PRINT]) {
             return Ok(Expression::Variable(self.previous().clone()));
         }
 
@@ -152,7 +485,7 @@ impl<'a> Parser<'a> {
             return Ok(Expression::Group(Box::new(expression)));
         }
 
-        Err(self.error(self.peek(), "Expect expression."))
+        Err(self.error(self.peek(), Code::ExpectExpression, "Expect expression."))
     }
 
     fn match_(&mut self, token_types: &[TokenType]) -> bool {
@@ -165,11 +498,11 @@ impl<'a> Parser<'a> {
         is_match
     }
 
-    fn consume(&mut self, token_type: &TokenType, message: &str) -> Result<&Token, String> {
+    fn consume(&mut self, token_type: &TokenType, message: &str) -> Result<&Token, Diagnostic> {
         if self.is_cur_match(token_type) {
             return Ok(self.advance());
         }
-        Err(self.error(self.peek(), message))
+        Err(self.error(self.peek(), Code::ExpectToken, message))
     }
 
     fn is_cur_match(&self, token_type: &TokenType) -> bool {
@@ -195,10 +528,17 @@ impl<'a> Parser<'a> {
         &self.tokens[self.current - 1]
     }
 
-    fn error(&self, token: &Token, message: &str) -> String {
-        format!(
-            "[line {}] Error at '{}': {}",
-            token.line_num, token.lexeme, message
-        )
+    fn error(&self, token: &Token, code: Code, message: &str) -> Diagnostic {
+        Diagnostic::at_token(code, token, message)
     }
 }
+
+/// The file name of `path` without its extension, if it can name a variable.
+fn module_name(path: &str) -> Option<String> {
+    let stem = Path::new(path).file_stem()?.to_str()?;
+    let mut chars = stem.chars();
+    let first = chars.next()?;
+    let valid =
+        (first.is_alphabetic() || first == '_') && chars.all(|c| c.is_alphanumeric() || c == '_');
+    valid.then(|| stem.to_string())
+}
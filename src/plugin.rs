@@ -0,0 +1,111 @@
+//! Native functions from shared libraries, loaded with `--plugin`. A plugin
+//! exports
+//!
+//! ```c
+//! typedef void (*LoxDefine)(void *natives, const char *name, size_t arity,
+//!                           LoxCallback callback, void *user_data);
+//! int lox_plugin_init(unsigned abi, void *natives, LoxDefine define);
+//! ```
+//!
+//! which calls `define` for each native it provides and returns 0, or
+//! non-zero to refuse to load, such as when `abi` is not [`ABI_VERSION`].
+//! Values and callbacks are those of [`crate::abi`].
+//!
+//! Plugin natives can do anything the process can, so the command line
+//! does not load plugins under `--sandbox`.
+
+use std::ffi::{c_char, c_int, c_uint, c_void, CString};
+
+use thiserror::Error;
+
+use crate::abi::{self, LoxCallback};
+use crate::grammar::Literal;
+
+/// Passed to `lox_plugin_init`; bumped whenever the types in [`crate::abi`]
+/// or the signature of `define` change.
+pub const ABI_VERSION: c_uint = 1;
+
+#[derive(Debug, Error)]
+pub enum PluginError {
+    #[error("Cannot load plugin {path}: {message}")]
+    Open { path: String, message: String },
+    #[error("{0} is not a plugin: it does not export lox_plugin_init")]
+    NoInit(String),
+    #[error("Plugin {path} failed to initialize (status {status})")]
+    Init { path: String, status: c_int },
+    #[error("Plugins are not supported on this platform")]
+    Unsupported,
+}
+
+type Init = unsafe extern "C" fn(c_uint, *mut c_void, Define) -> c_int;
+type Define = extern "C" fn(*mut c_void, *const c_char, usize, LoxCallback, *mut c_void);
+
+/// Loads the plugin at `path` and returns the natives it defines. The
+/// library stays loaded for the rest of the process, since the natives
+/// point into it.
+#[cfg(unix)]
+pub fn load(path: &str) -> Result<Vec<(String, Literal)>, PluginError> {
+    const RTLD_NOW: c_int = 2;
+
+    #[cfg_attr(target_os = "linux", link(name = "dl"))]
+    extern "C" {
+        fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        fn dlerror() -> *const c_char;
+    }
+
+    let open_error = |message: String| PluginError::Open {
+        path: path.to_string(),
+        message,
+    };
+    let filename = CString::new(path).map_err(|err| open_error(err.to_string()))?;
+    let mut natives: Vec<(String, Literal)> = vec![];
+    unsafe {
+        let library = dlopen(filename.as_ptr(), RTLD_NOW);
+        if library.is_null() {
+            let message = abi::string(dlerror()).unwrap_or("unknown error");
+            return Err(open_error(message.to_string()));
+        }
+        let init = dlsym(library, c"lox_plugin_init".as_ptr());
+        if init.is_null() {
+            return Err(PluginError::NoInit(path.to_string()));
+        }
+        let init: Init = std::mem::transmute(init);
+        let status = init(ABI_VERSION, &mut natives as *mut _ as *mut c_void, define);
+        if status != 0 {
+            return Err(PluginError::Init {
+                path: path.to_string(),
+                status,
+            });
+        }
+    }
+    Ok(natives)
+}
+
+#[cfg(not(unix))]
+pub fn load(_path: &str) -> Result<Vec<(String, Literal)>, PluginError> {
+    Err(PluginError::Unsupported)
+}
+
+extern "C" fn define(
+    natives: *mut c_void,
+    name: *const c_char,
+    arity: usize,
+    callback: LoxCallback,
+    user_data: *mut c_void,
+) {
+    // SAFETY: `natives` is the vector `load` passed to the plugin, and
+    // `name` a string the plugin owns for the duration of the call.
+    let (natives, name) = unsafe {
+        (
+            &mut *(natives as *mut Vec<(String, Literal)>),
+            abi::string(name),
+        )
+    };
+    if let Some(name) = name {
+        natives.push((
+            name.to_string(),
+            abi::native(name, arity, callback, user_data),
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+//! The prelude, Lox source embedded in the crate whose globals scripts
+//! start with. The command line defines them unless `--no-prelude` is
+//! given; hosts can with [`globals`].
+
+use std::cell::OnceCell;
+
+use crate::diagnostics::{Diagnostics, Format};
+use crate::grammar::*;
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+
+pub const SOURCE: &str = include_str!("prelude.lox");
+
+/// The variables the prelude declares, with their values. The prelude runs
+/// once per thread; later calls get copies of what it left.
+///
+/// ```
+/// # use rustyinterpreter::{prelude, Lox};
+/// let mut lox = Lox::new();
+/// for (name, value) in prelude::globals() {
+///     lox.interpreter().define_global(&name, value);
+/// }
+/// lox.run("print TAU / PI;").unwrap();
+/// ```
+pub fn globals() -> Vec<(String, Literal)> {
+    // Per thread, since values may hold `Rc`s.
+    thread_local! {
+        static GLOBALS: OnceCell<Vec<(String, Literal)>> = const { OnceCell::new() };
+    }
+    GLOBALS.with(|globals| globals.get_or_init(run).clone())
+}
+
+fn run() -> Vec<(String, Literal)> {
+    let mut diagnostics = Diagnostics::new(Format::Plain);
+    let tokens = Scanner::new(SOURCE).scan_tokens(&mut diagnostics);
+    let statements = Parser::new(&tokens).parse().expect("the prelude parses");
+    let names: Vec<String> = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Variable { name, .. } => Some(name.lexeme.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut interpreter = Interpreter::new();
+    interpreter.interpret(statements).expect("the prelude runs");
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let value = interpreter.global(&name)?.clone();
+            Some((name, value))
+        })
+        .collect()
+}
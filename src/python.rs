@@ -0,0 +1,712 @@
+//! Python bindings, enabled by the `python` feature: an extension module
+//! written against the CPython C API. Build it and put it where Python
+//! looks for modules with
+//!
+//! ```text
+//! cargo rustc --lib --release --features python --crate-type cdylib
+//! cp target/release/librustyinterpreter.so rustyinterpreter.so
+//! ```
+//!
+//! and use it as
+//!
+//! ```python
+//! import rustyinterpreter
+//!
+//! rustyinterpreter.run('print "hi";')
+//! lox = rustyinterpreter.Lox()
+//! lox.register("double", 1, lambda x: x * 2)
+//! lox.run("var n = double(21);")
+//! assert lox.get("n") == 42.0
+//! assert lox.evaluate("n + m", {"m": 1}) == 43.0
+//! ```
+//!
+//! `nil`, booleans, numbers, strings, lists and maps become `None`, `bool`,
+//! `float`, `str`, `list` and `dict`, and back; `int`s and tuples are taken
+//! too. `print` writes to `sys.stdout`, so output shows up in notebooks.
+//! Syntax errors raise `SyntaxError`, and runtime errors `RuntimeError`.
+
+use std::collections::BTreeMap;
+use std::ffi::{c_char, c_int, c_long, c_uint, c_void, CStr};
+use std::io::{self, Write};
+use std::ptr::{self, addr_of_mut};
+
+use crate::abi::string;
+use crate::diagnostics::{Diagnostic, Format, Phase};
+use crate::grammar::Literal;
+use crate::output::Flush;
+use crate::shared::Shared;
+use crate::Lox;
+
+#[repr(C)]
+pub struct PyObject {
+    ob_refcnt: isize,
+    ob_type: *mut PyObject,
+}
+
+/// Statics the C API exports, used only by address.
+#[repr(C)]
+struct Opaque {
+    _private: [u8; 0],
+}
+
+type Method = unsafe extern "C" fn(*mut PyObject, *mut PyObject) -> *mut PyObject;
+type New = unsafe extern "C" fn(*mut PyObject, *mut PyObject, *mut PyObject) -> *mut PyObject;
+type Dealloc = unsafe extern "C" fn(*mut PyObject);
+
+#[repr(C)]
+struct PyMethodDef {
+    name: *const c_char,
+    method: Option<Method>,
+    flags: c_int,
+    doc: *const c_char,
+}
+
+#[repr(C)]
+struct PyModuleDef {
+    // PyModuleDef_HEAD_INIT
+    ob_base: PyObject,
+    init: *mut c_void,
+    index: isize,
+    copy: *mut PyObject,
+
+    name: *const c_char,
+    doc: *const c_char,
+    size: isize,
+    methods: *mut PyMethodDef,
+    slots: *mut c_void,
+    traverse: *mut c_void,
+    clear: *mut c_void,
+    free: *mut c_void,
+}
+
+#[repr(C)]
+struct PyTypeSlot {
+    slot: c_int,
+    function: *mut c_void,
+}
+
+#[repr(C)]
+struct PyTypeSpec {
+    name: *const c_char,
+    basic_size: c_int,
+    item_size: c_int,
+    flags: c_uint,
+    slots: *mut PyTypeSlot,
+}
+
+const PYTHON_API_VERSION: c_int = 1013;
+const METH_VARARGS: c_int = 0x0001;
+const METH_NOARGS: c_int = 0x0004;
+const TPFLAGS_DEFAULT: c_uint = 1 << 18;
+const TP_DEALLOC: c_int = 52;
+const TP_DOC: c_int = 56;
+const TP_METHODS: c_int = 64;
+const TP_NEW: c_int = 65;
+
+extern "C" {
+    static mut _Py_NoneStruct: Opaque;
+    static mut PyBool_Type: Opaque;
+    static mut PyLong_Type: Opaque;
+    static mut PyFloat_Type: Opaque;
+    static mut PyUnicode_Type: Opaque;
+    static mut PyList_Type: Opaque;
+    static mut PyTuple_Type: Opaque;
+    static mut PyDict_Type: Opaque;
+    static mut PyExc_RuntimeError: *mut PyObject;
+    static mut PyExc_SyntaxError: *mut PyObject;
+    static mut PyExc_TypeError: *mut PyObject;
+
+    fn PyModule_Create2(def: *mut PyModuleDef, api_version: c_int) -> *mut PyObject;
+    fn PyModule_AddObject(
+        module: *mut PyObject,
+        name: *const c_char,
+        value: *mut PyObject,
+    ) -> c_int;
+    fn PyType_FromSpec(spec: *mut PyTypeSpec) -> *mut PyObject;
+    fn PyType_GenericAlloc(tp: *mut PyObject, items: isize) -> *mut PyObject;
+    fn PyObject_Free(object: *mut c_void);
+    fn PyArg_ParseTuple(args: *mut PyObject, format: *const c_char, ...) -> c_int;
+    fn Py_IncRef(object: *mut PyObject);
+    fn Py_DecRef(object: *mut PyObject);
+    fn PyObject_IsInstance(object: *mut PyObject, class: *mut PyObject) -> c_int;
+    fn PyObject_Str(object: *mut PyObject) -> *mut PyObject;
+    fn PyObject_IsTrue(object: *mut PyObject) -> c_int;
+    fn PyObject_CallObject(callable: *mut PyObject, args: *mut PyObject) -> *mut PyObject;
+    fn PyObject_CallMethod(
+        object: *mut PyObject,
+        name: *const c_char,
+        format: *const c_char,
+        ...
+    ) -> *mut PyObject;
+    fn PyCallable_Check(object: *mut PyObject) -> c_int;
+    fn PyErr_SetString(exception: *mut PyObject, message: *const c_char);
+    fn PyErr_Occurred() -> *mut PyObject;
+    fn PyErr_Clear();
+    fn PyErr_Fetch(
+        kind: *mut *mut PyObject,
+        value: *mut *mut PyObject,
+        traceback: *mut *mut PyObject,
+    );
+    fn PySys_GetObject(name: *const c_char) -> *mut PyObject;
+    fn PyBool_FromLong(value: c_long) -> *mut PyObject;
+    fn PyFloat_FromDouble(value: f64) -> *mut PyObject;
+    fn PyFloat_AsDouble(object: *mut PyObject) -> f64;
+    fn PyUnicode_FromStringAndSize(text: *const c_char, size: isize) -> *mut PyObject;
+    fn PyUnicode_AsUTF8AndSize(object: *mut PyObject, size: *mut isize) -> *const c_char;
+    fn PyList_New(size: isize) -> *mut PyObject;
+    fn PyList_Size(list: *mut PyObject) -> isize;
+    fn PyList_GetItem(list: *mut PyObject, index: isize) -> *mut PyObject;
+    fn PyList_SetItem(list: *mut PyObject, index: isize, item: *mut PyObject) -> c_int;
+    fn PyTuple_New(size: isize) -> *mut PyObject;
+    fn PyTuple_Size(tuple: *mut PyObject) -> isize;
+    fn PyTuple_GetItem(tuple: *mut PyObject, index: isize) -> *mut PyObject;
+    fn PyTuple_SetItem(tuple: *mut PyObject, index: isize, item: *mut PyObject) -> c_int;
+    fn PyDict_New() -> *mut PyObject;
+    fn PyDict_Next(
+        dict: *mut PyObject,
+        position: *mut isize,
+        key: *mut *mut PyObject,
+        value: *mut *mut PyObject,
+    ) -> c_int;
+    fn PyDict_SetItemString(dict: *mut PyObject, key: *const c_char, value: *mut PyObject)
+        -> c_int;
+}
+
+/// A `Lox` object: the Python header, then the session.
+#[repr(C)]
+struct LoxObject {
+    ob_base: PyObject,
+    lox: *mut Lox,
+}
+
+/// Called by Python on `import rustyinterpreter`.
+///
+/// # Safety
+///
+/// Only Python calls this, holding the GIL.
+#[no_mangle]
+pub unsafe extern "C" fn PyInit_rustyinterpreter() -> *mut PyObject {
+    let methods = Box::leak(Box::new([
+        method(
+            c"run",
+            run,
+            METH_VARARGS,
+            c"run(source)\n--\n\nRuns source in a new session.",
+        ),
+        PyMethodDef {
+            name: ptr::null(),
+            method: None,
+            flags: 0,
+            doc: ptr::null(),
+        },
+    ]));
+    let module = Box::leak(Box::new(PyModuleDef {
+        ob_base: PyObject {
+            ob_refcnt: 1,
+            ob_type: ptr::null_mut(),
+        },
+        init: ptr::null_mut(),
+        index: 0,
+        copy: ptr::null_mut(),
+        name: c"rustyinterpreter".as_ptr(),
+        doc: c"A Lox interpreter.".as_ptr(),
+        size: -1,
+        methods: methods.as_mut_ptr(),
+        slots: ptr::null_mut(),
+        traverse: ptr::null_mut(),
+        clear: ptr::null_mut(),
+        free: ptr::null_mut(),
+    }));
+    let module = PyModule_Create2(module, PYTHON_API_VERSION);
+    if module.is_null() {
+        return module;
+    }
+    let class = lox_type();
+    if class.is_null() || PyModule_AddObject(module, c"Lox".as_ptr(), class) < 0 {
+        Py_DecRef(class);
+        Py_DecRef(module);
+        return ptr::null_mut();
+    }
+    module
+}
+
+fn method(name: &'static CStr, method: Method, flags: c_int, doc: &'static CStr) -> PyMethodDef {
+    PyMethodDef {
+        name: name.as_ptr(),
+        method: Some(method),
+        flags,
+        doc: doc.as_ptr(),
+    }
+}
+
+unsafe fn lox_type() -> *mut PyObject {
+    let methods = Box::leak(Box::new([
+        method(
+            c"run",
+            lox_run,
+            METH_VARARGS,
+            c"run(source)\n--\n\nRuns source, keeping the globals it defines.",
+        ),
+        method(
+            c"evaluate",
+            lox_evaluate,
+            METH_VARARGS,
+            c"evaluate(source, variables=None)\n--\n\nThe value of the expression in source, \
+              with variables visible to it.",
+        ),
+        method(
+            c"get",
+            lox_get,
+            METH_VARARGS,
+            c"get(name)\n--\n\nThe global variable name.",
+        ),
+        method(
+            c"set",
+            lox_set,
+            METH_VARARGS,
+            c"set(name, value)\n--\n\nDefines the global variable name.",
+        ),
+        method(
+            c"register",
+            lox_register,
+            METH_VARARGS,
+            c"register(name, arity, function)\n--\n\nMakes function callable from Lox as \
+              name, with arity arguments.",
+        ),
+        method(
+            c"reset",
+            lox_reset,
+            METH_NOARGS,
+            c"reset()\n--\n\nForgets the globals defined by scripts.",
+        ),
+        PyMethodDef {
+            name: ptr::null(),
+            method: None,
+            flags: 0,
+            doc: ptr::null(),
+        },
+    ]));
+    let new: New = lox_new;
+    let dealloc: Dealloc = lox_dealloc;
+    let slots = Box::leak(Box::new([
+        PyTypeSlot {
+            slot: TP_NEW,
+            function: new as *mut c_void,
+        },
+        PyTypeSlot {
+            slot: TP_DEALLOC,
+            function: dealloc as *mut c_void,
+        },
+        PyTypeSlot {
+            slot: TP_METHODS,
+            function: methods.as_mut_ptr() as *mut c_void,
+        },
+        PyTypeSlot {
+            slot: TP_DOC,
+            function: c"A session: each run sees the globals left by earlier ones.".as_ptr()
+                as *mut c_void,
+        },
+        PyTypeSlot {
+            slot: 0,
+            function: ptr::null_mut(),
+        },
+    ]));
+    let spec = Box::leak(Box::new(PyTypeSpec {
+        name: c"rustyinterpreter.Lox".as_ptr(),
+        basic_size: std::mem::size_of::<LoxObject>() as c_int,
+        item_size: 0,
+        flags: TPFLAGS_DEFAULT,
+        slots: slots.as_mut_ptr(),
+    }));
+    PyType_FromSpec(spec)
+}
+
+/// A session printing to `sys.stdout`.
+fn session() -> Lox {
+    Lox::with_output(Stdout, Flush::Line)
+}
+
+unsafe extern "C" fn lox_new(
+    class: *mut PyObject,
+    _args: *mut PyObject,
+    _kwargs: *mut PyObject,
+) -> *mut PyObject {
+    let object = PyType_GenericAlloc(class, 0) as *mut LoxObject;
+    if !object.is_null() {
+        (*object).lox = Box::into_raw(Box::new(session()));
+    }
+    object as *mut PyObject
+}
+
+unsafe extern "C" fn lox_dealloc(object: *mut PyObject) {
+    let object = object as *mut LoxObject;
+    if !(*object).lox.is_null() {
+        drop(Box::from_raw((*object).lox));
+    }
+    // Instances of heap types hold a reference to their type.
+    let class = (*object).ob_base.ob_type;
+    PyObject_Free(object as *mut c_void);
+    Py_DecRef(class);
+}
+
+unsafe fn session_of<'a>(object: *mut PyObject) -> &'a mut Lox {
+    &mut *(*(object as *mut LoxObject)).lox
+}
+
+unsafe extern "C" fn run(_module: *mut PyObject, args: *mut PyObject) -> *mut PyObject {
+    let mut source: *const c_char = ptr::null();
+    if PyArg_ParseTuple(args, c"s".as_ptr(), &mut source) == 0 {
+        return ptr::null_mut();
+    }
+    run_in(&mut session(), source)
+}
+
+unsafe extern "C" fn lox_run(object: *mut PyObject, args: *mut PyObject) -> *mut PyObject {
+    let mut source: *const c_char = ptr::null();
+    if PyArg_ParseTuple(args, c"s".as_ptr(), &mut source) == 0 {
+        return ptr::null_mut();
+    }
+    run_in(session_of(object), source)
+}
+
+unsafe fn run_in(lox: &mut Lox, source: *const c_char) -> *mut PyObject {
+    let Some(source) = string(source) else {
+        return raise(PyExc_TypeError, "source is not valid UTF-8");
+    };
+    match lox.run(source) {
+        Ok(()) => none(),
+        Err(diagnostics) => raise_diagnostics(&diagnostics, source),
+    }
+}
+
+unsafe extern "C" fn lox_evaluate(object: *mut PyObject, args: *mut PyObject) -> *mut PyObject {
+    let mut source: *const c_char = ptr::null();
+    let mut variables: *mut PyObject = ptr::null_mut();
+    if PyArg_ParseTuple(args, c"s|O".as_ptr(), &mut source, &mut variables) == 0 {
+        return ptr::null_mut();
+    }
+    let Some(source) = string(source) else {
+        return raise(PyExc_TypeError, "source is not valid UTF-8");
+    };
+    let variables = if variables.is_null() || variables == none_object() {
+        BTreeMap::new()
+    } else {
+        match from_python(variables) {
+            Ok(Literal::Map(variables)) => (*variables).clone(),
+            Ok(_) => return raise(PyExc_TypeError, "variables must be a dict"),
+            Err(message) => return raise(PyExc_TypeError, &message),
+        }
+    };
+    match session_of(object).evaluate(source, variables) {
+        Ok(value) => to_python(&value),
+        Err(diagnostics) => raise_diagnostics(&diagnostics, source),
+    }
+}
+
+unsafe extern "C" fn lox_get(object: *mut PyObject, args: *mut PyObject) -> *mut PyObject {
+    let mut name: *const c_char = ptr::null();
+    if PyArg_ParseTuple(args, c"s".as_ptr(), &mut name) == 0 {
+        return ptr::null_mut();
+    }
+    let name = string(name).unwrap_or_default();
+    match session_of(object).interpreter().global(name) {
+        Some(value) => to_python(&value.clone()),
+        None => raise(PyExc_RuntimeError, &format!("Undefined variable '{name}'.")),
+    }
+}
+
+unsafe extern "C" fn lox_set(object: *mut PyObject, args: *mut PyObject) -> *mut PyObject {
+    let mut name: *const c_char = ptr::null();
+    let mut value: *mut PyObject = ptr::null_mut();
+    if PyArg_ParseTuple(args, c"sO".as_ptr(), &mut name, &mut value) == 0 {
+        return ptr::null_mut();
+    }
+    match from_python(value) {
+        Ok(value) => {
+            let name = string(name).unwrap_or_default();
+            session_of(object).interpreter().define_global(name, value);
+            none()
+        }
+        Err(message) => raise(PyExc_TypeError, &message),
+    }
+}
+
+unsafe extern "C" fn lox_register(object: *mut PyObject, args: *mut PyObject) -> *mut PyObject {
+    let mut name: *const c_char = ptr::null();
+    let mut arity: isize = 0;
+    let mut function: *mut PyObject = ptr::null_mut();
+    if PyArg_ParseTuple(args, c"snO".as_ptr(), &mut name, &mut arity, &mut function) == 0 {
+        return ptr::null_mut();
+    }
+    if PyCallable_Check(function) == 0 {
+        return raise(PyExc_TypeError, "function must be callable");
+    }
+    let Ok(arity) = usize::try_from(arity) else {
+        return raise(PyExc_TypeError, "arity must not be negative");
+    };
+    let name = string(name).unwrap_or_default();
+    let native = native(name, arity, Callable::new(function));
+    session_of(object).interpreter().define_global(name, native);
+    none()
+}
+
+unsafe extern "C" fn lox_reset(object: *mut PyObject, _args: *mut PyObject) -> *mut PyObject {
+    session_of(object).reset();
+    none()
+}
+
+/// A Python callable a native holds on to.
+struct Callable(*mut PyObject);
+
+// Natives run from within calls into the module, which hold the GIL.
+unsafe impl Send for Callable {}
+unsafe impl Sync for Callable {}
+
+impl Callable {
+    unsafe fn new(function: *mut PyObject) -> Self {
+        Py_IncRef(function);
+        Callable(function)
+    }
+
+    /// A method rather than the field, so that closures capture the whole
+    /// `Callable` along with its `Send` and `Sync`.
+    fn get(&self) -> *mut PyObject {
+        self.0
+    }
+}
+
+impl Drop for Callable {
+    fn drop(&mut self) {
+        // SAFETY: sessions are dropped by Python, holding the GIL.
+        unsafe { Py_DecRef(self.0) }
+    }
+}
+
+/// A native calling `function`, whose exceptions become runtime errors.
+fn native(name: &str, arity: usize, function: Callable) -> Literal {
+    Literal::native(name, arity, move |args| unsafe {
+        let tuple = PyTuple_New(args.len() as isize);
+        if tuple.is_null() {
+            return Err(exception());
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let arg = to_python(arg);
+            if arg.is_null() {
+                Py_DecRef(tuple);
+                return Err(exception());
+            }
+            PyTuple_SetItem(tuple, i as isize, arg);
+        }
+        let result = PyObject_CallObject(function.get(), tuple);
+        Py_DecRef(tuple);
+        if result.is_null() {
+            return Err(exception());
+        }
+        let value = from_python(result);
+        Py_DecRef(result);
+        value
+    })
+}
+
+/// The message of the pending Python exception, which is cleared.
+unsafe fn exception() -> String {
+    let (mut kind, mut value, mut traceback) = (ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+    PyErr_Fetch(&mut kind, &mut value, &mut traceback);
+    let message = if value.is_null() {
+        None
+    } else {
+        let text = PyObject_Str(value);
+        let message = text_of(text);
+        if text.is_null() {
+            // `str()` itself failed; its exception is not worth keeping.
+            PyErr_Clear();
+        } else {
+            Py_DecRef(text);
+        }
+        message
+    };
+    for object in [kind, value, traceback] {
+        if !object.is_null() {
+            Py_DecRef(object);
+        }
+    }
+    message.unwrap_or_else(|| "Python function failed.".to_string())
+}
+
+unsafe fn to_python(value: &Literal) -> *mut PyObject {
+    match value {
+        Literal::Nil => none(),
+        Literal::Boolean(b) => PyBool_FromLong(*b as c_long),
+        Literal::Number(n) => PyFloat_FromDouble(*n),
+        Literal::String(s) => {
+            PyUnicode_FromStringAndSize(s.as_ptr() as *const c_char, s.len() as isize)
+        }
+        Literal::List(items) => {
+            let list = PyList_New(items.len() as isize);
+            if list.is_null() {
+                return list;
+            }
+            for (i, item) in items.iter().enumerate() {
+                let item = to_python(item);
+                if item.is_null() {
+                    Py_DecRef(list);
+                    return item;
+                }
+                PyList_SetItem(list, i as isize, item);
+            }
+            list
+        }
+        Literal::Map(entries) => {
+            let dict = PyDict_New();
+            if dict.is_null() {
+                return dict;
+            }
+            for (key, value) in entries.iter() {
+                let value = to_python(value);
+                let key = std::ffi::CString::new(key.as_str()).unwrap_or_default();
+                if value.is_null() || PyDict_SetItemString(dict, key.as_ptr(), value) < 0 {
+                    if !value.is_null() {
+                        Py_DecRef(value);
+                    }
+                    Py_DecRef(dict);
+                    return ptr::null_mut();
+                }
+                Py_DecRef(value);
+            }
+            dict
+        }
+        Literal::Native(_) => {
+            let text = value.to_string();
+            PyUnicode_FromStringAndSize(text.as_ptr() as *const c_char, text.len() as isize)
+        }
+    }
+}
+
+unsafe fn from_python(object: *mut PyObject) -> Result<Literal, String> {
+    let is = |class: *mut Opaque| PyObject_IsInstance(object, class as *mut PyObject) == 1;
+    if object == none_object() {
+        Ok(Literal::Nil)
+    } else if is(addr_of_mut!(PyBool_Type)) {
+        Ok(Literal::Boolean(PyObject_IsTrue(object) == 1))
+    } else if is(addr_of_mut!(PyLong_Type)) || is(addr_of_mut!(PyFloat_Type)) {
+        let n = PyFloat_AsDouble(object);
+        if n == -1.0 && !PyErr_Occurred().is_null() {
+            return Err(exception());
+        }
+        Ok(Literal::Number(n))
+    } else if is(addr_of_mut!(PyUnicode_Type)) {
+        text_of(object)
+            .map(Literal::String)
+            .ok_or_else(|| exception())
+    } else if is(addr_of_mut!(PyList_Type)) || is(addr_of_mut!(PyTuple_Type)) {
+        let list = is(addr_of_mut!(PyList_Type));
+        let len = if list {
+            PyList_Size(object)
+        } else {
+            PyTuple_Size(object)
+        };
+        let mut items = Vec::with_capacity(len.max(0) as usize);
+        for i in 0..len {
+            let item = if list {
+                PyList_GetItem(object, i)
+            } else {
+                PyTuple_GetItem(object, i)
+            };
+            items.push(from_python(item)?);
+        }
+        Ok(Literal::List(Shared::new(items)))
+    } else if is(addr_of_mut!(PyDict_Type)) {
+        let mut entries = BTreeMap::new();
+        let (mut position, mut key, mut value) = (0, ptr::null_mut(), ptr::null_mut());
+        while PyDict_Next(object, &mut position, &mut key, &mut value) != 0 {
+            if PyObject_IsInstance(key, addr_of_mut!(PyUnicode_Type) as *mut PyObject) != 1 {
+                return Err("dict keys must be strings".to_string());
+            }
+            let key = text_of(key).ok_or_else(|| exception())?;
+            entries.insert(key, from_python(value)?);
+        }
+        Ok(Literal::Map(Shared::new(entries)))
+    } else {
+        let text = PyObject_Str(object);
+        let shown = text_of(text).unwrap_or_default();
+        if !text.is_null() {
+            Py_DecRef(text);
+        }
+        Err(format!("cannot convert {shown} to a Lox value"))
+    }
+}
+
+/// The contents of a Python `str`.
+unsafe fn text_of(object: *mut PyObject) -> Option<String> {
+    if object.is_null() {
+        return None;
+    }
+    let mut size = 0;
+    let text = PyUnicode_AsUTF8AndSize(object, &mut size);
+    if text.is_null() {
+        return None;
+    }
+    let bytes = std::slice::from_raw_parts(text as *const u8, size as usize);
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+unsafe fn none_object() -> *mut PyObject {
+    addr_of_mut!(_Py_NoneStruct) as *mut PyObject
+}
+
+unsafe fn none() -> *mut PyObject {
+    let none = none_object();
+    Py_IncRef(none);
+    none
+}
+
+unsafe fn raise(exception: *mut PyObject, message: &str) -> *mut PyObject {
+    let message = std::ffi::CString::new(message.replace('\0', "")).unwrap_or_default();
+    PyErr_SetString(exception, message.as_ptr());
+    ptr::null_mut()
+}
+
+unsafe fn raise_diagnostics(diagnostics: &[Diagnostic], source: &str) -> *mut PyObject {
+    let exception = match diagnostics.first().map(Diagnostic::phase) {
+        Some(Phase::Runtime) => PyExc_RuntimeError,
+        _ => PyExc_SyntaxError,
+    };
+    let message: Vec<String> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.render(Format::Plain, "", source))
+        .collect();
+    raise(exception, &message.join("\n"))
+}
+
+/// Writes to whatever `sys.stdout` is at the time.
+struct Stdout;
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        // SAFETY: sessions print from within calls into the module, which
+        // hold the GIL.
+        unsafe {
+            let stdout = PySys_GetObject(c"stdout".as_ptr());
+            if stdout.is_null() || stdout == none_object() {
+                return Ok(buf.len());
+            }
+            let text =
+                PyUnicode_FromStringAndSize(text.as_ptr() as *const c_char, text.len() as isize);
+            if text.is_null() {
+                return Err(io::Error::other(exception()));
+            }
+            let result = PyObject_CallMethod(stdout, c"write".as_ptr(), c"O".as_ptr(), text);
+            Py_DecRef(text);
+            if result.is_null() {
+                return Err(io::Error::other(exception()));
+            }
+            Py_DecRef(result);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,375 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::codes::Code;
+use crate::diagnostics::{Diagnostic, Diagnostics, Format};
+use crate::grammar::*;
+use crate::interpreter::{Interpreter, RuntimeError, RuntimeErrorKind};
+use crate::interrupt;
+use crate::natives::Sandbox;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::shared::{MaybeSend, Shared};
+
+const PROMPT: &str = "> ";
+const CONTINUATION: &str = "... ";
+const FILE: &str = "<repl>";
+const HELP: &str = "\
+:help             show this message
+:env              list the global variables defined in this session
+:env all          list every global variable, natives included
+:ast <expr>       print the syntax tree of an expression
+:tokens <expr>    print the tokens of an expression
+:type <expr>      evaluate an expression and print its type
+:time <input>     run an expression or statements and report the time taken
+:load <file>      run a file in this session
+:save <file>      write the input run so far to a file
+:snapshot <file>  write the global variables to a file
+:restore <file>   define the global variables saved by :snapshot
+:reset            forget every variable defined in this session
+:quit             leave the REPL";
+
+/// Interactive session on the tree-walking interpreter. Input that is not
+/// finished yet keeps reading under a continuation prompt; an empty line
+/// submits it anyway so the error can be shown. Lines starting with `:` are
+/// meta-commands, listed by `:help`. Ctrl-C stops the input running and
+/// returns to the prompt, where it ends the session as usual.
+pub struct Repl {
+    interpreter: Interpreter,
+    format: Format,
+    /// Source of every input that ran to completion, for `:save`.
+    history: Vec<String>,
+    prompt: String,
+    output: OutputFormat,
+    /// Set when the input calls `exit`, which ends the session.
+    status: Option<i32>,
+    optional_semicolons: bool,
+    print_keyword: bool,
+}
+
+impl Repl {
+    pub fn new(format: Format) -> Self {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_global("ARGS", Literal::List(Shared::new(vec![])));
+        Repl {
+            interpreter,
+            format,
+            history: vec![],
+            prompt: PROMPT.to_string(),
+            output: OutputFormat::default(),
+            status: None,
+            optional_semicolons: false,
+            print_keyword: true,
+        }
+    }
+
+    /// Defines globals, such as natives, before the first line is read.
+    pub fn with_globals(mut self, globals: impl IntoIterator<Item = (String, Literal)>) -> Self {
+        for (name, value) in globals {
+            self.interpreter.define_global(&name, value);
+        }
+        self
+    }
+
+    pub fn with_prompt(mut self, prompt: String) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Reads lines from `reader` instead of stdin.
+    pub fn with_input(mut self, reader: impl BufRead + MaybeSend + 'static) -> Self {
+        self.interpreter = self.interpreter.with_input(reader);
+        self
+    }
+
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.interpreter = self.interpreter.with_sandbox(sandbox);
+        self
+    }
+
+    pub fn with_search_path(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.interpreter.set_search_path(dirs);
+        self
+    }
+
+    pub fn with_checked_division(mut self, checked: bool) -> Self {
+        self.interpreter.set_checked_division(checked);
+        self
+    }
+
+    pub fn with_implicit_globals(mut self, implicit: bool) -> Self {
+        self.interpreter.set_implicit_globals(implicit);
+        self
+    }
+
+    /// Lets statements end at the end of the line, for the session and what
+    /// it imports and loads.
+    pub fn with_optional_semicolons(mut self, optional: bool) -> Self {
+        self.interpreter.set_optional_semicolons(optional);
+        self.optional_semicolons = optional;
+        self
+    }
+
+    pub fn with_print_keyword(mut self, keyword: bool) -> Self {
+        self.interpreter.set_print_keyword(keyword);
+        self.print_keyword = keyword;
+        self
+    }
+
+    pub fn with_compat(mut self, compat: Compat) -> Self {
+        self.interpreter.set_compat(compat);
+        self
+    }
+
+    pub fn with_output_format(mut self, output: OutputFormat) -> Self {
+        self.interpreter.set_output_format(output);
+        self.output = output;
+        self
+    }
+
+    /// Reads and runs input until it ends, `:quit` or `exit`. Returns the
+    /// status for the process to exit with.
+    pub fn run(&mut self) -> i32 {
+        let input = self.interpreter.input();
+        let mut buffer = String::new();
+        loop {
+            if buffer.is_empty() {
+                print!("{}", self.prompt);
+            } else {
+                print!("{CONTINUATION}");
+            }
+            io::stdout().flush().unwrap();
+            let Ok(Some(line)) = input.read_line() else {
+                println!();
+                return 0;
+            };
+            if buffer.is_empty() && line.trim().is_empty() {
+                continue;
+            }
+            if buffer.is_empty() && line.trim_start().starts_with(':') {
+                if !self.command(line.trim()) {
+                    return 0;
+                }
+                if let Some(status) = self.status {
+                    return status;
+                }
+                continue;
+            }
+            let forced = !buffer.is_empty() && line.trim().is_empty();
+            buffer.push_str(&line);
+            buffer.push('\n');
+            if forced || !self.incomplete(&buffer) {
+                let source = std::mem::take(&mut buffer);
+                self.eval(FILE, source.trim_end());
+                if let Some(status) = self.status {
+                    return status;
+                }
+            }
+        }
+    }
+
+    /// Handles a meta-command; returns false when the session should end.
+    fn command(&mut self, line: &str) -> bool {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+        match command {
+            ":help" => println!("{HELP}"),
+            ":quit" | ":q" => return false,
+            ":reset" => {
+                self.interpreter.reset();
+                self.history.clear();
+            }
+            ":env" => {
+                let globals = match argument {
+                    "all" => self.interpreter.globals(),
+                    _ => self.interpreter.script_globals(),
+                };
+                for (name, value) in globals {
+                    println!("{name} = {}", self.output.show(value));
+                }
+            }
+            ":tokens" => {
+                let mut diagnostics = Diagnostics::new(self.format);
+                for token in self.scanner(argument).scan_tokens(&mut diagnostics) {
+                    println!("{token}");
+                }
+                diagnostics.emit(FILE, argument);
+            }
+            ":ast" => {
+                if let Some(expression) = self.expression(argument) {
+                    println!("{expression}");
+                }
+            }
+            ":type" => {
+                if let Some(expression) = self.expression(argument) {
+                    let _interrupts = interrupt::catch();
+                    match self.interpreter.evaluate(&expression) {
+                        Ok(value) => println!("{}", value.type_name()),
+                        Err(err) => self.report(err.into(), FILE, argument),
+                    }
+                }
+            }
+            ":time" => {
+                let statements = self.interpreter.statements;
+                let start = Instant::now();
+                self.eval(FILE, argument);
+                println!(
+                    "time: {:.2?}, {} statements executed",
+                    start.elapsed(),
+                    self.interpreter.statements - statements
+                );
+            }
+            ":load" => match fs::read_to_string(argument) {
+                Ok(source) => {
+                    // Imports in the file are relative to it, but those typed
+                    // at the prompt afterwards are not.
+                    self.interpreter.set_script(argument);
+                    self.eval(argument, &source);
+                    self.interpreter.clear_script();
+                }
+                Err(err) => eprintln!("Failed to read {argument}: {err}"),
+            },
+            ":save" => {
+                let mut script = self.history.join("\n");
+                script.push('\n');
+                if let Err(err) = fs::write(argument, script) {
+                    eprintln!("Failed to write {argument}: {err}");
+                }
+            }
+            ":snapshot" => {
+                if let Err(err) = fs::write(argument, self.interpreter.snapshot()) {
+                    eprintln!("Failed to write {argument}: {err}");
+                }
+            }
+            ":restore" => match fs::read(argument) {
+                Ok(bytes) => {
+                    if let Err(err) = self.interpreter.restore(&bytes) {
+                        eprintln!("{argument}: {err}");
+                    }
+                }
+                Err(err) => eprintln!("Failed to read {argument}: {err}"),
+            },
+            _ => eprintln!("Unknown command {command}; try :help."),
+        }
+        true
+    }
+
+    /// A scanner for `source` with the session's options, for everything
+    /// typed at the prompt.
+    fn scanner<'a>(&self, source: &'a str) -> Scanner<'a> {
+        Scanner::new(source)
+            .with_optional_semicolons(self.optional_semicolons)
+            .with_print_keyword(self.print_keyword)
+    }
+
+    fn expression(&self, source: &str) -> Option<Expression> {
+        let mut diagnostics = Diagnostics::new(self.format);
+        let tokens = self.scanner(source).scan_tokens(&mut diagnostics);
+        if diagnostics.has_errors() {
+            diagnostics.emit(FILE, source);
+            return None;
+        }
+        match Parser::new(&tokens).expression() {
+            Ok(expression) => Some(expression),
+            Err(diagnostic) => {
+                self.report(diagnostic, FILE, source);
+                None
+            }
+        }
+    }
+
+    fn eval(&mut self, file: &str, source: &str) {
+        let _interrupts = interrupt::catch();
+        let mut diagnostics = Diagnostics::new(self.format);
+        let tokens = self.scanner(source).scan_tokens(&mut diagnostics);
+        if diagnostics.has_errors() {
+            diagnostics.emit(file, source);
+            return;
+        }
+        // A bare expression is echoed, as if it were printed. It is tried
+        // first since, with optional semicolons, it is a statement as well.
+        // It is saved as the print statement it stands for.
+        let (statements, script) = match Parser::new(&tokens).bare_expression() {
+            Some(expr) => (
+                vec![Statement::Print { expr, line: 1 }],
+                if self.print_keyword {
+                    format!("print {};", source.trim_end())
+                } else {
+                    format!("print({});", source.trim_end())
+                },
+            ),
+            None => match Parser::new(&tokens).parse() {
+                Ok(statements) => (statements, source.to_string()),
+                Err(diagnostic) => return self.report(diagnostic, file, source),
+            },
+        };
+        match self.interpreter.interpret(statements) {
+            Ok(()) => self.history.push(script.trim_end().to_string()),
+            Err(RuntimeError {
+                kind: RuntimeErrorKind::Exit(status),
+                ..
+            }) => self.status = Some(status),
+            Err(err) => self.report(err.into(), file, source),
+        }
+        // Ctrl-C after the last check is not carried over to the next input.
+        interrupt::take();
+    }
+
+    fn report(&self, diagnostic: Diagnostic, file: &str, source: &str) {
+        eprintln!("{}", diagnostic.render(self.format, file, source));
+    }
+
+    /// Whether `source` stops partway through: an open string, bracket or
+    /// brace, or a trailing operator still waiting for its right operand.
+    fn incomplete(&self, source: &str) -> bool {
+        let mut diagnostics = Diagnostics::new(Format::Plain);
+        let tokens = self.scanner(source).scan_tokens(&mut diagnostics);
+        if diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.code == Code::UnterminatedString)
+        {
+            return true;
+        }
+        let depth = tokens
+            .iter()
+            .fold(0i32, |depth, token| match token.token_type {
+                TokenType::LEFT_PAREN | TokenType::LEFT_BRACE | TokenType::LEFT_BRACKET => {
+                    depth + 1
+                }
+                TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACE | TokenType::RIGHT_BRACKET => {
+                    depth - 1
+                }
+                _ => depth,
+            });
+        if depth > 0 {
+            return true;
+        }
+        let last = tokens
+            .iter()
+            .rev()
+            .find(|token| token.token_type != TokenType::EOF);
+        last.is_some_and(|token| {
+            matches!(
+                token.token_type,
+                TokenType::COMMA
+                    | TokenType::DOT
+                    | TokenType::MINUS
+                    | TokenType::PLUS
+                    | TokenType::SLASH
+                    | TokenType::STAR
+                    | TokenType::EQUAL
+                    | TokenType::EQUAL_EQUAL
+                    | TokenType::BANG
+                    | TokenType::BANG_EQUAL
+                    | TokenType::LESS
+                    | TokenType::LESS_EQUAL
+                    | TokenType::GREATER
+                    | TokenType::GREATER_EQUAL
+                    | TokenType::AND
+                    | TokenType::OR
+            )
+        })
+    }
+}
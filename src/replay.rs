@@ -0,0 +1,141 @@
+//! Recording the results of nondeterministic natives, such as `clock`, and
+//! replaying them, so that a run that misbehaved once can be reproduced
+//! exactly. Natives defined by the host are not recorded.
+//!
+//! A log starts with a magic and the format version, followed by one entry
+//! per call, written as it happens so that a crash does not lose it.
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Write};
+
+use bytes::{Buf, BufMut};
+use thiserror::Error;
+
+use crate::grammar::{Literal, Native, NativeResult};
+use crate::shared::{Lock, Shared};
+use crate::snapshot;
+
+const MAGIC: &[u8; 4] = b"RLXR";
+const FORMAT_VERSION: u16 = 1;
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("not a replay log")]
+    BadMagic,
+    #[error("replay log format version {0} is not supported (expected {FORMAT_VERSION})")]
+    Version(u16),
+    #[error("replay log is truncated or corrupt")]
+    Corrupt,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+enum Mode {
+    Record(File),
+    Replay(VecDeque<(String, NativeResult)>),
+}
+
+/// A log being recorded to or replayed from. Clones share it.
+#[derive(Clone)]
+pub struct Log(Shared<Lock<Mode>>);
+
+impl Log {
+    /// Starts recording to a new log at `path`.
+    pub fn record(path: &str) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        let mut header = vec![];
+        header.put_slice(MAGIC);
+        header.put_u16(FORMAT_VERSION);
+        file.write_all(&header)?;
+        Ok(Log(Shared::new(Lock::new(Mode::Record(file)))))
+    }
+
+    /// Loads the log at `path` for replaying.
+    pub fn replay(path: &str) -> Result<Self, ReplayError> {
+        let bytes = fs::read(path)?;
+        let mut bytes = bytes.as_slice();
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        bytes.advance(MAGIC.len());
+        if bytes.remaining() < 2 {
+            return Err(ReplayError::Corrupt);
+        }
+        let version = bytes.get_u16();
+        if version != FORMAT_VERSION {
+            return Err(ReplayError::Version(version));
+        }
+        let mut entries = VecDeque::new();
+        while bytes.has_remaining() {
+            let entry = snapshot::get_value(&mut bytes).map_err(|_| ReplayError::Corrupt)?;
+            entries.push_back(decode_entry(entry).ok_or(ReplayError::Corrupt)?);
+        }
+        Ok(Log(Shared::new(Lock::new(Mode::Replay(entries)))))
+    }
+
+    /// `native`, with its results recorded to the log, or taken from it
+    /// instead of calling it.
+    pub fn wrap(&self, native: Shared<Native>) -> Literal {
+        let log = self.clone();
+        let (name, arity, variadic) = (native.name.clone(), native.arity, native.variadic);
+        let function = move |args: &[Literal]| match &mut *log.0.lock() {
+            Mode::Record(file) => {
+                let result = (native.function)(args);
+                file.write_all(&encode_entry(&native.name, &result))
+                    .map_err(|err| format!("Failed to write the replay log: {err}."))?;
+                result
+            }
+            Mode::Replay(entries) => match entries.pop_front() {
+                Some((name, result)) if name == native.name => result,
+                Some((name, _)) => Err(format!(
+                    "Replay diverged: the log has a call to '{name}' here, not '{}'.",
+                    native.name
+                )),
+                None => Err(format!(
+                    "Replay diverged: the log ends before this call to '{}'.",
+                    native.name
+                )),
+            },
+        };
+        if variadic {
+            Literal::variadic(&name, arity, function)
+        } else {
+            Literal::native(&name, arity, function)
+        }
+    }
+}
+
+/// An entry is the list `[name, ok, value]`, where the value is the error
+/// message when `ok` is false. Values that cannot be serialized are
+/// recorded as nil.
+fn encode_entry(name: &str, result: &NativeResult) -> Vec<u8> {
+    let (ok, value) = match result {
+        Ok(value) if snapshot::serializable(value) => (true, value.clone()),
+        Ok(_) => (true, Literal::Nil),
+        Err(message) => (false, Literal::String(message.clone())),
+    };
+    let entry = Literal::List(Shared::new(vec![
+        Literal::String(name.to_string()),
+        Literal::Boolean(ok),
+        value,
+    ]));
+    let mut buf = vec![];
+    snapshot::put_value(&mut buf, &entry);
+    buf
+}
+
+fn decode_entry(entry: Literal) -> Option<(String, NativeResult)> {
+    let Literal::List(items) = entry else {
+        return None;
+    };
+    match items.as_slice() {
+        [Literal::String(name), Literal::Boolean(true), value] => {
+            Some((name.clone(), Ok(value.clone())))
+        }
+        [Literal::String(name), Literal::Boolean(false), Literal::String(message)] => {
+            Some((name.clone(), Err(message.clone())))
+        }
+        _ => None,
+    }
+}
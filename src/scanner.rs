@@ -1,11 +1,25 @@
-use crate::grammar::{Literal, Token, TokenType};
+use crate::codes::Code;
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::grammar::{Literal, Span, Token, TokenType};
+
+/// A first line of exactly this turns on optional semicolons for the file.
+pub const OPTIONAL_SEMICOLONS_PRAGMA: &str = "// lox: optional-semicolons";
 
 pub struct Scanner<'a> {
     chars: std::iter::Peekable<std::str::Chars<'a>>,
     current: String,
     tokens: Vec<Token>,
     line_num: usize,
-    pub error: bool,
+    start: usize,
+    offset: usize,
+    /// Whether line ends can stand in for `;`; see [`Self::with_optional_semicolons`].
+    optional_semicolons: bool,
+    /// How many `(` and `[` are open.
+    nesting: usize,
+    /// Whether `print` starts a statement, rather than being a name.
+    print_keyword: bool,
+    /// The `///` comment lines since the last token.
+    doc: Option<String>,
 }
 
 impl<'a> Scanner<'a> {
@@ -15,52 +29,142 @@ impl<'a> Scanner<'a> {
             current: String::new(),
             tokens: vec![],
             line_num: 1,
-            error: false,
+            start: 0,
+            offset: 0,
+            optional_semicolons: input.lines().next().map(str::trim_end)
+                == Some(OPTIONAL_SEMICOLONS_PRAGMA),
+            nesting: 0,
+            print_keyword: true,
+            doc: None,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
+    /// Adds the `;` a statement ends with where it is left out, as
+    /// `--optional-semicolons` and the [`OPTIONAL_SEMICOLONS_PRAGMA`] ask.
+    /// One is added at the end of a line, before a `}` and at the end of the
+    /// input, when the token before it is a name, a literal, `)` or `]` and
+    /// no `(` or `[` is open. So a statement can go on to the next line after
+    /// an operator or inside brackets, but not before an operator. Such
+    /// tokens have an empty lexeme.
+    pub fn with_optional_semicolons(mut self, optional: bool) -> Self {
+        self.optional_semicolons |= optional;
+        self
+    }
+
+    /// Without the keyword, `print` is a name like any other, so that a
+    /// statement starting with `print(a, b)` calls the native, as
+    /// `--print=function` asks.
+    pub fn with_print_keyword(mut self, keyword: bool) -> Self {
+        self.print_keyword = keyword;
+        self
+    }
+
+    pub fn scan_tokens(&mut self, diagnostics: &mut Diagnostics) -> Vec<Token> {
         while self.chars.peek().is_some() {
-            self.scan_token();
+            self.scan_token(diagnostics);
         }
+        self.end_statement();
         self.tokens.push(Token {
             token_type: TokenType::EOF,
             lexeme: String::new(),
             literal: None,
             line_num: self.line_num,
+            span: Span {
+                start: self.offset,
+                end: self.offset,
+            },
+            doc: None,
         });
         self.tokens.clone()
     }
 
-    fn scan_token(&mut self) {
-        let c = self.chars.next().unwrap();
+    fn advance(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    fn scan_token(&mut self, diagnostics: &mut Diagnostics) {
+        self.start = self.offset;
+        let c = self.advance().unwrap();
         self.current = c.to_string();
         match c {
-            '(' => self.add_token(TokenType::LEFT_PAREN, None),
-            ')' => self.add_token(TokenType::RIGHT_PAREN, None),
+            '(' => {
+                self.nesting += 1;
+                self.add_token(TokenType::LEFT_PAREN, None)
+            }
+            ')' => {
+                self.nesting = self.nesting.saturating_sub(1);
+                self.add_token(TokenType::RIGHT_PAREN, None)
+            }
             '{' => self.add_token(TokenType::LEFT_BRACE, None),
-            '}' => self.add_token(TokenType::RIGHT_BRACE, None),
+            '}' => {
+                self.end_statement();
+                self.add_token(TokenType::RIGHT_BRACE, None)
+            }
+            '[' => {
+                self.nesting += 1;
+                self.add_token(TokenType::LEFT_BRACKET, None)
+            }
+            ']' => {
+                self.nesting = self.nesting.saturating_sub(1);
+                self.add_token(TokenType::RIGHT_BRACKET, None)
+            }
             ',' => self.add_token(TokenType::COMMA, None),
             '.' => self.add_token(TokenType::DOT, None),
             '-' => self.add_token(TokenType::MINUS, None),
             '+' => self.add_token(TokenType::PLUS, None),
             ';' => self.add_token(TokenType::SEMICOLON, None),
+            ':' => self.add_token(TokenType::COLON, None),
             '*' => self.add_token(TokenType::STAR, None),
             '=' | '!' | '<' | '>' => self.handle_comparison(c),
             '/' => self.handle_slash(),
             ' ' | '\r' | '\t' => (),
-            '\n' => self.line_num += 1,
-            '"' => self.handle_string(),
+            '\n' => {
+                self.end_statement();
+                self.line_num += 1
+            }
+            '"' => self.handle_string(diagnostics),
             c if c.is_ascii_digit() => self.handle_number(),
             c if c.is_alphabetic() || c == '_' => self.handle_identifier(),
-            _ => {
-                eprintln!(
-                    "[line {}] Error: Unexpected character: {}",
-                    self.line_num, c
-                );
-                self.error = true;
-            }
+            _ => diagnostics.push(self.error(
+                Code::UnexpectedCharacter,
+                format!("Unexpected character: {c}"),
+            )),
+        };
+    }
+
+    /// Adds a `;` after the last token, with optional semicolons, if it can
+    /// end a statement.
+    fn end_statement(&mut self) {
+        if !self.optional_semicolons || self.nesting > 0 {
+            return;
+        }
+        let Some(last) = self.tokens.last() else {
+            return;
         };
+        let ends = matches!(
+            last.token_type,
+            TokenType::IDENTIFIER
+                | TokenType::STRING
+                | TokenType::NUMBER
+                | TokenType::TRUE
+                | TokenType::FALSE
+                | TokenType::NIL
+                | TokenType::RIGHT_PAREN
+                | TokenType::RIGHT_BRACKET
+        );
+        if ends {
+            let (line_num, end) = (last.line_num, last.span.end);
+            self.tokens.push(Token {
+                token_type: TokenType::SEMICOLON,
+                lexeme: String::new(),
+                literal: None,
+                line_num,
+                span: Span { start: end, end },
+                doc: None,
+            });
+        }
     }
 
     fn add_token(&mut self, token_type: TokenType, literal: Option<Literal>) {
@@ -69,9 +173,22 @@ impl<'a> Scanner<'a> {
             lexeme: self.current.clone(),
             literal,
             line_num: self.line_num,
+            span: Span {
+                start: self.start,
+                end: self.offset,
+            },
+            doc: self.doc.take(),
         });
     }
 
+    fn error(&self, code: Code, message: impl Into<String>) -> Diagnostic {
+        let span = Span {
+            start: self.start,
+            end: self.offset,
+        };
+        Diagnostic::error(code, self.line_num, span, message)
+    }
+
     fn handle_comparison(&mut self, c: char) {
         let (single_char_token, double_char_token) = match c {
             '=' => (TokenType::EQUAL, TokenType::EQUAL_EQUAL),
@@ -81,40 +198,60 @@ impl<'a> Scanner<'a> {
             _ => unreachable!(),
         };
         if self.chars.peek() == Some(&'=') {
-            self.current.push(self.chars.next().unwrap());
+            let next = self.advance().unwrap();
+            self.current.push(next);
             self.add_token(double_char_token, None);
         } else {
             self.add_token(single_char_token, None);
         }
     }
 
+    /// A `/`, or a comment. Doc comments, which start with exactly three
+    /// slashes, are kept for the next token.
     fn handle_slash(&mut self) {
-        if self.chars.peek() == Some(&'/') {
-            self.advance_next_line();
-        } else {
+        if self.chars.peek() != Some(&'/') {
             self.add_token(TokenType::SLASH, None);
+            return;
+        }
+        let comment = self.advance_next_line();
+        if let Some(text) = comment
+            .strip_prefix("//")
+            .filter(|text| !text.starts_with('/'))
+        {
+            let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+            match &mut self.doc {
+                Some(doc) => {
+                    doc.push('\n');
+                    doc.push_str(text);
+                }
+                None => self.doc = Some(text.to_string()),
+            }
         }
     }
 
-    fn advance_next_line(&mut self) {
-        while let Some(c) = self.chars.next() {
+    /// Skips the rest of the line, giving it without the line end.
+    fn advance_next_line(&mut self) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.advance() {
             if c == '\n' {
+                self.end_statement();
                 self.line_num += 1;
                 break;
             }
+            text.push(c);
         }
+        text
     }
 
-    fn handle_string(&mut self) {
-        while let Some(c) = self.chars.next() {
+    fn handle_string(&mut self, diagnostics: &mut Diagnostics) {
+        while let Some(c) = self.advance() {
             self.current.push(c);
             if c == '"' {
                 break;
             }
         }
         if !self.current.ends_with('"') {
-            eprintln!("[line {}] Error: Unterminated string.", self.line_num);
-            self.error = true;
+            diagnostics.push(self.error(Code::UnterminatedString, "Unterminated string."));
             return;
         }
         // remove quotes
@@ -128,7 +265,7 @@ impl<'a> Scanner<'a> {
             match next_char {
                 '0'..='9' => {
                     self.current.push(next_char);
-                    self.chars.next();
+                    self.advance();
                 }
                 '.' if !has_dot
                     && self
@@ -139,7 +276,7 @@ impl<'a> Scanner<'a> {
                 {
                     self.current.push(next_char);
                     has_dot = true;
-                    self.chars.next();
+                    self.advance();
                 }
                 _ => break,
             }
@@ -152,12 +289,15 @@ impl<'a> Scanner<'a> {
         while let Some(next_char) = self.chars.peek() {
             if next_char.is_alphanumeric() || *next_char == '_' {
                 self.current.push(*next_char);
-                self.chars.next();
+                self.advance();
             } else {
                 break;
             }
         }
-        let token_type = TokenType::get_token_type(&self.current);
+        let token_type = match TokenType::get_token_type(&self.current) {
+            TokenType::PRINT if !self.print_keyword => TokenType::IDENTIFIER,
+            token_type => token_type,
+        };
         self.add_token(token_type, None)
     }
 }
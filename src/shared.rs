@@ -0,0 +1,66 @@
+//! Sharing between clones of runtime values. Single-threaded `Rc` and
+//! `RefCell` by default; with the `sync` feature, `Arc` and `Mutex`, so that
+//! an interpreter and everything it holds can be moved to another thread.
+
+/// Reference-counted pointer used for lists, maps and natives.
+#[cfg(not(feature = "sync"))]
+pub type Shared<T> = std::rc::Rc<T>;
+#[cfg(feature = "sync")]
+pub type Shared<T> = std::sync::Arc<T>;
+
+/// Interior mutability for state that clones share, such as the input.
+#[derive(Debug, Default)]
+pub struct Lock<T>(
+    #[cfg(not(feature = "sync"))] std::cell::RefCell<T>,
+    #[cfg(feature = "sync")] std::sync::Mutex<T>,
+);
+
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Self {
+        Lock(value.into())
+    }
+
+    #[cfg(not(feature = "sync"))]
+    pub fn lock(&self) -> std::cell::RefMut<'_, T> {
+        self.0.borrow_mut()
+    }
+
+    /// A panic while the lock was held cannot leave a reader or buffer
+    /// half-updated in a way later users would trip over, so poisoning is
+    /// ignored.
+    #[cfg(feature = "sync")]
+    pub fn lock(&self) -> std::sync::MutexGuard<'_, T> {
+        self.0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Bound on what the host hands over to be kept, such as writers, readers
+/// and native functions: nothing by default, `Send` with the `sync` feature.
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "sync"))]
+impl<T> MaybeSend for T {}
+#[cfg(feature = "sync")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "sync")]
+impl<T: Send> MaybeSend for T {}
+
+/// Like [`MaybeSend`], for what is shared behind [`Shared`]: `Send + Sync`
+/// with the `sync` feature.
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "sync"))]
+impl<T> MaybeSync for T {}
+#[cfg(feature = "sync")]
+pub trait MaybeSync: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: Send + Sync> MaybeSync for T {}
+
+#[cfg(feature = "sync")]
+const _: () = {
+    fn assert_send<T: Send>() {}
+    let _ = assert_send::<crate::Lox>;
+    let _ = assert_send::<crate::vm::Vm>;
+};
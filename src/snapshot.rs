@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use bytes::{Buf, BufMut};
+use thiserror::Error;
+
+use crate::grammar::Literal;
+use crate::shared::Shared;
+
+/// Files start with this magic and the format version, followed by the
+/// number of globals and each one's name and value. Bump the version
+/// whenever the layout changes.
+const MAGIC: &[u8; 4] = b"RLXS";
+const FORMAT_VERSION: u16 = 1;
+
+pub const EXTENSION: &str = "rlxs";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("not an interpreter snapshot")]
+    BadMagic,
+    #[error("snapshot format version {0} is not supported (expected {FORMAT_VERSION})")]
+    Version(u16),
+    #[error("snapshot is truncated or corrupt")]
+    Corrupt,
+}
+
+/// Encodes `globals`. Natives belong to the host, which registers them
+/// again in the session a snapshot is restored into, so they are left out,
+/// along with lists and maps that hold one.
+pub fn encode<'a>(globals: impl IntoIterator<Item = (&'a String, &'a Literal)>) -> Vec<u8> {
+    let globals: Vec<_> = globals
+        .into_iter()
+        .filter(|(_, value)| serializable(value))
+        .collect();
+    let mut buf = vec![];
+    buf.put_slice(MAGIC);
+    buf.put_u16(FORMAT_VERSION);
+    buf.put_u32(globals.len() as u32);
+    for (name, value) in globals {
+        put_string(&mut buf, name);
+        put_value(&mut buf, value);
+    }
+    buf
+}
+
+pub fn decode(mut bytes: &[u8]) -> Result<Vec<(String, Literal)>, SnapshotError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+    bytes.advance(MAGIC.len());
+    let version = read(&mut bytes, Buf::get_u16)?;
+    if version != FORMAT_VERSION {
+        return Err(SnapshotError::Version(version));
+    }
+    let mut globals = vec![];
+    for _ in 0..read(&mut bytes, Buf::get_u32)? {
+        let name = get_string(&mut bytes)?;
+        globals.push((name, get_value(&mut bytes)?));
+    }
+    if bytes.has_remaining() {
+        return Err(SnapshotError::Corrupt);
+    }
+    Ok(globals)
+}
+
+/// Whether `value` can be written with [`put_value`]: it holds no natives.
+pub fn serializable(value: &Literal) -> bool {
+    match value {
+        Literal::Native(_) => false,
+        Literal::List(items) => items.iter().all(serializable),
+        Literal::Map(entries) => entries.values().all(serializable),
+        _ => true,
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, s: &str) {
+    buf.put_u32(s.len() as u32);
+    buf.put_slice(s.as_bytes());
+}
+
+/// Appends `value`, which must be [`serializable`]. Also used by
+/// [`crate::replay`] logs.
+pub fn put_value(buf: &mut Vec<u8>, value: &Literal) {
+    match value {
+        Literal::Nil => buf.put_u8(0),
+        Literal::Boolean(b) => {
+            buf.put_u8(1);
+            buf.put_u8(*b as u8);
+        }
+        Literal::Number(n) => {
+            buf.put_u8(2);
+            buf.put_f64(*n);
+        }
+        Literal::String(s) => {
+            buf.put_u8(3);
+            put_string(buf, s);
+        }
+        Literal::List(items) => {
+            buf.put_u8(4);
+            buf.put_u32(items.len() as u32);
+            for item in items.iter() {
+                put_value(buf, item);
+            }
+        }
+        Literal::Map(entries) => {
+            buf.put_u8(5);
+            buf.put_u32(entries.len() as u32);
+            for (key, value) in entries.iter() {
+                put_string(buf, key);
+                put_value(buf, value);
+            }
+        }
+        Literal::Native(_) => unreachable!("natives are filtered out"),
+    }
+}
+
+fn get_string(bytes: &mut &[u8]) -> Result<String, SnapshotError> {
+    let len = read(bytes, Buf::get_u32)? as usize;
+    if bytes.remaining() < len {
+        return Err(SnapshotError::Corrupt);
+    }
+    let s = String::from_utf8(bytes[..len].to_vec()).map_err(|_| SnapshotError::Corrupt)?;
+    bytes.advance(len);
+    Ok(s)
+}
+
+pub fn get_value(bytes: &mut &[u8]) -> Result<Literal, SnapshotError> {
+    Ok(match read(bytes, Buf::get_u8)? {
+        0 => Literal::Nil,
+        1 => Literal::Boolean(read(bytes, Buf::get_u8)? != 0),
+        2 => Literal::Number(read(bytes, Buf::get_f64)?),
+        3 => Literal::String(get_string(bytes)?),
+        4 => {
+            let mut items = vec![];
+            for _ in 0..read(bytes, Buf::get_u32)? {
+                items.push(get_value(bytes)?);
+            }
+            Literal::List(Shared::new(items))
+        }
+        5 => {
+            let mut entries = BTreeMap::new();
+            for _ in 0..read(bytes, Buf::get_u32)? {
+                let key = get_string(bytes)?;
+                entries.insert(key, get_value(bytes)?);
+            }
+            Literal::Map(Shared::new(entries))
+        }
+        _ => return Err(SnapshotError::Corrupt),
+    })
+}
+
+fn read<'a, T>(bytes: &mut &'a [u8], get: fn(&mut &'a [u8]) -> T) -> Result<T, SnapshotError> {
+    if bytes.remaining() < std::mem::size_of::<T>() {
+        return Err(SnapshotError::Corrupt);
+    }
+    Ok(get(bytes))
+}
@@ -0,0 +1,155 @@
+//! Size and shape of a script for the `stats` command: lines by kind from
+//! the tokens, statements and declarations from the syntax tree, and block
+//! nesting from the braces.
+
+use crate::grammar::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CodeStats {
+    pub lines: usize,
+    /// Lines with a token on them.
+    pub code_lines: usize,
+    /// Lines with only a comment.
+    pub comment_lines: usize,
+    pub blank_lines: usize,
+    /// Statements at any depth.
+    pub statements: usize,
+    pub declarations: usize,
+    /// Of the declarations, those with `const`.
+    pub constants: usize,
+    pub imports: usize,
+    pub blocks: usize,
+    /// How deep blocks go inside one another; 0 without blocks.
+    pub max_depth: usize,
+    /// The first line of the longest block and how many lines it spans.
+    pub longest_block: Option<(usize, usize)>,
+}
+
+impl CodeStats {
+    pub fn measure(source: &str, tokens: &[Token], statements: &[Statement]) -> Self {
+        let mut stats = CodeStats {
+            lines: source.lines().count(),
+            ..Self::default()
+        };
+        stats.count_lines(source, tokens);
+        stats.count_blocks(tokens);
+        stats.count_statements(statements);
+        stats
+    }
+
+    fn count_lines(&mut self, source: &str, tokens: &[Token]) {
+        let line_of = |offset: usize| source[..offset].matches('\n').count();
+        let mut code = vec![false; self.lines + 1];
+        let mut comment = vec![false; self.lines + 1];
+        let mut end = 0;
+        let mut mark_comments = |gap: &str, start: usize| {
+            for (offset, _) in gap.match_indices("//") {
+                comment[line_of(start + offset)] = true;
+            }
+        };
+        // Tokens the scanner inserts, such as optional semicolons, cover no
+        // text.
+        for token in tokens
+            .iter()
+            .filter(|token| token.span.end > token.span.start)
+        {
+            mark_comments(&source[end..token.span.start], end);
+            code[line_of(token.span.start)..=line_of(token.span.end)].fill(true);
+            end = token.span.end;
+        }
+        mark_comments(&source[end..], end);
+        for line in 0..self.lines {
+            match (code[line], comment[line]) {
+                (true, _) => self.code_lines += 1,
+                (false, true) => self.comment_lines += 1,
+                (false, false) => self.blank_lines += 1,
+            }
+        }
+    }
+
+    /// Every block has braces, and every pair of braces is a block.
+    fn count_blocks(&mut self, tokens: &[Token]) {
+        let mut open = vec![];
+        for token in tokens {
+            match token.token_type {
+                TokenType::LEFT_BRACE => {
+                    open.push(token.line_num);
+                    self.blocks += 1;
+                    self.max_depth = self.max_depth.max(open.len());
+                }
+                TokenType::RIGHT_BRACE => {
+                    let Some(start) = open.pop() else { continue };
+                    let lines = token.line_num - start + 1;
+                    if self
+                        .longest_block
+                        .map_or(true, |(_, longest)| lines > longest)
+                    {
+                        self.longest_block = Some((start, lines));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn count_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statements += 1;
+            match statement {
+                Statement::Variable { constant, .. } => {
+                    self.declarations += 1;
+                    self.constants += usize::from(*constant);
+                }
+                Statement::Import { .. } => self.imports += 1,
+                Statement::Block { statements, .. } => self.count_statements(statements),
+                Statement::Try {
+                    body,
+                    catches,
+                    finally,
+                    ..
+                } => {
+                    self.count_statements(body);
+                    for catch in catches {
+                        self.count_statements(&catch.body);
+                    }
+                    if let Some(finally) = finally {
+                        self.count_statements(finally);
+                    }
+                }
+                Statement::Expression { .. }
+                | Statement::Print { .. }
+                | Statement::Defer { .. }
+                | Statement::Throw { .. } => {}
+            }
+        }
+    }
+
+    /// One line per counter, for `stats`.
+    pub fn report(&self) -> String {
+        let written = self.code_lines + self.comment_lines;
+        let ratio = if written == 0 {
+            0.0
+        } else {
+            self.comment_lines as f64 * 100.0 / written as f64
+        };
+        let longest = match self.longest_block {
+            Some((line, lines)) => format!("{lines} lines, at line {line}"),
+            None => "none".to_string(),
+        };
+        format!(
+            "lines: {}\ncode lines: {}\ncomment lines: {} ({ratio:.1}% of non-blank lines)\n\
+             blank lines: {}\nstatements: {}\ndeclarations: {} ({} constant)\nimports: {}\n\
+             blocks: {}\nmax nesting depth: {}\nlongest block: {longest}",
+            self.lines,
+            self.code_lines,
+            self.comment_lines,
+            self.blank_lines,
+            self.statements,
+            self.declarations,
+            self.constants,
+            self.imports,
+            self.blocks,
+            self.max_depth,
+        )
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::{self, Write};
+use std::time::Instant;
+
+use crate::grammar::*;
+use crate::json;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Indented lines for people to read.
+    Text,
+    /// One JSON object per event, for tools.
+    Jsonl,
+}
+
+impl Format {
+    /// Parses the value of `--trace-format=`.
+    pub fn from_flag(value: &str) -> Option<Self> {
+        match value {
+            "text" => Some(Format::Text),
+            "jsonl" => Some(Format::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+/// Something the tree-walker reports while `--trace` is on.
+pub enum Event<'a> {
+    Statement(&'a Statement),
+    /// A variable was defined or assigned `value`.
+    Assign {
+        name: &'a str,
+        value: &'a Literal,
+    },
+    EnterBlock,
+    /// Only sent when the block ran to its end; an error leaves it early.
+    ExitBlock,
+    Error(&'a str),
+}
+
+/// Writes events to stderr, so that they interleave with the script's own
+/// output on a terminal without ending up in redirected stdout. Text lines
+/// are indented by the block depth the event happened at.
+pub struct Tracer {
+    out: io::Stderr,
+    format: Format,
+    start: Instant,
+}
+
+impl Tracer {
+    pub fn new(format: Format) -> Self {
+        Tracer {
+            out: io::stderr(),
+            format,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn event(&mut self, line: usize, depth: usize, event: Event) {
+        let text = match self.format {
+            Format::Text => match text(&event) {
+                Some(text) => format!("[line {line}] {}{text}", "  ".repeat(depth)),
+                None => return,
+            },
+            Format::Jsonl => format!(
+                "{{\"time_us\":{},\"line\":{line},\"depth\":{depth},{}}}",
+                self.start.elapsed().as_micros(),
+                fields(&event)
+            ),
+        };
+        let _ = writeln!(self.out.lock(), "{text}");
+    }
+}
+
+/// Text for an event; entering and leaving a block go without saying, as
+/// the block statement and the indentation already show them.
+fn text(event: &Event) -> Option<String> {
+    Some(match event {
+        Event::Statement(statement) => describe(statement),
+        Event::Assign { name, value } => format!("{name} = {}", show(value)),
+        Event::EnterBlock | Event::ExitBlock => return None,
+        Event::Error(message) => format!("error: {message}"),
+    })
+}
+
+/// The `event` member and any others specific to the kind of event.
+fn fields(event: &Event) -> String {
+    match event {
+        Event::Statement(statement) => format!(
+            "\"event\":\"statement\",\"statement\":{}",
+            json::string(&describe(statement))
+        ),
+        Event::Assign { name, value } => format!(
+            "\"event\":\"assign\",\"name\":{},\"value\":{}",
+            json::string(name),
+            value_json(value)
+        ),
+        Event::EnterBlock => "\"event\":\"enter_block\"".to_string(),
+        Event::ExitBlock => "\"event\":\"exit_block\"".to_string(),
+        Event::Error(message) => {
+            format!("\"event\":\"error\",\"message\":{}", json::string(message))
+        }
+    }
+}
+
+/// One line summing up `statement`; blocks leave their contents to the
+/// events traced inside them.
+fn describe(statement: &Statement) -> String {
+    match statement {
+        Statement::Expression { expr, .. } => expr.to_string(),
+        Statement::Print { expr, .. } => format!("print {expr}"),
+        Statement::Variable {
+            name,
+            init: Some(init),
+            constant: true,
+            ..
+        } => format!("const {} = {init}", name.lexeme),
+        Statement::Variable {
+            name,
+            init: Some(init),
+            ..
+        } => format!("var {} = {init}", name.lexeme),
+        Statement::Variable {
+            name, init: None, ..
+        } => format!("var {}", name.lexeme),
+        Statement::Block { statements, .. } => format!("block ({} statements)", statements.len()),
+        Statement::Import { path, name, .. } => format!("import {path:?} as {name}"),
+        Statement::Defer { expr, .. } => format!("defer {expr}"),
+        Statement::Throw { value, .. } => format!("throw {value}"),
+        Statement::Try { .. } => "try".to_string(),
+    }
+}
+
+/// Values as `print` shows them, except that strings are quoted to stand out.
+fn show(value: &Literal) -> String {
+    match value {
+        Literal::String(s) => format!("{s:?}"),
+        Literal::Number(n) => format_number(*n, false),
+        value => value.to_string(),
+    }
+}
+
+fn value_json(value: &Literal) -> String {
+    match value {
+        Literal::Boolean(b) => b.to_string(),
+        Literal::String(s) => json::string(s),
+        Literal::Number(n) if n.is_finite() => n.to_string(),
+        // JSON has no NaN or infinities.
+        Literal::Number(n) => json::string(&n.to_string()),
+        Literal::Nil => "null".to_string(),
+        Literal::List(items) => {
+            let items: Vec<_> = items.iter().map(value_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        Literal::Map(entries) => {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|(key, value)| format!("{}:{}", json::string(key), value_json(value)))
+                .collect();
+            format!("{{{}}}", entries.join(","))
+        }
+        Literal::Native(_) => json::string(&value.to_string()),
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::grammar::*;
+
+/// Runtime support emitted at the top of every generated program. It mirrors
+/// the tree-walking interpreter: same value semantics, messages and exit codes.
+/// Of the standard natives it only has `clock`, `sleep`, `input`, `type`,
+/// `print` and `truthy`; programs using others fail with an undefined
+/// variable.
+const RUNTIME: &str = r#"#![allow(dead_code, unused_mut, unused_variables, unused_parens, clippy::all)]
+
+use std::collections::HashMap;
+use std::fmt;
+use std::process::exit;
+
+#[derive(Clone, PartialEq)]
+enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    List(Vec<Value>),
+    Native(&'static str),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Native(_) => "function",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{}", number(*n)),
+            Value::Str(s) => write!(f, "{s}"),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match item {
+                        Value::Str(s) => write!(f, "{s:?}")?,
+                        item => write!(f, "{item}")?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Native(_) => write!(f, "<native fn>"),
+        }
+    }
+}
+
+/// As the interpreter's `format_number`.
+fn number(n: f64) -> String {
+    let text = if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 || (1e-3..1e7).contains(&n.abs()) {
+        let text = n.to_string();
+        if text.contains('.') { text } else { format!("{text}.0") }
+    } else {
+        let text = format!("{n:e}");
+        let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+        if mantissa.contains('.') {
+            format!("{mantissa}E{exponent}")
+        } else {
+            format!("{mantissa}.0E{exponent}")
+        }
+    };
+    match text.strip_suffix(".0") {
+        Some(short) if !PLAIN => short.to_string(),
+        _ => text,
+    }
+}
+
+struct Error {
+    message: String,
+    line: usize,
+}
+
+type R<T> = Result<T, Error>;
+
+fn fail<T>(line: usize, message: &str) -> R<T> {
+    Err(Error { message: message.to_string(), line })
+}
+
+#[derive(Default)]
+struct Globals(HashMap<&'static str, Value>);
+
+impl Globals {
+    fn define(&mut self, name: &'static str, value: Value) {
+        self.0.insert(name, value);
+    }
+
+    fn get(&self, name: &str, line: usize) -> R<Value> {
+        match self.0.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => fail(line, &format!("Undefined variable '{name}'.")),
+        }
+    }
+
+    fn set(&mut self, name: &'static str, value: Value, line: usize) -> R<Value> {
+        match self.0.get_mut(name) {
+            Some(slot) => {
+                *slot = value.clone();
+                Ok(value)
+            }
+            None if IMPLICIT_GLOBALS => {
+                self.0.insert(name, value.clone());
+                Ok(value)
+            }
+            None => fail(line, &format!("Undefined variable '{name}'.")),
+        }
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Nil => false,
+        Value::Bool(b) => *b,
+        _ if LOX_TRUTHINESS => true,
+        Value::Number(n) => *n != 0.0,
+        Value::Str(s) => !s.is_empty(),
+        Value::List(items) => !items.is_empty(),
+        Value::Native(_) => true,
+    }
+}
+
+fn not(value: Value) -> Value {
+    Value::Bool(!truthy(&value))
+}
+
+fn negate(value: Value, line: usize) -> R<Value> {
+    match value {
+        Value::Number(n) => Ok(Value::Number(-n)),
+        _ => fail(line, "Operand must be a number."),
+    }
+}
+
+fn numbers(l: Value, r: Value, line: usize) -> R<(f64, f64)> {
+    match (l, r) {
+        (Value::Number(l), Value::Number(r)) => Ok((l, r)),
+        _ => fail(line, "Operands must be numbers."),
+    }
+}
+
+fn add(l: Value, r: Value, line: usize) -> R<Value> {
+    match (l, r) {
+        (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+        (Value::Str(l), Value::Str(r)) => Ok(Value::Str(l + &r)),
+        _ => fail(line, "Operands must be two numbers or two strings."),
+    }
+}
+
+fn sub(l: Value, r: Value, line: usize) -> R<Value> {
+    numbers(l, r, line).map(|(l, r)| Value::Number(l - r))
+}
+
+fn mul(l: Value, r: Value, line: usize) -> R<Value> {
+    numbers(l, r, line).map(|(l, r)| Value::Number(l * r))
+}
+
+fn div(l: Value, r: Value, line: usize) -> R<Value> {
+    match numbers(l, r, line)? {
+        (_, r) if r == 0.0 && CHECKED_DIVISION => fail(line, "Division by zero."),
+        (l, r) => Ok(Value::Number(l / r)),
+    }
+}
+
+fn lt(l: Value, r: Value, line: usize) -> R<Value> {
+    numbers(l, r, line).map(|(l, r)| Value::Bool(l < r))
+}
+
+fn le(l: Value, r: Value, line: usize) -> R<Value> {
+    numbers(l, r, line).map(|(l, r)| Value::Bool(l <= r))
+}
+
+fn gt(l: Value, r: Value, line: usize) -> R<Value> {
+    numbers(l, r, line).map(|(l, r)| Value::Bool(l > r))
+}
+
+fn ge(l: Value, r: Value, line: usize) -> R<Value> {
+    numbers(l, r, line).map(|(l, r)| Value::Bool(l >= r))
+}
+
+fn index(object: Value, index: Value, line: usize) -> R<Value> {
+    let Value::List(items) = object else {
+        return fail(line, "Only lists can be indexed.");
+    };
+    let Value::Number(n) = index else {
+        return fail(line, "List index must be a number.");
+    };
+    if n.fract() != 0.0 || n < 0.0 || n >= items.len() as f64 {
+        return fail(line, "List index out of range.");
+    }
+    Ok(items[n as usize].clone())
+}
+
+fn call(callee: Value, args: Vec<Value>, line: usize) -> R<Value> {
+    let Value::Native(name) = callee else {
+        return fail(line, "Can only call functions and classes.");
+    };
+    let arity = match name {
+        "clock" => 0,
+        "sleep" => 1,
+        "input" => 0,
+        "type" => 1,
+        "truthy" => 1,
+        // Variadic.
+        "print" => args.len(),
+        _ => unreachable!(),
+    };
+    if args.len() != arity {
+        return fail(line, &format!("Expected {arity} arguments but got {}.", args.len()));
+    }
+    match name {
+        "clock" => {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+            Ok(Value::Number(now.map_or(0.0, |now| now.as_secs_f64())))
+        }
+        "sleep" => match &args[0] {
+            Value::Number(ms) if *ms >= 0.0 && ms.is_finite() => {
+                std::thread::sleep(std::time::Duration::from_secs_f64(ms / 1000.0));
+                Ok(Value::Nil)
+            }
+            Value::Number(_) => fail(line, "sleep() needs a non-negative number of milliseconds."),
+            other => fail(line, &format!("Expected number but got {}.", other.type_name())),
+        },
+        "input" => {
+            let mut text = String::new();
+            match std::io::stdin().read_line(&mut text) {
+                Ok(0) => Ok(Value::Nil),
+                Ok(_) => {
+                    let len = text.trim_end_matches(['\n', '\r']).len();
+                    text.truncate(len);
+                    Ok(Value::Str(text))
+                }
+                Err(err) => fail(line, &format!("Failed to read input: {err}.")),
+            }
+        }
+        "type" => Ok(Value::Str(match &args[0] {
+            Value::Bool(_) => "bool".to_string(),
+            other => other.type_name().to_string(),
+        })),
+        "truthy" => Ok(Value::Bool(truthy(&args[0]))),
+        "print" => {
+            let values: Vec<String> = args.iter().map(|value| value.to_string()).collect();
+            println!("{}", values.join(" "));
+            Ok(Value::Nil)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn main() {
+    let mut globals = Globals::default();
+    let args = std::env::args().skip(1).map(Value::Str).collect();
+    globals.define("ARGS", Value::List(args));
+    globals.define("clock", Value::Native("clock"));
+    globals.define("sleep", Value::Native("sleep"));
+    globals.define("input", Value::Native("input"));
+    globals.define("type", Value::Native("type"));
+    globals.define("print", Value::Native("print"));
+    globals.define("truthy", Value::Native("truthy"));
+    if let Err(err) = run(&mut globals) {
+        eprintln!("{}\n[line {}]", err.message, err.line);
+        exit(70);
+    }
+}
+"#;
+
+/// Lowers a parsed program to a standalone Rust source file. Top-level
+/// variables become entries in a runtime map so undefined-variable errors
+/// still surface at runtime; block-scoped variables become Rust locals.
+pub struct Transpiler {
+    out: String,
+    indent: usize,
+    scopes: Vec<HashMap<String, String>>,
+    locals: usize,
+    line: usize,
+    output: OutputFormat,
+    compat: Compat,
+    checked_division: bool,
+    implicit_globals: bool,
+}
+
+impl Transpiler {
+    pub fn new(output: OutputFormat) -> Self {
+        Transpiler {
+            output,
+            compat: Compat::default(),
+            checked_division: false,
+            implicit_globals: false,
+            out: String::new(),
+            indent: 1,
+            scopes: vec![],
+            locals: 0,
+            line: 1,
+        }
+    }
+
+    /// Fixes the truthiness rule of the program, which cannot change once
+    /// compiled.
+    pub fn with_compat(mut self, compat: Compat) -> Self {
+        self.compat = compat;
+        self
+    }
+
+    pub fn with_checked_division(mut self, checked: bool) -> Self {
+        self.checked_division = checked;
+        self
+    }
+
+    pub fn with_implicit_globals(mut self, implicit: bool) -> Self {
+        self.implicit_globals = implicit;
+        self
+    }
+
+    pub fn transpile(mut self, source_name: &str, statements: &[Statement]) -> String {
+        let mut program = format!("// Generated from {source_name}. Build with `rustc -O`.\n");
+        program.push_str(RUNTIME);
+        // Whether whole numbers print with `.0`, fixed by `--format=` at compile time.
+        let plain = self.output == OutputFormat::Plain;
+        program.push_str(&format!("\nconst PLAIN: bool = {plain};\n"));
+        // Whether only nil and false are false, fixed by `--compat=`.
+        let lox = self.compat == Compat::Lox;
+        program.push_str(&format!("const LOX_TRUTHINESS: bool = {lox};\n"));
+        let checked = self.checked_division;
+        program.push_str(&format!("const CHECKED_DIVISION: bool = {checked};\n"));
+        let implicit = self.implicit_globals;
+        program.push_str(&format!("const IMPLICIT_GLOBALS: bool = {implicit};\n"));
+        for statement in statements {
+            self.statement(statement);
+        }
+        program.push_str("\nfn run(g: &mut Globals) -> R<()> {\n");
+        program.push_str(&self.out);
+        program.push_str("    Ok(())\n}\n");
+        program
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Print { expr, .. } => {
+                let expr = self.expression(expr);
+                self.line(&format!("println!(\"{{}}\", {expr});"));
+            }
+            Statement::Expression { expr, .. } => {
+                let expr = self.expression(expr);
+                self.line(&format!("let _ = {expr};"));
+            }
+            Statement::Variable {
+                name,
+                init,
+                constant,
+                ..
+            } => {
+                self.line = name.line_num;
+                let value = match init {
+                    Some(expr) => self.expression(expr),
+                    None => "Value::Nil".to_string(),
+                };
+                match self.scopes.last_mut() {
+                    Some(scope) => {
+                        let ident = format!("v{}_{}", self.locals, mangle(&name.lexeme));
+                        self.locals += 1;
+                        scope.insert(name.lexeme.clone(), ident.clone());
+                        let binding = if *constant { "let" } else { "let mut" };
+                        self.line(&format!("{binding} {ident} = {value};"));
+                    }
+                    None => {
+                        self.line(&format!("g.define({:?}, {value});", name.lexeme));
+                    }
+                }
+            }
+            Statement::Block { statements, .. } => {
+                self.line("{");
+                self.indent += 1;
+                self.scopes.push(HashMap::new());
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.scopes.pop();
+                self.indent -= 1;
+                self.line("}");
+            }
+            Statement::Throw { keyword, value } => {
+                self.line = keyword.line_num;
+                let value = self.expression(value);
+                let line = keyword.line_num;
+                self.line(&format!(
+                    "fail::<()>({line}, &format!(\"Uncaught exception: {{}}\", {value}))?;"
+                ));
+            }
+            Statement::Try { line, .. } => {
+                self.line = *line;
+                self.line(&format!(
+                    "fail::<()>({line}, \"try is not supported in compiled programs.\")?;"
+                ));
+            }
+            Statement::Defer { keyword, .. } => {
+                let line = keyword.line_num;
+                self.line = line;
+                self.line(&format!(
+                    "fail::<()>({line}, \"defer is not supported in compiled programs.\")?;"
+                ));
+            }
+            Statement::Import { line, .. } => {
+                self.line = *line;
+                self.line(&format!(
+                    "fail::<()>({line}, \"import is not supported in compiled programs.\")?;"
+                ));
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) -> String {
+        match expr {
+            Expression::Literal(literal) => match literal {
+                Literal::Nil => "Value::Nil".to_string(),
+                Literal::Boolean(b) => format!("Value::Bool({b})"),
+                Literal::Number(n) => format!("Value::Number({n:?})"),
+                Literal::String(s) => format!("Value::Str(String::from({s:?}))"),
+                Literal::List(_) | Literal::Map(_) | Literal::Native(_) => {
+                    unreachable!("only scalars have literal syntax")
+                }
+            },
+            Expression::Group(expr) => self.expression(expr),
+            Expression::Unary { op, expr } => {
+                let expr = self.expression(expr);
+                self.line = op.line_num;
+                match op.token_type {
+                    TokenType::BANG => format!("not({expr})"),
+                    TokenType::MINUS => format!("negate({expr}, {})?", self.line),
+                    _ => unreachable!(),
+                }
+            }
+            Expression::Binary { op, left, right } => {
+                let left = self.expression(left);
+                let right = self.expression(right);
+                self.line = op.line_num;
+                let function = match op.token_type {
+                    TokenType::PLUS => "add",
+                    TokenType::MINUS => "sub",
+                    TokenType::STAR => "mul",
+                    TokenType::SLASH => "div",
+                    TokenType::LESS => "lt",
+                    TokenType::LESS_EQUAL => "le",
+                    TokenType::GREATER => "gt",
+                    TokenType::GREATER_EQUAL => "ge",
+                    TokenType::EQUAL_EQUAL => return format!("Value::Bool({left} == {right})"),
+                    TokenType::BANG_EQUAL => return format!("Value::Bool({left} != {right})"),
+                    _ => unreachable!(),
+                };
+                format!("{function}({left}, {right}, {})?", self.line)
+            }
+            Expression::Variable(name) => {
+                self.line = name.line_num;
+                match self.resolve(&name.lexeme) {
+                    Some(ident) => format!("{ident}.clone()"),
+                    None => format!("g.get({:?}, {})?", name.lexeme, self.line),
+                }
+            }
+            Expression::Assign { name, right } => {
+                let value = self.expression(right);
+                self.line = name.line_num;
+                match self.resolve(&name.lexeme) {
+                    Some(ident) => format!("{{ let v = {value}; {ident} = v.clone(); v }}"),
+                    None => format!("g.set({:?}, {value}, {})?", name.lexeme, self.line),
+                }
+            }
+            Expression::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.expression(object);
+                let index = self.expression(index);
+                self.line = bracket.line_num;
+                format!("index({object}, {index}, {})?", self.line)
+            }
+            Expression::Call {
+                callee,
+                paren,
+                args,
+            } => {
+                let callee = self.expression(callee);
+                let args: Vec<_> = args.iter().map(|arg| self.expression(arg)).collect();
+                self.line = paren.line_num;
+                format!("call({callee}, vec![{}], {})?", args.join(", "), self.line)
+            }
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Option<&String> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn line(&mut self, code: &str) {
+        writeln!(self.out, "{}{code}", "    ".repeat(self.indent)).unwrap();
+    }
+}
+
+/// Lox identifiers may contain any alphabetic character; keep ASCII ones
+/// readable and hex-encode the rest so the result is a valid Rust identifier.
+fn mangle(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c.to_string()
+            } else {
+                format!("u{:x}", c as u32)
+            }
+        })
+        .collect()
+}
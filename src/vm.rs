@@ -0,0 +1,624 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+
+use crate::chunk::{Chunk, Op};
+use crate::grammar::{Compat, Literal, NativeResult, OutputFormat, Span};
+use crate::input::Input;
+use crate::interpreter::{self, Deadline, Limits, RuntimeError, RuntimeErrorKind};
+use crate::interrupt;
+use crate::metrics::{self, Memory, Metrics};
+use crate::natives::{self, Context, Request, Sandbox};
+use crate::output::{Flush, Output};
+use crate::replay::Log;
+use crate::shared::{MaybeSend, MaybeSync};
+
+#[cfg(feature = "jit")]
+mod jit;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// Stack machine executing chunks produced by the [`Compiler`](crate::compiler::Compiler).
+///
+/// Globals live in slots that are never removed once defined, so each global
+/// instruction remembers the slot it last resolved to (an inline cache) and
+/// only hashes the name on its first execution. The caches outlive a run, so
+/// running a chunk again, as `bench` and hosts do, hits them.
+pub struct Vm {
+    stack: Vec<Literal>,
+    globals: Vec<Literal>,
+    global_slots: HashMap<String, usize>,
+    /// The name of each slot, which cached slots are checked against, since
+    /// the caches are by position and may have been filled by another chunk.
+    names: Vec<String>,
+    /// The slots of globals declared with `const`.
+    constants: HashSet<usize>,
+    /// Globals defined by the host, which [`Self::reset`] goes back to.
+    host: Vec<(String, Literal)>,
+    caches: Vec<Option<usize>>,
+    pub global_cache: CacheStats,
+    pub instructions: u64,
+    allocations: u64,
+    memory: Memory,
+    elapsed: Duration,
+    deadline: Option<Deadline>,
+    output: OutputFormat,
+    /// Whether dividing by zero is an error.
+    checked_division: bool,
+    /// Whether assigning to an undefined global defines it.
+    implicit_globals: bool,
+    /// Shared with the standard natives.
+    context: Context,
+    #[cfg(feature = "jit")]
+    jit: jit::Tier,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let mut vm = Vm {
+            stack: vec![],
+            globals: vec![],
+            global_slots: HashMap::new(),
+            names: vec![],
+            constants: HashSet::new(),
+            host: vec![],
+            caches: vec![],
+            global_cache: CacheStats::default(),
+            instructions: 0,
+            allocations: 0,
+            memory: Memory::default(),
+            elapsed: Duration::ZERO,
+            deadline: None,
+            output: OutputFormat::default(),
+            checked_division: false,
+            implicit_globals: false,
+            context: Context::new(Input::stdin(), Output::stdout()),
+            #[cfg(feature = "jit")]
+            jit: jit::Tier::default(),
+        };
+        for (name, value) in natives::standard(&vm.context.clone()) {
+            vm.define_global(name, value);
+        }
+        vm
+    }
+
+    /// A VM whose `print` writes to `writer` instead of stdout.
+    pub fn with_output(writer: impl Write + MaybeSend + 'static, flush: Flush) -> Self {
+        let vm = Self::new();
+        *vm.context.output.lock() = Output::new(writer, flush);
+        vm
+    }
+
+    /// Reads input from `reader` instead of stdin.
+    pub fn with_input(self, reader: impl BufRead + MaybeSend + 'static) -> Self {
+        self.context.input.replace(reader);
+        self
+    }
+
+    /// Leaves out the standard natives `sandbox` does not allow.
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        let denied: Vec<_> = natives::denied(sandbox).collect();
+        self.host
+            .retain(|(name, _)| !denied.contains(&name.as_str()));
+        self.reset();
+        self
+    }
+
+    /// Like [`Interpreter::with_log`](crate::interpreter::Interpreter::with_log).
+    pub fn with_log(mut self, log: &Log) -> Self {
+        for (name, value) in &mut self.host {
+            if let Literal::Native(native) = value {
+                if natives::nondeterministic().any(|recorded| recorded == name) {
+                    *value = log.wrap(native.clone());
+                }
+            }
+        }
+        self.reset();
+        self
+    }
+
+    /// The VM's input, for natives to read from.
+    pub fn input(&self) -> Input {
+        self.context.input.clone()
+    }
+
+    pub fn run(&mut self, chunk: &Chunk) -> Result<(), RuntimeError> {
+        let start = Instant::now();
+        let result = self.execute(chunk);
+        self.elapsed += start.elapsed();
+        let line = chunk.lines.last().copied().unwrap_or(0);
+        let flushed = self
+            .context
+            .output
+            .lock()
+            .flush()
+            .map_err(|err| interpreter::output_error(line, err));
+        result.and(flushed)
+    }
+
+    fn execute(&mut self, chunk: &Chunk) -> Result<(), RuntimeError> {
+        self.stack.clear();
+        if self.caches.len() < chunk.code.len() {
+            self.caches.resize(chunk.code.len(), None);
+        }
+        #[cfg(feature = "jit")]
+        if let Some(code) = self.jit.code(chunk) {
+            return jit::run(self, &code);
+        }
+        let mut ip = 0;
+        loop {
+            let op = chunk.code[ip];
+            let line = chunk.lines[ip];
+            let span = chunk.spans[ip];
+            let at = ip;
+            ip += 1;
+            self.tick(line, span)?;
+            match op {
+                Op::Constant(index) => self.push(chunk.constants[index as usize].clone()),
+                Op::Nil => self.push(Literal::Nil),
+                Op::True => self.push(Literal::Boolean(true)),
+                Op::False => self.push(Literal::Boolean(false)),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::GetLocal(slot) => self.push(self.stack[slot as usize].clone()),
+                Op::SetLocal(slot) => self.stack[slot as usize] = self.peek().clone(),
+                Op::GetGlobal(index) => {
+                    self.get_global(at, global_name(chunk, index), line, span)?
+                }
+                Op::DefineGlobal(index) => {
+                    self.define_global_at(at, global_name(chunk, index), false)
+                }
+                Op::DefineConstant(index) => {
+                    self.define_global_at(at, global_name(chunk, index), true)
+                }
+                Op::SetGlobal(index) => {
+                    self.set_global_at(at, global_name(chunk, index), line, span)?
+                }
+                Op::Equal => self.equal(false),
+                Op::NotEqual => self.equal(true),
+                Op::Greater => self.compare(line, span, |l, r| l > r)?,
+                Op::GreaterEqual => self.compare(line, span, |l, r| l >= r)?,
+                Op::Less => self.compare(line, span, |l, r| l < r)?,
+                Op::LessEqual => self.compare(line, span, |l, r| l <= r)?,
+                Op::Add => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.add(left, right, line, span)?;
+                }
+                Op::AddLocal(slot) => {
+                    let right = self.stack[slot as usize].clone();
+                    let left = self.pop();
+                    self.add(left, right, line, span)?;
+                }
+                Op::AddConstant(index) => {
+                    let right = chunk.constants[index as usize].clone();
+                    let left = self.pop();
+                    self.add(left, right, line, span)?;
+                }
+                Op::Subtract => self.arithmetic(line, span, |l, r| l - r)?,
+                Op::Multiply => self.arithmetic(line, span, |l, r| l * r)?,
+                Op::Divide => self.divide(line, span)?,
+                Op::Not => self.not(),
+                Op::Negate => self.negate(line, span)?,
+                Op::Index => self.index(line, span)?,
+                Op::Call(argc) => self.call(argc as usize, line, span)?,
+                Op::Print => self.print(line)?,
+                Op::Return => return Ok(()),
+            }
+        }
+    }
+
+    /// Calls the value below `argc` arguments on the stack, answering the
+    /// request the callee made of the VM, if any.
+    fn call(&mut self, argc: usize, line: usize, span: Span) -> Result<(), RuntimeError> {
+        let args = self.stack.split_off(self.stack.len() - argc);
+        let callee = self.pop();
+        let value = interpreter::call(&callee, &args)
+            .map_err(|(kind, message)| RuntimeError::at(line, span, kind, message))?;
+        if metrics::allocates(&value) {
+            self.allocations += 1;
+        }
+        let value = match self.context.take_request() {
+            Some(request) => self.answer(request, line, span)?,
+            None => value,
+        };
+        // Interrupts are noticed on the next instruction.
+        if let Some(deadline) = &self.deadline {
+            deadline.check(line, span)?;
+        }
+        self.push(value);
+        Ok(())
+    }
+
+    /// Counts an instruction about to run, and stops the run if it is past
+    /// its deadline or interrupted.
+    fn tick(&mut self, line: usize, span: Span) -> Result<(), RuntimeError> {
+        self.instructions += 1;
+        if let Some(deadline) = &self.deadline {
+            // Reading the clock on every instruction would dominate.
+            if self.instructions % 1024 == 0 {
+                deadline.check(line, span)?;
+            }
+        }
+        if interrupt::pending() {
+            interrupt::take();
+            return Err(RuntimeError::at(
+                line,
+                span,
+                RuntimeErrorKind::Interrupted,
+                "Interrupted.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Resolves the global `name` for the instruction at `at`, consulting
+    /// and filling that instruction's cache.
+    fn global_slot(&mut self, at: usize, name: &str) -> Option<usize> {
+        if let Some(slot) = self.caches[at] {
+            if self.names.get(slot).is_some_and(|cached| cached == name) {
+                self.global_cache.hits += 1;
+                return Some(slot);
+            }
+        }
+        self.global_cache.misses += 1;
+        let slot = *self.global_slots.get(name)?;
+        self.caches[at] = Some(slot);
+        Some(slot)
+    }
+
+    fn get_global(
+        &mut self,
+        at: usize,
+        name: &str,
+        line: usize,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        match self.global_slot(at, name) {
+            Some(slot) => self.push(self.globals[slot].clone()),
+            None => return Err(undefined_variable(line, span, name)),
+        }
+        Ok(())
+    }
+
+    /// Defines the global `name` with the value on top of the stack.
+    fn define_global_at(&mut self, at: usize, name: &str, constant: bool) {
+        let value = self.pop();
+        self.memory.add(&value);
+        let slot = match self.global_slot(at, name) {
+            Some(slot) => {
+                self.memory.remove(&self.globals[slot]);
+                self.globals[slot] = value;
+                slot
+            }
+            None => {
+                let slot = self.add_global(name, value);
+                self.caches[at] = Some(slot);
+                slot
+            }
+        };
+        if constant {
+            self.constants.insert(slot);
+        } else {
+            self.constants.remove(&slot);
+        }
+    }
+
+    /// Assigns the value on top of the stack, leaving it there, to the
+    /// global `name`.
+    fn set_global_at(
+        &mut self,
+        at: usize,
+        name: &str,
+        line: usize,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        match self.global_slot(at, name) {
+            Some(slot) if self.constants.contains(&slot) => {
+                return Err(interpreter::assigned_constant(line, span, name));
+            }
+            Some(slot) => {
+                let value = self.peek().clone();
+                self.memory.remove(&self.globals[slot]);
+                self.memory.add(&value);
+                self.globals[slot] = value;
+            }
+            None if self.implicit_globals => {
+                let value = self.peek().clone();
+                self.memory.add(&value);
+                let slot = self.add_global(name, value);
+                self.caches[at] = Some(slot);
+            }
+            None => return Err(undefined_variable(line, span, name)),
+        }
+        Ok(())
+    }
+
+    /// Applies `limits`; the timeout counts from now. Blocks do not nest at
+    /// runtime here, so there is no depth to limit.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.deadline = limits.timeout.map(Deadline::start);
+        self.context
+            .set_deadline(self.deadline.map(|deadline| deadline.at()));
+    }
+
+    /// Makes `random` and `randomInt` return the same numbers on every run.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.context.seed(seed);
+    }
+
+    pub fn set_output_format(&mut self, output: OutputFormat) {
+        self.output = output;
+        self.context.set_output_format(output);
+    }
+
+    /// Like [`Interpreter::set_checked_division`](crate::interpreter::Interpreter::set_checked_division).
+    pub fn set_checked_division(&mut self, checked: bool) {
+        self.checked_division = checked;
+    }
+
+    /// Like [`Interpreter::set_implicit_globals`](crate::interpreter::Interpreter::set_implicit_globals).
+    pub fn set_implicit_globals(&mut self, implicit: bool) {
+        self.implicit_globals = implicit;
+    }
+
+    /// Like [`Interpreter::set_compat`](crate::interpreter::Interpreter::set_compat).
+    pub fn set_compat(&mut self, compat: Compat) {
+        self.context.set_compat(compat);
+    }
+
+    /// Like [`Interpreter::register_native`](crate::interpreter::Interpreter::register_native).
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Literal]) -> NativeResult + MaybeSync + 'static,
+    ) {
+        self.define_global(name, Literal::native(name, arity, function));
+    }
+
+    /// Defines a global before any chunk runs, such as `ARGS`. It survives
+    /// [`Self::reset`].
+    pub fn define_global(&mut self, name: &str, value: Literal) {
+        self.host.push((name.to_string(), value.clone()));
+        self.set_global(name, value);
+    }
+
+    /// Like [`Interpreter::reset`](crate::interpreter::Interpreter::reset).
+    pub fn reset(&mut self) {
+        self.globals.clear();
+        self.global_slots.clear();
+        self.names.clear();
+        self.caches.clear();
+        self.constants.clear();
+        self.memory.clear();
+        for (name, value) in std::mem::take(&mut self.host) {
+            self.define_global(&name, value);
+        }
+    }
+
+    /// How many chunks the `jit` tier has compiled, which it does once they
+    /// have run a few times.
+    #[cfg(feature = "jit")]
+    pub fn compiled_chunks(&self) -> usize {
+        self.jit.len()
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            steps: self.instructions,
+            allocations: self.allocations,
+            peak_memory: self.memory.peak,
+            elapsed: self.elapsed,
+        }
+    }
+
+    /// Like [`Interpreter`](crate::interpreter::Interpreter)'s, but locals
+    /// live in stack slots without their names, so `eval` and `locals` are
+    /// errors and `defined` only sees globals.
+    fn answer(
+        &mut self,
+        request: Request,
+        line: usize,
+        span: Span,
+    ) -> Result<Literal, RuntimeError> {
+        let unsupported = |name| {
+            Err(RuntimeError::at(
+                line,
+                span,
+                RuntimeErrorKind::Native,
+                format!("{name}() is only supported by the tree-walking interpreter."),
+            ))
+        };
+        match request {
+            Request::Exit(status) => Err(interpreter::exited(line, span, status)),
+            Request::Panic(message) => Err(interpreter::panicked(line, span, &message)),
+            Request::Eval(_) => unsupported("eval"),
+            Request::Locals => unsupported("locals"),
+            Request::Globals => Ok(interpreter::map(
+                self.global_slots
+                    .iter()
+                    .map(|(name, &slot)| (name, &self.globals[slot])),
+            )),
+            Request::Defined(name) => Ok(Literal::Boolean(self.global_slots.contains_key(&name))),
+        }
+    }
+
+    fn set_global(&mut self, name: &str, value: Literal) {
+        match self.global_slots.get(name) {
+            Some(&slot) => {
+                self.constants.remove(&slot);
+                self.globals[slot] = value;
+            }
+            None => {
+                self.add_global(name, value);
+            }
+        }
+    }
+
+    /// Puts the new global `name` in the next slot, and returns the slot.
+    fn add_global(&mut self, name: &str, value: Literal) -> usize {
+        let slot = self.globals.len();
+        self.global_slots.insert(name.to_string(), slot);
+        self.names.push(name.to_string());
+        self.globals.push(value);
+        slot
+    }
+
+    fn equal(&mut self, negate: bool) {
+        let right = self.pop();
+        let left = self.pop();
+        self.push(Literal::Boolean((left == right) != negate));
+    }
+
+    fn divide(&mut self, line: usize, span: Span) -> Result<(), RuntimeError> {
+        let (l, r) = self.numbers(line, span)?;
+        if r == 0.0 && self.checked_division {
+            return Err(interpreter::division_by_zero(line, span));
+        }
+        self.push(Literal::Number(l / r));
+        Ok(())
+    }
+
+    fn not(&mut self) {
+        let value = self.pop();
+        let truthy = self.context.truthy(&value);
+        self.push(Literal::Boolean(!truthy));
+    }
+
+    fn negate(&mut self, line: usize, span: Span) -> Result<(), RuntimeError> {
+        match self.pop() {
+            Literal::Number(n) => self.push(Literal::Number(-n)),
+            _ => return Err(type_error(line, span, "Operand must be a number.")),
+        }
+        Ok(())
+    }
+
+    fn index(&mut self, line: usize, span: Span) -> Result<(), RuntimeError> {
+        let index = self.pop();
+        let object = self.pop();
+        let value = interpreter::index(&object, &index)
+            .map_err(|(kind, message)| RuntimeError::at(line, span, kind, message))?;
+        self.push(value);
+        Ok(())
+    }
+
+    fn print(&mut self, line: usize) -> Result<(), RuntimeError> {
+        let value = self.pop();
+        let text = self.output.show(&value);
+        self.context
+            .output
+            .lock()
+            .print(&text)
+            .map_err(|err| interpreter::output_error(line, err))
+    }
+
+    fn push(&mut self, value: Literal) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Literal {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn peek(&self) -> &Literal {
+        self.stack.last().expect("stack underflow")
+    }
+
+    fn add(
+        &mut self,
+        left: Literal,
+        right: Literal,
+        line: usize,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        match (left, right) {
+            (Literal::Number(l), Literal::Number(r)) => self.push(Literal::Number(l + r)),
+            (Literal::String(l), Literal::String(r)) => {
+                self.allocations += 1;
+                self.push(Literal::String(format!("{}{}", l, r)))
+            }
+            _ => {
+                return Err(type_error(
+                    line,
+                    span,
+                    "Operands must be two numbers or two strings.",
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn numbers(&mut self, line: usize, span: Span) -> Result<(f64, f64), RuntimeError> {
+        let right = self.pop();
+        let left = self.pop();
+        match (left, right) {
+            (Literal::Number(l), Literal::Number(r)) => Ok((l, r)),
+            _ => Err(type_error(line, span, "Operands must be numbers.")),
+        }
+    }
+
+    fn arithmetic(
+        &mut self,
+        line: usize,
+        span: Span,
+        f: fn(f64, f64) -> f64,
+    ) -> Result<(), RuntimeError> {
+        let (l, r) = self.numbers(line, span)?;
+        self.push(Literal::Number(f(l, r)));
+        Ok(())
+    }
+
+    fn compare(
+        &mut self,
+        line: usize,
+        span: Span,
+        f: fn(f64, f64) -> bool,
+    ) -> Result<(), RuntimeError> {
+        let (l, r) = self.numbers(line, span)?;
+        self.push(Literal::Boolean(f(l, r)));
+        Ok(())
+    }
+}
+
+fn global_name(chunk: &Chunk, index: u16) -> &str {
+    match &chunk.constants[index as usize] {
+        Literal::String(name) => name,
+        _ => unreachable!("global names are string constants"),
+    }
+}
+
+fn type_error(line: usize, span: Span, message: &str) -> RuntimeError {
+    RuntimeError::at(line, span, RuntimeErrorKind::Type, message)
+}
+
+fn undefined_variable(line: usize, span: Span, name: &str) -> RuntimeError {
+    RuntimeError::at(
+        line,
+        span,
+        RuntimeErrorKind::UndefinedVariable,
+        format!("Undefined variable '{}'.", name),
+    )
+}
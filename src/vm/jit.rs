@@ -0,0 +1,281 @@
+//! The second tier behind the `jit` feature. The VM counts the runs of each
+//! chunk, and once one has run [`HOT`] times, as in `bench` or a host that
+//! reruns a compiled program, compiles it to threaded code: a handler per
+//! instruction with its operand decoded, constants copied out and global
+//! names read once, so later runs skip decoding and dispatch. The handlers
+//! are the VM's own operations, calls and the requests natives make
+//! included, so both tiers behave the same.
+//!
+//! Despite the name, no machine code is generated: compiled code is a table
+//! of function pointers, one per instruction, which the tier walks in order.
+//! Generating machine code would need a code generator such as Cranelift.
+
+use std::collections::HashMap;
+
+use super::{global_name, Vm};
+use crate::chunk::{Chunk, Op};
+use crate::grammar::{Literal, Span};
+use crate::interpreter::RuntimeError;
+use crate::shared::Shared;
+
+/// Runs on the VM after which a chunk is compiled.
+pub const HOT: u32 = 3;
+
+type Handler = fn(&mut Vm, &Instruction) -> Result<(), RuntimeError>;
+
+pub(super) struct Instruction {
+    handler: Handler,
+    operand: Operand,
+    /// Where the instruction is in the chunk, for the global caches.
+    at: usize,
+    line: usize,
+    span: Span,
+}
+
+enum Operand {
+    None,
+    Slot(usize),
+    Value(Literal),
+    Global(String),
+    Args(usize),
+}
+
+impl Instruction {
+    fn slot(&self) -> usize {
+        match self.operand {
+            Operand::Slot(slot) => slot,
+            _ => unreachable!("only local instructions take slots"),
+        }
+    }
+
+    fn value(&self) -> &Literal {
+        match &self.operand {
+            Operand::Value(value) => value,
+            _ => unreachable!("only constant instructions take values"),
+        }
+    }
+
+    fn args(&self) -> usize {
+        match self.operand {
+            Operand::Args(argc) => argc,
+            _ => unreachable!("only calls take argument counts"),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match &self.operand {
+            Operand::Global(name) => name,
+            _ => unreachable!("only global instructions take names"),
+        }
+    }
+}
+
+/// Run counts and compiled code, by [`Chunk::fingerprint`].
+#[derive(Default)]
+pub(super) struct Tier {
+    runs: HashMap<u64, u32>,
+    /// `None` for hot chunks that cannot be compiled, having no `Return`.
+    compiled: HashMap<u64, Option<Shared<[Instruction]>>>,
+}
+
+impl Tier {
+    /// Counts a run of `chunk`, and gives its code once it is hot.
+    pub(super) fn code(&mut self, chunk: &Chunk) -> Option<Shared<[Instruction]>> {
+        let key = chunk.fingerprint();
+        if let Some(code) = self.compiled.get(&key) {
+            return code.clone();
+        }
+        let runs = self.runs.entry(key).or_insert(0);
+        *runs += 1;
+        if *runs <= HOT {
+            return None;
+        }
+        self.runs.remove(&key);
+        let code = compile(chunk).map(Shared::from);
+        self.compiled.insert(key, code.clone());
+        code
+    }
+
+    /// How many chunks have been compiled.
+    pub(super) fn len(&self) -> usize {
+        self.compiled.values().filter(|code| code.is_some()).count()
+    }
+}
+
+pub(super) fn run(vm: &mut Vm, code: &[Instruction]) -> Result<(), RuntimeError> {
+    for instruction in code {
+        vm.tick(instruction.line, instruction.span)?;
+        (instruction.handler)(vm, instruction)?;
+    }
+    Ok(())
+}
+
+/// The instructions up to the first `Return`, or `None` if there is none.
+fn compile(chunk: &Chunk) -> Option<Vec<Instruction>> {
+    let mut code = vec![];
+    for (at, &op) in chunk.code.iter().enumerate() {
+        let constant = |index: u16| Operand::Value(chunk.constants[index as usize].clone());
+        let global = |index: u16| Operand::Global(global_name(chunk, index).to_string());
+        let (handler, operand): (Handler, Operand) = match op {
+            Op::Constant(index) => (
+                |vm, i| {
+                    vm.push(i.value().clone());
+                    Ok(())
+                },
+                constant(index),
+            ),
+            Op::Nil => (
+                |vm, _| {
+                    vm.push(Literal::Nil);
+                    Ok(())
+                },
+                Operand::None,
+            ),
+            Op::True => (
+                |vm, _| {
+                    vm.push(Literal::Boolean(true));
+                    Ok(())
+                },
+                Operand::None,
+            ),
+            Op::False => (
+                |vm, _| {
+                    vm.push(Literal::Boolean(false));
+                    Ok(())
+                },
+                Operand::None,
+            ),
+            Op::Pop => (
+                |vm, _| {
+                    vm.pop();
+                    Ok(())
+                },
+                Operand::None,
+            ),
+            Op::GetLocal(slot) => (
+                |vm, i| {
+                    vm.push(vm.stack[i.slot()].clone());
+                    Ok(())
+                },
+                Operand::Slot(slot as usize),
+            ),
+            Op::SetLocal(slot) => (
+                |vm, i| {
+                    vm.stack[i.slot()] = vm.peek().clone();
+                    Ok(())
+                },
+                Operand::Slot(slot as usize),
+            ),
+            Op::GetGlobal(index) => (
+                |vm, i| vm.get_global(i.at, i.name(), i.line, i.span),
+                global(index),
+            ),
+            Op::DefineGlobal(index) => (
+                |vm, i| {
+                    vm.define_global_at(i.at, i.name(), false);
+                    Ok(())
+                },
+                global(index),
+            ),
+            Op::DefineConstant(index) => (
+                |vm, i| {
+                    vm.define_global_at(i.at, i.name(), true);
+                    Ok(())
+                },
+                global(index),
+            ),
+            Op::SetGlobal(index) => (
+                |vm, i| vm.set_global_at(i.at, i.name(), i.line, i.span),
+                global(index),
+            ),
+            Op::Equal => (
+                |vm, _| {
+                    vm.equal(false);
+                    Ok(())
+                },
+                Operand::None,
+            ),
+            Op::NotEqual => (
+                |vm, _| {
+                    vm.equal(true);
+                    Ok(())
+                },
+                Operand::None,
+            ),
+            Op::Greater => (
+                |vm, i| vm.compare(i.line, i.span, |l, r| l > r),
+                Operand::None,
+            ),
+            Op::GreaterEqual => (
+                |vm, i| vm.compare(i.line, i.span, |l, r| l >= r),
+                Operand::None,
+            ),
+            Op::Less => (
+                |vm, i| vm.compare(i.line, i.span, |l, r| l < r),
+                Operand::None,
+            ),
+            Op::LessEqual => (
+                |vm, i| vm.compare(i.line, i.span, |l, r| l <= r),
+                Operand::None,
+            ),
+            Op::Add => (
+                |vm, i| {
+                    let right = vm.pop();
+                    let left = vm.pop();
+                    vm.add(left, right, i.line, i.span)
+                },
+                Operand::None,
+            ),
+            Op::AddLocal(slot) => (
+                |vm, i| {
+                    let right = vm.stack[i.slot()].clone();
+                    let left = vm.pop();
+                    vm.add(left, right, i.line, i.span)
+                },
+                Operand::Slot(slot as usize),
+            ),
+            Op::AddConstant(index) => (
+                |vm, i| {
+                    let left = vm.pop();
+                    vm.add(left, i.value().clone(), i.line, i.span)
+                },
+                constant(index),
+            ),
+            Op::Subtract => (
+                |vm, i| vm.arithmetic(i.line, i.span, |l, r| l - r),
+                Operand::None,
+            ),
+            Op::Multiply => (
+                |vm, i| vm.arithmetic(i.line, i.span, |l, r| l * r),
+                Operand::None,
+            ),
+            Op::Divide => (|vm, i| vm.divide(i.line, i.span), Operand::None),
+            Op::Not => (
+                |vm, _| {
+                    vm.not();
+                    Ok(())
+                },
+                Operand::None,
+            ),
+            Op::Negate => (|vm, i| vm.negate(i.line, i.span), Operand::None),
+            Op::Index => (|vm, i| vm.index(i.line, i.span), Operand::None),
+            Op::Print => (|vm, i| vm.print(i.line), Operand::None),
+            Op::Call(argc) => (
+                |vm, i| vm.call(i.args(), i.line, i.span),
+                Operand::Args(argc as usize),
+            ),
+            Op::Return => (|_, _| Ok(()), Operand::None),
+        };
+        code.push(Instruction {
+            handler,
+            operand,
+            at,
+            line: chunk.lines[at],
+            span: chunk.spans[at],
+        });
+        if op == Op::Return {
+            return Some(code);
+        }
+    }
+    None
+}
@@ -0,0 +1,167 @@
+use std::process::Command;
+
+use rustyinterpreter::artifact::{self, ArtifactError};
+use rustyinterpreter::chunk::{Chunk, Op};
+use rustyinterpreter::compiler::Compiler;
+use rustyinterpreter::diagnostics::{Diagnostics, Format};
+use rustyinterpreter::grammar::{Literal, Span};
+use rustyinterpreter::optimizer;
+use rustyinterpreter::parser::Parser;
+use rustyinterpreter::scanner::Scanner;
+
+fn compile(source: &str) -> Chunk {
+    let tokens = Scanner::new(source).scan_tokens(&mut Diagnostics::new(Format::Plain));
+    let statements = Parser::new(&tokens).parse().unwrap();
+    Compiler::new().compile(&statements).unwrap()
+}
+
+/// A chunk of `ops`, all on line 1, with `constants`.
+fn chunk(ops: &[Op], constants: Vec<Literal>) -> Chunk {
+    let mut chunk = Chunk::default();
+    for value in constants {
+        chunk.add_constant(value);
+    }
+    for &op in ops {
+        chunk.write(op, 1, Span { start: 0, end: 0 });
+    }
+    chunk
+}
+
+fn decode(chunk: &Chunk) -> Result<Chunk, ArtifactError> {
+    artifact::decode(&artifact::encode(chunk, 0, artifact::Options::default()))
+        .map(|artifact| artifact.chunk)
+}
+
+const PROGRAM: &str = "var a = 1; const b = \"x\"; { var c = a + 2; c = c * 3; print c + c; } \
+                       print b + \"y\"; print !(a == 1) != (a >= 1); a = -a; print len(b) / 0;";
+
+#[test]
+fn compiled_programs_round_trip() {
+    for optimized in [false, true] {
+        let mut chunk = compile(PROGRAM);
+        if optimized {
+            optimizer::optimize(&mut chunk);
+        }
+        let options = artifact::Options {
+            optimized,
+            ..artifact::Options::default()
+        };
+        let bytes = artifact::encode(&chunk, artifact::hash(PROGRAM), options);
+        let decoded = artifact::decode(&bytes).unwrap();
+        assert_eq!(decoded.source_hash, artifact::hash(PROGRAM));
+        assert_eq!(decoded.options, options);
+        assert_eq!(decoded.chunk.code, chunk.code);
+        assert_eq!(decoded.chunk.constants, chunk.constants);
+        assert_eq!(decoded.chunk.lines, chunk.lines);
+        assert_eq!(decoded.chunk.spans, chunk.spans);
+    }
+}
+
+#[test]
+fn scanner_options_round_trip() {
+    let chunk = compile("print 1;");
+    for flags in 0..8 {
+        let options = artifact::Options {
+            optimized: flags & 1 != 0,
+            optional_semicolons: flags & 2 != 0,
+            print_keyword: flags & 4 != 0,
+        };
+        let decoded = artifact::decode(&artifact::encode(&chunk, 0, options)).unwrap();
+        assert_eq!(decoded.options, options);
+    }
+}
+
+#[test]
+fn rejects_other_files_and_versions() {
+    assert!(matches!(
+        artifact::decode(b"#!/usr/bin/env lox"),
+        Err(ArtifactError::BadMagic)
+    ));
+    let mut bytes = artifact::encode(&compile("print 1;"), 0, artifact::Options::default());
+    bytes[5] = bytes[5].wrapping_add(1);
+    assert!(matches!(
+        artifact::decode(&bytes),
+        Err(ArtifactError::Version(_))
+    ));
+}
+
+#[test]
+fn rejects_truncated_files() {
+    let bytes = artifact::encode(&compile(PROGRAM), 0, artifact::Options::default());
+    for len in 4..bytes.len() {
+        assert!(artifact::decode(&bytes[..len]).is_err(), "{len} bytes");
+    }
+}
+
+#[test]
+fn rejects_missing_constants_and_locals() {
+    let corrupt = [
+        chunk(
+            &[Op::Constant(1), Op::Print, Op::Return],
+            vec![Literal::Nil],
+        ),
+        chunk(&[Op::Nil, Op::GetLocal(1), Op::Return], vec![]),
+        chunk(&[Op::Nil, Op::SetLocal(1), Op::Return], vec![]),
+        chunk(&[Op::Nil, Op::AddLocal(1), Op::Return], vec![]),
+        chunk(&[Op::GetLocal(0), Op::Return], vec![]),
+    ];
+    for chunk in corrupt {
+        assert!(
+            matches!(decode(&chunk), Err(ArtifactError::Corrupt)),
+            "{:?}",
+            chunk.code
+        );
+    }
+}
+
+#[test]
+fn rejects_code_that_underflows_the_stack() {
+    let corrupt = [
+        chunk(&[Op::Pop, Op::Return], vec![]),
+        chunk(&[Op::Print, Op::Return], vec![]),
+        chunk(&[Op::Nil, Op::Add, Op::Return], vec![]),
+        chunk(&[Op::Nil, Op::Call(1), Op::Return], vec![]),
+        chunk(&[Op::DefineGlobal(0), Op::Return], vec!["a".into()]),
+    ];
+    for chunk in corrupt {
+        assert!(
+            matches!(decode(&chunk), Err(ArtifactError::Corrupt)),
+            "{:?}",
+            chunk.code
+        );
+    }
+    let call = chunk(
+        &[Op::Nil, Op::Nil, Op::Call(1), Op::Print, Op::Return],
+        vec![],
+    );
+    assert!(decode(&call).is_ok());
+}
+
+#[test]
+fn rejects_globals_not_named_by_strings() {
+    let chunk = chunk(
+        &[Op::GetGlobal(0), Op::Print, Op::Return],
+        vec![Literal::Number(1.0)],
+    );
+    assert!(matches!(decode(&chunk), Err(ArtifactError::Corrupt)));
+}
+
+#[test]
+fn cache_is_not_reused_under_other_scanner_options() {
+    let dir = std::env::temp_dir().join(format!("rustyinterpreter-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.lox"), "print 1\nprint 2\n").unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+            .args(args)
+            .current_dir(&dir)
+            .env("XDG_CONFIG_HOME", dir.join("no-user-config"))
+            .output()
+            .unwrap()
+    };
+    let output = run(&["run", "--cache", "--optional-semicolons", "main.lox"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n2\n");
+    assert!(dir.join("main.rlxc").exists());
+    assert_eq!(run(&["run", "--cache", "main.lox"]).status.code(), Some(65));
+    let _ = std::fs::remove_dir_all(dir);
+}
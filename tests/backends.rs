@@ -0,0 +1,108 @@
+use rustyinterpreter::chunk::Chunk;
+use rustyinterpreter::compiler::Compiler;
+use rustyinterpreter::diagnostics::{Diagnostics, Format};
+use rustyinterpreter::output::{Capture, Flush};
+use rustyinterpreter::parser::Parser;
+use rustyinterpreter::scanner::Scanner;
+use rustyinterpreter::vm::Vm;
+use rustyinterpreter::Lox;
+
+/// What `source` prints on the tree-walking interpreter, followed by the
+/// message of the error it stops with.
+fn interpret(source: &str) -> String {
+    let capture = Capture::new();
+    let mut lox = Lox::with_output(capture.clone(), Flush::End);
+    let error = lox.run(source).err();
+    let mut output = capture.contents();
+    if let Some(errors) = error {
+        output.push_str(&format!("error: {}", errors[0].message));
+    }
+    output
+}
+
+fn compile(source: &str) -> Chunk {
+    let tokens = Scanner::new(source).scan_tokens(&mut Diagnostics::new(Format::Plain));
+    let statements = Parser::new(&tokens).parse().unwrap();
+    Compiler::new().compile(&statements).unwrap()
+}
+
+/// The same, on the bytecode VM.
+fn run_vm(source: &str) -> String {
+    let chunk = compile(source);
+    let capture = Capture::new();
+    let mut vm = Vm::with_output(capture.clone(), Flush::End);
+    let error = vm.run(&chunk).err();
+    let mut output = capture.contents();
+    if let Some(err) = error {
+        output.push_str(&format!("error: {}", err.message));
+    }
+    output
+}
+
+fn assert_same(source: &str) {
+    let expected = interpret(source);
+    assert!(!expected.is_empty(), "{source} prints nothing");
+    assert_eq!(run_vm(source), expected, "{source}");
+}
+
+#[test]
+fn arithmetic_and_comparison() {
+    assert_same("print 1 + 2 * 3 - 4 / 8; print -(3 - 5); print 7 / 0; print -7 / 0;");
+    assert_same("print 1 < 2; print 2 <= 2; print 3 > 4; print 3 >= 4;");
+    assert_same("print 1 == 1; print 1 != 1; print nil == false; print \"a\" == \"a\";");
+    assert_same("print !true; print !nil; print !0; print !!\"\";");
+    assert_same("print 0.1 + 0.2; print 12345678 * 10; print 1 / 3; print 0.0001;");
+}
+
+#[test]
+fn strings() {
+    assert_same("print \"con\" + \"cat\"; print \"\" + \"\"; print \"a\" == \"b\";");
+    assert_same("print len(\"hello\"); print str(4) + \"2\";");
+}
+
+#[test]
+fn variables_and_scopes() {
+    assert_same(
+        "var a = 1; var b; print b; b = a = a + 1; print a; print b; \
+         { var a = 10; { var c = a + b; print c; a = c; } print a; } print a;",
+    );
+    assert_same("const limit = 3; { var x = limit; x = x + x; print x; } print limit;");
+}
+
+#[test]
+fn runtime_errors() {
+    assert_same("print 1; print -\"a\";");
+    assert_same("print \"a\" + 1;");
+    assert_same("print 1 < \"b\";");
+    assert_same("print 1; print missing;");
+    assert_same("missing = 1;");
+    assert_same("print len(1, 2);");
+}
+
+#[test]
+fn global_caches_hit_when_a_chunk_runs_again() {
+    let chunk = compile("var a = 1; var b = a + 1; print a + b;");
+    let capture = Capture::new();
+    let mut vm = Vm::with_output(capture.clone(), Flush::End);
+    vm.run(&chunk).unwrap();
+    assert_eq!(vm.global_cache.hits, 0);
+    let misses = vm.global_cache.misses;
+    vm.run(&chunk).unwrap();
+    assert_eq!(vm.global_cache.hits, misses);
+    assert_eq!(vm.global_cache.misses, misses);
+    assert_eq!(capture.contents(), "3\n3\n");
+}
+
+#[test]
+fn global_caches_are_checked_against_the_name() {
+    let capture = Capture::new();
+    let mut vm = Vm::with_output(capture.clone(), Flush::End);
+    vm.run(&compile("var a = \"a\"; var b = \"b\"; print a;"))
+        .unwrap();
+    // The same positions, naming the globals the other way round.
+    vm.run(&compile("var b = \"B\"; var a = \"A\"; print b;"))
+        .unwrap();
+    vm.reset();
+    vm.run(&compile("var b = 2; var a = 1; print a;")).unwrap();
+    assert_eq!(capture.contents(), "a\nB\n1\n");
+}
@@ -0,0 +1,119 @@
+//! `lox.toml` and `rustyinterpreter.toml`, read by the command line from
+//! the working directory.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const BIN: &str = env!("CARGO_BIN_EXE_codecrafters-interpreter");
+
+/// A fresh directory holding `files`, named after the test.
+fn project(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rustyinterpreter-{test}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for (name, contents) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+/// Runs the interpreter in `dir` with `args`, away from the user's own
+/// settings.
+fn run(dir: &PathBuf, args: &[&str]) -> Output {
+    Command::new(BIN)
+        .args(args)
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir.join("no-user-config"))
+        .env_remove("LOX_PATH")
+        .env_remove("RUSTYINT_BACKEND")
+        .env_remove("RUSTYINT_MAX_DEPTH")
+        .env_remove("RUSTYINT_TIMEOUT_MS")
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+const MAIN: &str = "import \"util\"; print util.half; print 3;";
+const UTIL: &str = "var half = 0.5;";
+
+#[test]
+fn manifest_names_the_entry_and_search_path() {
+    let dir = project(
+        "manifest",
+        &[
+            (
+                "lox.toml",
+                "# The project.\n\
+                 [project]\n\
+                 name = \"demo\" # not used by the interpreter\n\
+                 version = \"0.1.0\"\n\
+                 entry = \"main.lox\"\n\
+                 lox_path = [\"lib\", \"vendor#1\"]\n",
+            ),
+            ("main.lox", MAIN),
+            ("lib/util.lox", UTIL),
+        ],
+    );
+    let output = run(&dir, &["run"]);
+    assert_eq!(stderr(&output), "");
+    assert_eq!(stdout(&output), "0.5\n3\n");
+    assert_eq!(output.status.code(), Some(0));
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn settings_apply_and_flags_win() {
+    let dir = project(
+        "settings",
+        &[
+            ("rustyinterpreter.toml", "format = \"plain\"\nseed = 5\n"),
+            ("lox.toml", "[project]\nlox_path = [\"lib\"]\n"),
+            ("main.lox", MAIN),
+            ("lib/util.lox", UTIL),
+        ],
+    );
+    assert_eq!(stdout(&run(&dir, &["run", "main.lox"])), "0.5\n3.0\n");
+    assert_eq!(
+        stdout(&run(&dir, &["run", "--format=lox", "main.lox"])),
+        "0.5\n3\n"
+    );
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn errors_name_the_file_and_line() {
+    let cases = [
+        ("seed = -1", "lox.toml:1: `seed` must be >= 0"),
+        ("\n\nseed = \"a\"", "lox.toml:3: `seed` cannot be a string"),
+        ("colour = true", "lox.toml:1: unknown setting `colour`"),
+        (
+            "[project]\nlox_path = [[\"lib\"]]",
+            "lox.toml:2: invalid value",
+        ),
+        (
+            "[project]\nlox_path = [1]",
+            "lox.toml:2: `project.lox_path` must list strings",
+        ),
+        ("format = \"plain", "lox.toml:1:"),
+    ];
+    for (n, (manifest, message)) in cases.into_iter().enumerate() {
+        let dir = project(
+            &format!("errors-{n}"),
+            &[("lox.toml", manifest), ("main.lox", "print 1;")],
+        );
+        let output = run(&dir, &["run", "main.lox"]);
+        assert_eq!(output.status.code(), Some(78), "{manifest}");
+        assert!(stderr(&output).starts_with(message), "{}", stderr(&output));
+        assert_eq!(stdout(&output), "");
+        let _ = fs::remove_dir_all(dir);
+    }
+}
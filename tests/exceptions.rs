@@ -0,0 +1,111 @@
+use rustyinterpreter::output::{Capture, Flush};
+use rustyinterpreter::Lox;
+
+/// What `source` prints, and the message of the error it stops with.
+fn run(source: &str) -> (String, Option<String>) {
+    let capture = Capture::new();
+    let mut lox = Lox::with_output(capture.clone(), Flush::End);
+    let error = lox
+        .run(source)
+        .err()
+        .map(|errors| errors[0].message.clone());
+    (capture.contents(), error)
+}
+
+#[test]
+fn deferred_run_last_first_before_catch_and_finally() {
+    let (output, error) = run("{
+        defer print(\"outer defer\");
+        try {
+            defer print(\"defer 1\");
+            defer print(\"defer 2\");
+            print \"body\";
+            throw error(\"Config\", \"bad\");
+            print \"unreachable\";
+        } catch (e: Config) {
+            print \"caught \" + e.message;
+        } catch (e) {
+            print \"other\";
+        } finally {
+            print \"finally\";
+        }
+        print \"after\";
+    }");
+    assert_eq!(error, None);
+    assert_eq!(
+        output,
+        "body\ndefer 2\ndefer 1\ncaught bad\nfinally\nafter\nouter defer\n"
+    );
+}
+
+#[test]
+fn finally_runs_before_an_outer_catch() {
+    let (output, error) = run("
+        try {
+            try {
+                throw \"inner\";
+            } catch (e: Config) {
+                print \"wrong type\";
+            } finally {
+                print \"inner finally\";
+            }
+        } catch (e) {
+            print \"outer caught \" + e;
+        } finally {
+            print \"outer finally\";
+        }
+    ");
+    assert_eq!(error, None);
+    assert_eq!(output, "inner finally\nouter caught inner\nouter finally\n");
+}
+
+#[test]
+fn cleanup_runs_when_nothing_catches() {
+    let (output, error) = run("{
+        defer print(\"deferred\");
+        try {
+            throw \"oops\";
+        } finally {
+            print \"finally\";
+        }
+        print \"unreachable\";
+    }");
+    assert_eq!(output, "finally\ndeferred\n");
+    assert_eq!(error.as_deref(), Some("Uncaught exception: oops"));
+}
+
+#[test]
+fn finally_runs_without_an_error() {
+    let (output, error) = run("try { print 1; } catch (e) { print 2; } finally { print 3; }");
+    assert_eq!(error, None);
+    assert_eq!(output, "1\n3\n");
+}
+
+#[test]
+fn deferred_see_their_block_after_a_nested_error() {
+    let (output, error) = run("var x = \"outer\";
+        {
+            var x = \"block\";
+            defer print(x);
+            { var x = \"inner\"; nope; }
+        }");
+    assert_eq!(output, "block\n");
+    assert_eq!(error.as_deref(), Some("Undefined variable 'nope'."));
+}
+
+#[test]
+fn try_bodies_clean_up_nested_scopes_before_catch() {
+    let (output, error) = run("{
+        var x = \"block\";
+        try {
+            defer print(x);
+            { var x = \"inner\"; throw x; }
+        } catch (e) {
+            print e + \" caught in \" + x;
+        } finally {
+            print x;
+        }
+    }");
+    assert_eq!(error, None);
+    assert_eq!(output, "block\ninner caught in block\nblock\n");
+}
@@ -0,0 +1,90 @@
+use std::iter;
+
+use rustyinterpreter::artifact;
+use rustyinterpreter::grammar::Literal;
+use rustyinterpreter::Lox;
+
+/// `native(s)`, which must return a string.
+#[cfg(feature = "digest")]
+fn digest(native: &str, s: &str) -> String {
+    let value = Lox::new()
+        .evaluate(&format!("{native}(s)"), [("s", s)])
+        .unwrap();
+    String::try_from(value).unwrap()
+}
+
+#[cfg(feature = "digest")]
+const VECTORS: &[(&str, &str, &str)] = &[
+    (
+        "",
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        "d41d8cd98f00b204e9800998ecf8427e",
+    ),
+    (
+        "abc",
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        "900150983cd24fb0d6963f7d28e17f72",
+    ),
+    (
+        "The quick brown fox jumps over the lazy dog",
+        "d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592",
+        "9e107d9d372bb6826bd81d3542a419d6",
+    ),
+    // 56 bytes, so the length spills into a second block.
+    (
+        "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+        "8215ef0796a20bcaaae116d3876c664a",
+    ),
+    (
+        "héllo",
+        "3c48591d8d098a4538f5e013dfcf406e948eac4d3277b10bf614e295d6068179",
+        "be50e8478cf24ff3595bc7307fb91b50",
+    ),
+];
+
+#[cfg(feature = "digest")]
+#[test]
+fn sha256_matches_known_vectors() {
+    for (input, sha256, _) in VECTORS {
+        assert_eq!(digest("sha256", input), *sha256, "{input:?}");
+    }
+    assert_eq!(
+        digest("sha256", &"a".repeat(1000)),
+        "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+    );
+}
+
+#[cfg(feature = "digest")]
+#[test]
+fn md5_matches_known_vectors() {
+    for (input, _, md5) in VECTORS {
+        assert_eq!(digest("md5", input), *md5, "{input:?}");
+    }
+    assert_eq!(
+        digest("md5", &"a".repeat(1000)),
+        "cabe45dcc9ae5b66ba86600cca6b8ba8"
+    );
+}
+
+#[test]
+fn source_hash_is_fnv_1a() {
+    assert_eq!(artifact::hash(""), 0xcbf29ce484222325);
+    assert_eq!(artifact::hash("a"), 0xaf63dc4c8601ec8c);
+    assert_eq!(artifact::hash("foobar"), 0x85944171f73967e8);
+}
+
+#[test]
+fn hash_is_stable_and_tells_types_apart() {
+    let mut lox = Lox::new();
+    let mut hash = |source: &str| match lox.evaluate(source, iter::empty::<(&str, f64)>()).unwrap()
+    {
+        Literal::Number(n) => n,
+        value => panic!("hash gave {value:?}"),
+    };
+    assert_eq!(hash("hash(\"abc\")"), hash("hash(\"ab\" + \"c\")"));
+    assert_ne!(hash("hash(1)"), hash("hash(\"1\")"));
+    assert_ne!(hash("hash(nil)"), hash("hash(false)"));
+    let n = hash("hash(\"abc\")");
+    assert!(n >= 0.0 && n < 2f64.powi(53) && n.fract() == 0.0);
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "jit")]
+
+use rustyinterpreter::chunk::Chunk;
+use rustyinterpreter::compiler::Compiler;
+use rustyinterpreter::diagnostics::{Diagnostics, Format};
+use rustyinterpreter::output::{Capture, Flush};
+use rustyinterpreter::parser::Parser;
+use rustyinterpreter::scanner::Scanner;
+use rustyinterpreter::vm::Vm;
+
+fn compile(source: &str) -> Chunk {
+    let tokens = Scanner::new(source).scan_tokens(&mut Diagnostics::new(Format::Plain));
+    let statements = Parser::new(&tokens).parse().unwrap();
+    Compiler::new().compile(&statements).unwrap()
+}
+
+/// Runs `source` `runs` times on one VM, resetting it in between, and gives
+/// what each run printed or the error it stopped with.
+fn run(source: &str, runs: usize) -> (Vec<String>, Vm) {
+    let chunk = compile(source);
+    let capture = Capture::new();
+    let mut vm = Vm::with_output(capture.clone(), Flush::End);
+    let mut outputs = vec![];
+    let mut printed = 0;
+    for _ in 0..runs {
+        vm.reset();
+        let result = vm.run(&chunk);
+        let contents = capture.contents();
+        let mut output = contents[printed..].to_string();
+        printed = contents.len();
+        if let Err(err) = result {
+            output.push_str(&format!("error: {}", err.message));
+        }
+        outputs.push(output);
+    }
+    (outputs, vm)
+}
+
+#[test]
+fn hot_chunks_are_compiled_and_print_the_same() {
+    let source = "var a = 1; const b = \"x\"; { var c = a + 2; c = c * 3; print c; } \
+                  print b + \"y\"; print !(a == 1) == (a >= 1); a = -a; print a / 0;";
+    let (outputs, vm) = run(source, 6);
+    assert_eq!(vm.compiled_chunks(), 1);
+    assert!(outputs.iter().all(|output| *output == outputs[0]));
+    assert_eq!(outputs[0], "9\nxy\nfalse\n-Infinity\n");
+}
+
+#[test]
+fn compiled_chunks_stop_with_the_same_errors() {
+    let (outputs, vm) = run("var a = 1; print a; print -\"a\";", 6);
+    assert_eq!(vm.compiled_chunks(), 1);
+    assert!(outputs.iter().all(|output| *output == outputs[0]));
+    assert!(outputs[0].starts_with("1\nerror: "), "{}", outputs[0]);
+}
+
+#[test]
+fn compiled_chunks_call_natives() {
+    let (outputs, vm) = run("print len(\"abc\"); print str(1) + \"2\";", 6);
+    assert_eq!(vm.compiled_chunks(), 1);
+    assert!(outputs.iter().all(|output| output == "3\n12\n"));
+}
+
+#[test]
+fn compiled_chunks_answer_requests_from_natives() {
+    let (outputs, vm) = run("print 1; panic(\"stop\"); print 2;", 6);
+    assert_eq!(vm.compiled_chunks(), 1);
+    assert!(outputs
+        .iter()
+        .all(|output| output == "1\nerror: Panic: stop"));
+}
+
+#[test]
+fn chunks_are_told_apart_by_their_contents() {
+    let capture = Capture::new();
+    let mut vm = Vm::with_output(capture.clone(), Flush::End);
+    let first = compile("print 1;");
+    for _ in 0..6 {
+        vm.run(&first).unwrap();
+    }
+    assert_eq!(vm.compiled_chunks(), 1);
+    // The same shape, likely at the same address, with another constant.
+    drop(first);
+    let second = compile("print 2;");
+    vm.run(&second).unwrap();
+    assert_eq!(vm.compiled_chunks(), 1);
+    assert!(capture.contents().ends_with("1\n2\n"));
+}
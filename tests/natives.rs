@@ -0,0 +1,30 @@
+use std::iter;
+
+use rustyinterpreter::grammar::Literal;
+use rustyinterpreter::Lox;
+
+fn evaluate(source: &str) -> Literal {
+    Lox::new()
+        .evaluate(source, iter::empty::<(&str, f64)>())
+        .unwrap()
+}
+
+#[test]
+fn type_names_match_error_messages() {
+    let cases = [
+        ("true", "boolean"),
+        ("1", "number"),
+        ("\"a\"", "string"),
+        ("nil", "nil"),
+        ("split(\"a b\", \" \")", "list"),
+        ("globals()", "map"),
+        ("len", "function"),
+    ];
+    for (value, name) in cases {
+        assert_eq!(
+            evaluate(&format!("type({value})")),
+            Literal::String(name.to_string()),
+            "{value}"
+        );
+    }
+}
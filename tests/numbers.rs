@@ -0,0 +1,48 @@
+use rustyinterpreter::diagnostics::{Diagnostics, Format};
+use rustyinterpreter::output::{Capture, Flush};
+use rustyinterpreter::scanner::Scanner;
+use rustyinterpreter::Lox;
+
+const SOURCE: &str = "12345678 0.0001 3 0.5";
+
+#[test]
+fn tokens_keep_the_literal_format() {
+    let tokens = Scanner::new(SOURCE).scan_tokens(&mut Diagnostics::new(Format::Plain));
+    let lines: Vec<String> = tokens.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        lines,
+        [
+            "NUMBER 12345678 12345678.0",
+            "NUMBER 0.0001 0.0001",
+            "NUMBER 3 3.0",
+            "NUMBER 0.5 0.5",
+            "EOF  null",
+        ]
+    );
+}
+
+#[test]
+fn print_writes_numbers_as_jlox_does() {
+    let capture = Capture::new();
+    let mut lox = Lox::with_output(capture.clone(), Flush::End);
+    let prints: String = SOURCE
+        .split(' ')
+        .map(|number| format!("print {number}; print str({number});"))
+        .collect();
+    lox.run(&prints).unwrap();
+    assert_eq!(
+        capture.contents(),
+        "1.2345678E7\n1.2345678E7\n1.0E-4\n1.0E-4\n3\n3\n0.5\n0.5\n"
+    );
+}
+
+#[test]
+fn error_messages_write_numbers_as_print_does() {
+    let mut lox = Lox::with_output(Capture::new(), Flush::End);
+    let message = |lox: &mut Lox, source: &str| lox.run(source).unwrap_err()[0].message.clone();
+    assert_eq!(message(&mut lox, "panic(3);"), "Panic: 3");
+    assert_eq!(
+        message(&mut lox, "throw 100000000 * 10000000000000;"),
+        "Uncaught exception: 1.0E21"
+    );
+}
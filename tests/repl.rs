@@ -0,0 +1,64 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const BIN: &str = env!("CARGO_BIN_EXE_codecrafters-interpreter");
+
+/// What the REPL started with `args` prints for `input`, prompts included.
+fn session(args: &[&str], input: &str) -> String {
+    let mut child = Command::new(BIN)
+        .arg("repl")
+        .args(args)
+        .env(
+            "XDG_CONFIG_HOME",
+            std::env::temp_dir().join("no-user-config"),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn scratch(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "rustyinterpreter-{name}-{}.lox",
+        std::process::id()
+    ))
+}
+
+#[test]
+fn saved_expressions_run_without_the_print_keyword() {
+    let path = scratch("repl-save");
+    session(
+        &["--print=function"],
+        &format!("1 + 2\n:save {}\n", path.display()),
+    );
+    let script = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(script, "print(1 + 2);\n");
+    let output = Command::new(BIN)
+        .args(["run", "--print=function"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn commands_scan_with_the_session_options() {
+    let input = ":tokens print\n:ast print(1)\n:type print\n";
+    assert_eq!(
+        session(&["--print=function"], input),
+        "> IDENTIFIER print null\nEOF  null\n> (call (var print) 1.0)\n> function\n> \n"
+    );
+    assert!(session(&[], input).starts_with("> PRINT print null\n"));
+}